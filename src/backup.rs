@@ -0,0 +1,68 @@
+use std::{fs, io, path::{Path, PathBuf}};
+
+use crate::paths;
+
+// Copies of a file as it was before a save, set by the `backup` setting: "off", "tilde" for `file~` beside it, or
+// "directory" for timestamped copies kept in the state directory, the newest KEEP of each file
+const KEEP: usize = 10;
+
+pub fn is_known(setting: &str) -> bool {
+    matches!(setting, "off" | "tilde" | "directory")
+}
+
+// Where a file's timestamped copies go: its whole path with the slashes swapped out, so files of the same name in
+// different directories don't mix
+fn directory_prefix(path: &Path) -> Option<PathBuf> {
+    let path = fs::canonicalize(path).ok()?;
+    let name = path.to_string_lossy().replace('%', "%%").replace('/', "%");
+    Some(paths::state_dir()?.join("backups").join(name))
+}
+
+// The local time as "20240131-140502"
+fn timestamp() -> String {
+    let now = unsafe { libc::time(std::ptr::null_mut()) };
+    let mut tm = unsafe { std::mem::zeroed::<libc::tm>() };
+    if unsafe { libc::localtime_r(&now, &mut tm) }.is_null() {
+        return now.to_string();
+    }
+    format!("{:04}{:02}{:02}-{:02}{:02}{:02}", tm.tm_year + 1900, tm.tm_mon + 1, tm.tm_mday, tm.tm_hour, tm.tm_min, tm.tm_sec)
+}
+
+fn is_timestamp(text: &str) -> bool {
+    text.len() == 15 && text.chars().all(|char| char.is_ascii_digit() || char == '-')
+}
+
+// Copies the file as it is on disk before it is overwritten. A file that doesn't exist yet has nothing to keep
+pub fn make(path: &Path, setting: &str) -> io::Result<()> {
+    if !path.is_file() {
+        return Ok(());
+    }
+
+    match setting {
+        "tilde" => {
+            let mut name = path.file_name().unwrap_or_default().to_os_string();
+            name.push("~");
+            fs::copy(path, path.with_file_name(name)).map(|_| ())
+        }
+        "directory" => {
+            let prefix = directory_prefix(path).ok_or(io::Error::other("no state directory"))?;
+            let dir = prefix.parent().unwrap_or(Path::new("."));
+            fs::create_dir_all(dir)?;
+            let mut name = prefix.file_name().unwrap_or_default().to_os_string();
+            name.push(format!(".{}", timestamp()));
+            fs::copy(path, dir.join(name))?;
+
+            // The timestamps sort by name, so the oldest go first
+            let start = prefix.file_name().unwrap_or_default().to_string_lossy().into_owned() + ".";
+            let mut copies = fs::read_dir(dir)?.filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|copy| copy.file_name().is_some_and(|name| name.to_string_lossy().strip_prefix(&start).is_some_and(is_timestamp)))
+                .collect::<Vec<PathBuf>>();
+            copies.sort();
+            for old in &copies[..copies.len().saturating_sub(KEEP)] {
+                let _ = fs::remove_file(old);
+            }
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
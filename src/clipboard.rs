@@ -0,0 +1,117 @@
+use std::{env, io::Write, time::Duration};
+
+use crate::{shell, terminal};
+
+// How long to wait for the terminal to send the clipboard. Longer than other queries, since the terminal may ask
+// the user first
+const PASTE_TIMEOUT: Duration = Duration::from_secs(2);
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn base64_encode(bytes: &[u8]) -> String {
+    let mut out = String::new();
+    for chunk in bytes.chunks(3) {
+        let group = chunk.iter().enumerate().fold(0u32, |group, (index, byte)| group | (*byte as u32) << (16 - 8 * index));
+        for index in 0..4 {
+            out.push(if index <= chunk.len() { BASE64[(group >> (18 - 6 * index)) as usize & 63] as char } else { '=' });
+        }
+    }
+    out
+}
+
+fn base64_decode(text: &str) -> Option<Vec<u8>> {
+    let values = text.bytes().filter(|byte| *byte != b'=').map(|byte| BASE64.iter().position(|known| *known == byte)).collect::<Option<Vec<usize>>>()?;
+    let mut out = vec![];
+    for chunk in values.chunks(4) {
+        let group = chunk.iter().enumerate().fold(0u32, |group, (index, value)| group | (*value as u32) << (18 - 6 * index));
+        out.extend((0..chunk.len().saturating_sub(1)).map(|index| (group >> (16 - 8 * index)) as u8));
+    }
+    Some(out)
+}
+
+// A program pair that reaches the clipboard directly, for terminals without OSC 52
+pub struct Tool {
+    pub name: &'static str,
+    copy: &'static str, // Reads the text on stdin. Copiers that stay running to own the selection mustn't hold our pipes
+    paste: &'static str,
+    session: Option<&'static str>, // The variable that is set when the tool's display server is running
+}
+
+const TOOLS: &[Tool] = &[
+    Tool { name: "wl-copy", copy: "wl-copy >/dev/null 2>&1", paste: "wl-paste --no-newline", session: Some("WAYLAND_DISPLAY") },
+    Tool { name: "xclip", copy: "xclip -selection clipboard >/dev/null 2>&1", paste: "xclip -selection clipboard -o", session: Some("DISPLAY") },
+    Tool { name: "pbcopy", copy: "pbcopy", paste: "pbpaste", session: None },
+];
+
+// Where copies go and pastes come from. Either way the rest of the editor only sees copy and paste
+pub enum Backend {
+    Osc52,
+    Tool(&'static Tool),
+}
+
+impl Tool {
+    fn available(&self) -> bool {
+        let session = match self.session {
+            Some(variable) => env::var_os(variable).is_some_and(|value| !value.is_empty()),
+            None => cfg!(target_os = "macos"),
+        };
+        session && shell::on_path(self.name)
+    }
+}
+
+// The backend for the `clipboard` setting. "auto" uses OSC 52 over SSH, where the tools would reach the remote
+// machine's clipboard rather than the user's, and otherwise the first tool that works here, falling back to OSC 52
+pub fn backend(setting: &str) -> Result<Backend, String> {
+    match setting {
+        "osc52" => Ok(Backend::Osc52),
+        "auto" if env::var_os("SSH_TTY").is_some() || env::var_os("SSH_CONNECTION").is_some() => Ok(Backend::Osc52),
+        "auto" => Ok(TOOLS.iter().find(|tool| tool.available()).map_or(Backend::Osc52, Backend::Tool)),
+        name => TOOLS.iter().find(|tool| tool.name == name).map(Backend::Tool).ok_or(format!("unknown clipboard `{}`", name)),
+    }
+}
+
+// The names the `clipboard` setting accepts
+pub fn is_known(setting: &str) -> bool {
+    setting == "auto" || setting == "osc52" || TOOLS.iter().any(|tool| tool.name == setting)
+}
+
+impl Backend {
+    pub fn copy(&self, text: &str) -> Result<(), String> {
+        match self {
+            Backend::Osc52 => osc52_copy(text),
+            Backend::Tool(tool) => run(tool.copy, text).map(|_| ()),
+        }
+    }
+
+    pub fn paste(&self) -> Result<String, String> {
+        match self {
+            Backend::Osc52 => osc52_paste(),
+            Backend::Tool(tool) => run(tool.paste, "").map(|text| text.replace("\r\n", "\n")),
+        }
+    }
+}
+
+fn run(command: &str, input: &str) -> Result<String, String> {
+    match shell::run(command, input, None) {
+        Ok(output) if output.status.success() => Ok(String::from_utf8_lossy(&output.stdout).into_owned()),
+        Ok(output) => Err(format!("`{}` failed: {}", command, shell::summary(&output))),
+        Err(err) => Err(format!("`{}` failed: {}", command, err)),
+    }
+}
+
+// Sets the system clipboard through the terminal with OSC 52, which works over SSH since the terminal on the
+// user's machine does the copying. Terminals that don't support it ignore the sequence
+fn osc52_copy(text: &str) -> Result<(), String> {
+    let mut out = std::io::stdout();
+    write!(out, "\x1b]52;c;{}\x1b\\", base64_encode(text.as_bytes())).map_err(|err| err.to_string())?;
+    out.flush().map_err(|err| err.to_string())
+}
+
+// Asks the terminal for the clipboard. Many terminals only allow this once it is enabled in their settings
+fn osc52_paste() -> Result<String, String> {
+    let replies = terminal::query("\x1b]52;c;?\x1b\\", PASTE_TIMEOUT).map_err(|err| err.to_string())?;
+    let reply = replies.split("\x1b]52;").nth(1).ok_or("The terminal didn't send the clipboard (OSC 52 reading may be turned off)")?;
+    let data = reply.split(['\x1b', '\x07']).next().unwrap_or("").split_once(';').map_or("", |(_, data)| data);
+    let bytes = base64_decode(data).ok_or("The terminal sent a malformed clipboard")?;
+    Ok(String::from_utf8_lossy(&bytes).replace("\r\n", "\n"))
+}
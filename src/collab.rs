@@ -0,0 +1,459 @@
+use std::{collections::{HashMap, VecDeque}, fs::File, io::{BufRead, BufReader, Read, Write}, net::{Shutdown, TcpListener, TcpStream}, sync::mpsc::{self, SyncSender}, thread, time::Duration};
+
+use crate::{editor::Editor, event_loop, operations::Operation, text::TextBuffer};
+
+// Experimental shared editing of one buffer over TCP (use an ssh tunnel across untrusted networks). The editor
+// started with --share is the host and orders every edit; editors started with --join are guests. The host makes up
+// a secret that guests must send first, and listens on loopback unless given a host as well as a port. Edits travel as
+// inserts and deletes at char offsets into the buffer's text, with lines joined by \n. A guest sends one edit at a
+// time and waits for the host to acknowledge it, and edits that crossed on the way are transformed against each
+// other (operational transform, with the host's edit going first when two inserts land at the same place).
+//
+// One message per line:
+//   hello <secret>                   guest to host, before anything else; the host hangs up on a wrong secret
+//   doc <revision> <text>            host to a guest that just joined: the whole buffer
+//   ins <revision> <at> <text>       an insert; from a guest, the revision it was made on
+//   del <revision> <at> <length>     a delete, likewise
+//   ack                              host to a guest: its edit was applied
+//   cursor <peer> <line> <column>    where someone's cursor is; guests send it without the peer
+#[derive(Clone, Debug)]
+pub enum Edit {
+    Insert { at: usize, text: String },
+    Delete { at: usize, length: usize },
+}
+
+impl Edit {
+    // This edit changed to apply after `other` was, where both were made on the same text. `first` says which
+    // goes first when both insert at the same place
+    fn transform(&self, other: &Edit, first: bool) -> Edit {
+        match (self.clone(), other) {
+            (Edit::Insert { at, text }, Edit::Insert { at: other_at, text: other_text }) => {
+                let after = at > *other_at || (at == *other_at && !first);
+                Edit::Insert { at: if after { at + other_text.chars().count() } else { at }, text }
+            }
+            (Edit::Insert { at, text }, Edit::Delete { at: other_at, length }) => {
+                Edit::Insert { at: if at <= *other_at { at } else { at.saturating_sub(*length).max(*other_at) }, text }
+            }
+            (Edit::Delete { at, length }, Edit::Insert { at: other_at, text }) => {
+                let count = text.chars().count();
+                if *other_at <= at {
+                    Edit::Delete { at: at + count, length }
+                } else if *other_at >= at.saturating_add(length) {
+                    Edit::Delete { at, length }
+                } else {
+                    // Inserted into the middle of what this deletes, which takes the insert with it
+                    Edit::Delete { at, length: length.saturating_add(count) }
+                }
+            }
+            (Edit::Delete { at, length }, Edit::Delete { at: other_at, length: other_length }) => {
+                let overlap = at.saturating_add(length).min(other_at.saturating_add(*other_length)).saturating_sub(at.max(*other_at));
+                let shift = at.saturating_sub(*other_at).min(*other_length);
+                Edit::Delete { at: at - shift, length: length - overlap }
+            }
+        }
+    }
+
+    // Where a char offset ends up once this edit is made
+    fn moved(&self, offset: usize) -> usize {
+        match self {
+            Edit::Insert { at, text } if *at <= offset => offset + text.chars().count(),
+            Edit::Delete { at, length } if *at < offset => offset - (offset - at).min(*length),
+            _ => offset,
+        }
+    }
+
+    fn message(&self, revision: usize) -> String {
+        match self {
+            Edit::Insert { at, text } => format!("ins {} {} {}", revision, at, escape(text)),
+            Edit::Delete { at, length } => format!("del {} {} {}", revision, at, length),
+        }
+    }
+}
+
+// Messages waiting for a peer's writer thread; one that falls this far behind is dropped rather than stalling the editor
+const PEER_BACKLOG: usize = 4096;
+
+struct Peer {
+    id: usize,
+    sender: SyncSender<String>,
+    stream: TcpStream, // For hanging up, which ends its reader thread too
+}
+
+impl Peer {
+    // Writes to the peer from a thread of its own, so a slow or stalled one never blocks the editor
+    fn new(id: usize, stream: TcpStream) -> Result<Peer, String> {
+        let mut writer = stream.try_clone().map_err(|err| err.to_string())?;
+        let (sender, receiver) = mpsc::sync_channel::<String>(PEER_BACKLOG);
+        thread::spawn(move || {
+            for message in receiver {
+                if writeln!(writer, "{}", message).is_err() {
+                    break;
+                }
+            }
+            let _ = writer.shutdown(Shutdown::Both);
+        });
+        Ok(Peer { id, sender, stream })
+    }
+
+    fn send(&self, message: String) {
+        if self.sender.try_send(message).is_err() {
+            let _ = self.stream.shutdown(Shutdown::Both);
+        }
+    }
+}
+
+pub struct Session {
+    pub document: usize, // The shared buffer's document, as in View
+    host: bool,
+    peers: Vec<Peer>, // Guests, or for a guest the host
+    revision: usize, // Edits the host has ordered so far
+    history: Vec<Edit>, // Host only: every edit, for transforming ones guests made on older revisions
+    pending: VecDeque<Edit>, // Guest only: edits not yet acknowledged, the first one sent
+    pub cursors: HashMap<usize, (usize, usize)>, // Other people's cursors by peer, 0 being the host
+    sent_cursor: Option<(usize, usize)>,
+    pub applying: bool, // Set while applying someone else's edit, which isn't sent back out
+}
+
+impl Session {
+    fn new(document: usize, host: bool, peers: Vec<Peer>) -> Session {
+        Session { document, host, peers, revision: 0, history: vec![], pending: VecDeque::new(), cursors: HashMap::new(), sent_cursor: None, applying: false }
+    }
+
+    fn send(&self, message: &str) {
+        for peer in &self.peers {
+            peer.send(message.to_string());
+        }
+    }
+
+    fn send_to(&self, id: usize, message: &str) {
+        if let Some(peer) = self.peers.iter().find(|peer| peer.id == id) {
+            peer.send(message.to_string());
+        }
+    }
+
+    // An edit made here
+    fn local(&mut self, edit: Edit) {
+        if self.host {
+            self.history.push(edit.clone());
+            self.revision += 1;
+            self.send(&edit.message(self.revision - 1));
+        } else {
+            self.pending.push_back(edit.clone());
+            if self.pending.len() == 1 {
+                self.send(&edit.message(self.revision));
+            }
+        }
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+fn unescape(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(char) = chars.next() {
+        if char != '\\' {
+            result.push(char);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => result.push('\n'),
+            Some(other) => result.push(other),
+            None => {}
+        }
+    }
+    result
+}
+
+// A fresh secret for guests to join with, as 32 hex digits
+fn make_secret() -> Result<String, String> {
+    let mut bytes = [0; 16];
+    File::open("/dev/urandom").and_then(|mut file| file.read_exact(&mut bytes)).map_err(|err| format!("Unable to make a secret: {}", err))?;
+    Ok(bytes.iter().map(|byte| format!("{:02x}", byte)).collect::<String>())
+}
+
+// Compares in the same time wherever the first difference is, so the secret can't be guessed a digit at a time
+fn same_secret(given: &str, secret: &str) -> bool {
+    given.len() == secret.len() && given.bytes().zip(secret.bytes()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+// Host mode: shares the focused buffer with whoever connects to `address` with the secret. A bare port, e.g. 7878,
+// listens on 127.0.0.1 only; other hosts must be given explicitly, e.g. 0.0.0.0:7878
+pub fn share(editor: &mut Editor, address: &str) -> Result<(), String> {
+    let address = if address.chars().all(|char| char.is_ascii_digit()) { format!("127.0.0.1:{}", address) } else { address.to_string() };
+    let listener = TcpListener::bind(&address).map_err(|err| format!("Unable to share on {}: {}", address, err))?;
+    let secret = make_secret()?;
+    editor.session = Some(Session::new(editor.panes[editor.focused].document, true, vec![]));
+
+    let expected = secret.clone();
+    thread::spawn(move || {
+        for (id, stream) in (1..).zip(listener.incoming().flatten()) {
+            let secret = expected.clone();
+            thread::spawn(move || greet(stream, id, &secret));
+        }
+    });
+    editor.message = format!("Sharing this buffer, join with --join={}#{}", address, secret);
+    Ok(())
+}
+
+// Lets a guest in once it has sent the secret, giving it a few seconds to
+fn greet(stream: TcpStream, id: usize, secret: &str) {
+    let Ok(reader) = stream.try_clone() else { return };
+    let mut reader = BufReader::new(reader);
+    let _ = stream.set_read_timeout(Some(Duration::from_secs(10)));
+    let mut hello = String::new();
+    let _ = reader.read_line(&mut hello);
+    let given = hello.trim_end().strip_prefix("hello ").unwrap_or_default();
+    if !same_secret(given, secret) {
+        let _ = stream.shutdown(Shutdown::Both);
+        event_loop::send(Box::new(|editor: &mut Editor| editor.message = "Turned away a connection without the secret".to_string()));
+        return;
+    }
+
+    let _ = stream.set_read_timeout(None);
+    let Ok(peer) = Peer::new(id, stream) else { return };
+    event_loop::send(Box::new(move |editor: &mut Editor| welcome(editor, peer)));
+    read(reader, id);
+}
+
+// Guest mode: replaces the focused buffer with the one shared at `address`, given as address#secret
+pub fn join(editor: &mut Editor, address: &str) -> Result<(), String> {
+    let (address, secret) = address.rsplit_once('#').ok_or("Join with --join=address#secret, as shown by the host")?;
+    let stream = TcpStream::connect(address).map_err(|err| format!("Unable to join {}: {}", address, err))?;
+    let reader = BufReader::new(stream.try_clone().map_err(|err| err.to_string())?);
+    let peer = Peer::new(0, stream)?;
+    peer.send(format!("hello {}", secret));
+    editor.session = Some(Session::new(editor.panes[editor.focused].document, false, vec![peer]));
+    thread::spawn(move || read(reader, 0));
+    editor.message = format!("Joined {}", address);
+    Ok(())
+}
+
+// Hands each message from a peer to the main thread, and that they left once the connection closes
+fn read(reader: BufReader<TcpStream>, id: usize) {
+    for line in reader.lines() {
+        let Ok(line) = line else { break };
+        event_loop::send(Box::new(move |editor: &mut Editor| receive(editor, id, &line)));
+    }
+    event_loop::send(Box::new(move |editor: &mut Editor| left(editor, id)));
+}
+
+fn welcome(editor: &mut Editor, peer: Peer) {
+    let Some(session) = &editor.session else { return };
+    let revision = session.revision;
+    let text = with_shared(editor, |editor| editor.buffer.join("\n")).unwrap_or_default();
+    peer.send(format!("doc {} {}", revision, escape(&text)));
+
+    let Some(session) = &mut editor.session else { return };
+    for (id, (line, column)) in session.cursors.clone() {
+        peer.send(format!("cursor {} {} {}", id, line, column));
+    }
+    peer.send(format!("cursor 0 {} {}", editor.line, editor.column));
+    session.peers.push(peer);
+    editor.message = "Someone joined the shared buffer".to_string();
+}
+
+fn left(editor: &mut Editor, id: usize) {
+    let Some(session) = &mut editor.session else { return };
+    session.peers.retain(|peer| peer.id != id);
+    session.cursors.remove(&id);
+    if session.host {
+        session.send(&format!("cursor {} gone", id));
+        editor.message = "Someone left the shared buffer".to_string();
+    } else {
+        editor.session = None;
+        editor.message = "The shared session ended, this buffer is now your own".to_string();
+    }
+}
+
+fn receive(editor: &mut Editor, id: usize, message: &str) {
+    let Some(session) = &mut editor.session else { return };
+    // The last field runs to the end of the line, spaces and all: a doc's text, or an insert's
+    let fields = if message.starts_with("doc ") { 3 } else { 4 };
+    let words = message.splitn(fields, ' ').collect::<Vec<&str>>();
+    match words[..] {
+        ["doc", revision, text] if !session.host => {
+            session.revision = revision.parse().unwrap_or(0);
+            let lines = unescape(text).split('\n').map(str::to_string).collect::<Vec<String>>();
+            session.applying = true;
+            with_shared(editor, |editor| {
+                editor.replace_lines(0, editor.buffer.len(), lines, (0, 0));
+                editor.history.seal();
+            });
+            if let Some(session) = &mut editor.session {
+                session.applying = false;
+            }
+        }
+        ["ins" | "del", revision, at, argument] => {
+            let (Ok(revision), Ok(at)) = (revision.parse::<usize>(), at.parse::<usize>()) else { return };
+            let edit = if words[0] == "ins" {
+                Edit::Insert { at, text: unescape(argument) }
+            } else {
+                Edit::Delete { at, length: argument.parse().unwrap_or(0) }
+            };
+
+            let edit = if session.host {
+                // Made on an older revision: catch it up with everything ordered since, then pass it on
+                let edit = session.history.iter().skip(revision).fold(edit, |edit, other| edit.transform(other, false));
+                session.history.push(edit.clone());
+                session.revision += 1;
+                let message = edit.message(session.revision - 1);
+                for peer in session.peers.iter().filter(|peer| peer.id != id) {
+                    peer.send(message.clone());
+                }
+                session.send_to(id, "ack");
+                edit
+            } else {
+                // Crossed with our unacknowledged edits, which the host will transform the same way
+                session.revision += 1;
+                let mut edit = edit;
+                for pending in session.pending.iter_mut() {
+                    let transformed = pending.transform(&edit, false);
+                    edit = edit.transform(pending, true);
+                    *pending = transformed;
+                }
+                edit
+            };
+            apply_remote(editor, &edit);
+        }
+        ["ack"] if !session.host => {
+            session.revision += 1;
+            session.pending.pop_front();
+            if let Some(next) = session.pending.front() {
+                let message = next.message(session.revision);
+                session.send(&message);
+            }
+        }
+        ["cursor", peer, "gone"] => {
+            session.cursors.remove(&peer.parse().unwrap_or(usize::MAX));
+        }
+        ["cursor", peer, line, column] if !session.host => {
+            if let (Ok(peer), Ok(line), Ok(column)) = (peer.parse(), line.parse(), column.parse()) {
+                session.cursors.insert(peer, (line, column));
+            }
+        }
+        ["cursor", line, column] if session.host => {
+            if let (Ok(line), Ok(column)) = (line.parse(), column.parse()) {
+                session.cursors.insert(id, (line, column));
+                let message = format!("cursor {} {} {}", id, line, column);
+                for peer in session.peers.iter().filter(|peer| peer.id != id) {
+                    peer.send(message.clone());
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+// Runs `action` with the shared buffer focused, returning None when no pane shows it any more
+fn with_shared<T>(editor: &mut Editor, action: impl FnOnce(&mut Editor) -> T) -> Option<T> {
+    let document = editor.session.as_ref()?.document;
+    if editor.panes[editor.focused].document == document {
+        return Some(action(editor));
+    }
+
+    // Focusing drops the selection and extra cursors, which are in another buffer and put back as they were
+    let index = (0..editor.panes.len()).find(|index| editor.panes[*index].document == document)?;
+    let (focused, selection, selecting, cursors) = (editor.focused, editor.selection, editor.selecting, std::mem::take(&mut editor.cursors));
+    editor.focus(index);
+    let result = action(editor);
+    editor.focus(focused);
+    (editor.selection, editor.selecting, editor.cursors) = (selection, selecting, cursors);
+    Some(result)
+}
+
+// Makes someone else's edit, outside the undo history (see Editor::record), keeping the cursor, the selection
+// and any extra cursors on the same text
+fn apply_remote(editor: &mut Editor, edit: &Edit) {
+    if let Some(session) = &mut editor.session {
+        session.applying = true;
+    }
+    with_shared(editor, |editor| {
+        let cursor = edit.moved(offset_of(&editor.buffer, (editor.line, editor.column)));
+        let selection = editor.selection.map(|anchor| edit.moved(offset_of(&editor.buffer, anchor)));
+        let cursors = editor.cursors.iter().map(|cursor| edit.moved(offset_of(&editor.buffer, *cursor))).collect::<Vec<usize>>();
+        let operation = match edit {
+            Edit::Insert { at, text } => Operation::InsertText { at: position_of(&editor.buffer, *at), text: text.clone() },
+            // Offsets come from a peer, so past the end is taken as the end rather than trusted
+            Edit::Delete { at, length } => Operation::DeleteRange { start: position_of(&editor.buffer, *at), end: position_of(&editor.buffer, at.saturating_add(*length)) },
+        };
+        editor.history.seal();
+        editor.apply(operation);
+        editor.history.seal();
+        let position = position_of(&editor.buffer, cursor);
+        editor.goto(position);
+        editor.selection = selection.map(|offset| position_of(&editor.buffer, offset));
+        editor.cursors = cursors.into_iter().map(|offset| position_of(&editor.buffer, offset)).collect();
+    });
+    if let Some(session) = &mut editor.session {
+        session.applying = false;
+    }
+}
+
+// The char offset of a position in the buffer's text, with lines joined by \n
+fn offset_of(buffer: &impl TextBuffer, (line, column): (usize, usize)) -> usize {
+    let Some(last) = buffer.len().checked_sub(1) else { return 0 };
+    let line = line.min(last);
+    buffer.lines(..line).map(|text| text.chars().count() + 1).sum::<usize>() + column.min(buffer[line].chars().count())
+}
+
+fn position_of(buffer: &impl TextBuffer, mut offset: usize) -> (usize, usize) {
+    for (line, text) in buffer.iter().enumerate() {
+        let length = text.chars().count();
+        if offset <= length {
+            return (line, offset);
+        }
+        offset -= length + 1;
+    }
+    buffer.last().map_or((0, 0), |text| (buffer.len() - 1, text.chars().count()))
+}
+
+// Sends a change just made to the shared buffer as a delete and an insert, trimmed to the text that differs
+pub fn changed(editor: &mut Editor, start: usize, old: &[String], new: &[String], at: Option<usize>) {
+    let Some(session) = &mut editor.session else { return };
+    if session.applying || editor.panes[editor.focused].document != session.document {
+        return;
+    }
+
+    let buffer = &editor.buffer;
+    // Where line `start` begins, or would after the last line when the change took lines off the end
+    let line_start = buffer.lines(..start.min(buffer.len())).map(|text| text.chars().count() + 1).sum::<usize>();
+    let (offset, deleted, inserted) = match at {
+        Some(byte) => (line_start + buffer[start][..byte].chars().count(), old[0].clone(), new[0].clone()),
+        None if old.is_empty() && start < buffer.len() - new.len() => (line_start, String::new(), new.join("\n") + "\n"),
+        None if old.is_empty() && start > 0 => (line_start - 1, String::new(), "\n".to_string() + &new.join("\n")),
+        // Lines into a buffer that had none, so no line break goes with them
+        None if old.is_empty() => (0, String::new(), new.join("\n")),
+        None if new.is_empty() && start < buffer.len() => (line_start, old.join("\n") + "\n", String::new()),
+        None if new.is_empty() && start > 0 => (line_start - 1, "\n".to_string() + &old.join("\n"), String::new()),
+        None if new.is_empty() => (0, old.join("\n"), String::new()),
+        None => (line_start, old.join("\n"), new.join("\n")),
+    };
+
+    let (deleted, inserted) = (deleted.chars().collect::<Vec<char>>(), inserted.chars().collect::<Vec<char>>());
+    let prefix = deleted.iter().zip(&inserted).take_while(|(old, new)| old == new).count();
+    let suffix = deleted[prefix..].iter().rev().zip(inserted[prefix..].iter().rev()).take_while(|(old, new)| old == new).count();
+    let (deleted, inserted) = (&deleted[prefix..deleted.len() - suffix], &inserted[prefix..inserted.len() - suffix]);
+    if !deleted.is_empty() {
+        session.local(Edit::Delete { at: offset + prefix, length: deleted.len() });
+    }
+    if !inserted.is_empty() {
+        session.local(Edit::Insert { at: offset + prefix, text: inserted.iter().collect() });
+    }
+}
+
+// Lets the others see where the cursor is after each key
+pub fn after_key(editor: &mut Editor) {
+    let Some(session) = &mut editor.session else { return };
+    let cursor = (editor.line, editor.column);
+    if editor.panes[editor.focused].document != session.document || session.sent_cursor == Some(cursor) {
+        return;
+    }
+
+    session.sent_cursor = Some(cursor);
+    let message = if session.host { format!("cursor 0 {} {}", cursor.0, cursor.1) } else { format!("cursor {} {}", cursor.0, cursor.1) };
+    session.send(&message);
+}
+
@@ -0,0 +1,806 @@
+use std::{fs, path::{Path, PathBuf}};
+
+use crate::{config::Value, conflicts, csv, editor::Editor, encoding::{Encoding, LineEnding}, filetype, filters, folds, hooks, json, marks, offset, panes::Output, picker, print, project_replace, remap, replace::{self, Matcher, ReplaceOptions}, selection, shell, tasks, text::TextBuffer, tree::FileTree, urls, vars};
+
+// Every command with a short description, for help text and the tutorial
+pub const COMMANDS: &[(&str, &str)] = &[
+    ("saveas path", "Save the buffer under a new path and keep editing it there"),
+    ("print [-n] [-h]", "Print the buffer, optionally with line numbers and a header"),
+    ("format", "Run the buffer through the configured formatter"),
+    ("json [-m]", "Pretty-print the selection or buffer as JSON, or minify it with -m; a parse error selects the line it is on"),
+    ("build", "Run the configured build command"),
+    ("run", "Save and run the file with the interpreter for its filetype, showing the output in a pane"),
+    ("number [start] [step]", "Insert an increasing number at the left edge of the selected block"),
+    ("replace [-i] [-p] [-r] [-a] pattern replacement", "Replace every occurrence; -i ignores case, -p preserves it, -r uses a regex ($1, \\U..\\E), -a previews and replaces across the project"),
+    ("mark letter", "Remember the cursor position; uppercase marks are kept across sessions and files"),
+    ("jump letter", "Return to a mark, opening its file for uppercase marks"),
+    ("lineending lf|crlf", "Convert the line endings the buffer is saved with"),
+    ("encoding utf-8|latin-1", "Convert the encoding the buffer is saved with"),
+    ("filter name", "Toggle an input filter for this buffer, e.g. smartquotes for text and markdown"),
+    ("set [name [value]]", "Show the buffer's variables or one of them, or set one for this buffer, e.g. set indent_width 2"),
+    ("unset name", "Drop a variable set on this buffer, going back to the config's value"),
+    ("trash", "Browse recently deleted lines and selections and restore one at the cursor"),
+    ("conflict [next|prev|ours|theirs|both]", "Count merge conflicts, move between them, or resolve the one at the cursor by taking a side or both"),
+    ("columns [next|prev]", "In a CSV or TSV file, toggle lining up its columns, or move to the next or previous field; alt+arrows move too"),
+    ("rebase", "In a rebase todo list, switch between letters changing the command on the line and typing"),
+    ("search [-i] [-r] [pattern]", "Jump to the next match of the pattern, or of the last one"),
+    ("cursors", "Put a cursor at every match of the last search in the selection or buffer, so typing edits them all"),
+    ("conceal", "Toggle masking of secrets on screen"),
+    ("debug", "Toggle the debug overlay showing input latency"),
+    ("fold [all | level n]", "Fold the indented block at the cursor, every block, or every block n or more levels deep"),
+    ("unfold [all]", "Unfold the block at the cursor, or everything"),
+    ("goto line[:column] | -b offset | -c offset", "Jump to a line, or to a byte or char offset in the file (decimal or 0x hex)"),
+    ("offset [status]", "Show the cursor's byte and char offset in the file, or toggle showing them in the status bar"),
+    ("zen [dim]", "Toggle distraction free writing: centered text, no status bar, optionally dimming other paragraphs"),
+    ("typewriter", "Toggle typewriter scrolling, which keeps the cursor line centered"),
+    ("tasks", "List the background tasks still running, like builds and after_save hooks"),
+    ("messages", "Show the message log, which has the output of after_save hooks"),
+    ("next | prev | first | last", "Open the next, previous, first or last of the files given on the command line"),
+    ("args", "List the files given on the command line"),
+    ("argdo command", "Run a command on each of the files given on the command line, saving each one"),
+    ("split [file]", "Split the focused pane into another one below, onto the buffer or a file; ctrl+w moves between panes"),
+    ("vsplit [file]", "Split the focused pane into another one to its right, onto the buffer or a file"),
+    ("close", "Close the output pane, or the focused pane"),
+    ("numbers", "Toggle line numbers in the focused pane"),
+    ("whitespace", "Toggle showing spaces and tabs in the focused pane"),
+    ("invisibles", "Toggle showing zero-width chars and soft hyphens as placeholders in the focused pane"),
+    ("wrap", "Toggle wrapping long lines onto the rows below in the focused pane"),
+    ("ruler", "Toggle a column ruler above the text"),
+    ("tree", "Show the project's files in a sidebar and focus it, or close it when focused; ctrl+w also moves to it"),
+    ("open", "Open the URL under the cursor in the browser, with xdg-open or open on macOS"),
+    ("whichkey", "Press a key to see what it does in each mode and where its binding comes from"),
+    ("showkeys", "Toggle the on-screen display of pressed keys"),
+];
+
+// Splits a command line into words. Single or double quotes group words with spaces, and a backslash
+// escapes a quote or a space
+pub fn split_words(command: &str) -> Result<Vec<String>, String> {
+    let mut words = vec![];
+    let mut current: Option<String> = None;
+    let mut quote: Option<char> = None;
+    let mut chars = command.chars();
+
+    while let Some(char) = chars.next() {
+        match (char, quote) {
+            ('\\', _) => {
+                let word = current.get_or_insert_default();
+                match chars.next() {
+                    Some(escaped) if escaped == '"' || escaped == '\'' || escaped.is_whitespace() => word.push(escaped),
+                    // Other backslashes are kept so regex escapes like \w survive
+                    Some(other) => {
+                        word.push('\\');
+                        word.push(other);
+                    }
+                    None => word.push('\\'),
+                }
+            }
+            (char, Some(open)) if char == open => quote = None,
+            (char, Some(_)) => current.get_or_insert_default().push(char),
+            ('"' | '\'', None) => {
+                quote = Some(char);
+                current.get_or_insert_default();
+            }
+            (char, None) if char.is_whitespace() => words.extend(current.take()),
+            (char, None) => current.get_or_insert_default().push(char),
+        }
+    }
+
+    if quote.is_some() {
+        return Err("unterminated quote".to_string());
+    }
+
+    words.extend(current);
+    Ok(words)
+}
+
+// Runs a command typed at the ctrl+e prompt, e.g. "print -n -h"
+pub fn run(editor: &mut Editor, command: &str) {
+    let words = match split_words(command) {
+        Ok(words) => words,
+        Err(err) => {
+            editor.message = err;
+            return;
+        }
+    };
+    let Some(name) = words.first().map(String::as_str) else { return };
+    let args = words[1..].iter().map(String::as_str).collect::<Vec<&str>>();
+
+    match name {
+        "print" => print::print(editor, &args),
+        "format" => format(editor),
+        "json" => match args[..] {
+            [] => json(editor, false),
+            ["-m"] => json(editor, true),
+            _ => editor.message = "usage: json [-m]".to_string(),
+        },
+        "showkeys" => {
+            editor.show_keys = !editor.show_keys;
+            editor.recent_keys.clear();
+            editor.message = format!("Keypress display {}", if editor.show_keys { "on" } else { "off" });
+        }
+        "open" => {
+            let line = &editor.buffer[editor.line];
+            editor.message = match urls::at(line, editor.column) {
+                Some(url) => match urls::open(&url) {
+                    Ok(()) => format!("Opened {}", url),
+                    Err(err) => err,
+                },
+                None => "No URL under the cursor".to_string(),
+            };
+        }
+        "whichkey" => {
+            editor.message = "Press a key to inspect".to_string();
+            if editor.draw().is_err() {
+                editor.message.clear();
+                return;
+            }
+            let key = editor.read_key();
+            let lines = editor.describe_key(&key);
+            editor.message.clear();
+            if let Err(err) = editor.show_output(Output { title: format!("[whichkey] {}", key), lines }) {
+                editor.message = err;
+            }
+        }
+        "debug" => {
+            editor.show_debug = !editor.show_debug;
+            editor.message = format!("Debug overlay {}", if editor.show_debug { "on" } else { "off" });
+        }
+        "fold" => fold(editor, &args),
+        "unfold" => match args[..] {
+            [] => {
+                let header = folds::regions(&editor.buffer).into_iter().rev().find(|(start, end, _)| (*start..=*end).contains(&editor.line) && editor.folds.is_closed(*start));
+                match header {
+                    Some((start, _, _)) => editor.folds.open(start),
+                    None => editor.message = "No fold here".to_string(),
+                }
+            }
+            ["all"] => editor.folds.clear(),
+            _ => editor.message = "usage: unfold [all]".to_string(),
+        },
+        "goto" => goto(editor, &args),
+        "offset" => match args[..] {
+            [] => {
+                let (bytes, chars) = editor.offsets();
+                editor.message = format!("Byte offset {} (0x{:x}), char offset {}", bytes, bytes, chars);
+            }
+            ["status"] => {
+                editor.show_offset = !editor.show_offset;
+                editor.message = format!("Offsets in the status bar {}", if editor.show_offset { "on" } else { "off" });
+            }
+            _ => editor.message = "usage: offset [status]".to_string(),
+        },
+        "zen" => match args[..] {
+            [] | ["dim"] => {
+                editor.zen = !editor.zen;
+                editor.zen_dim = editor.zen && args == ["dim"];
+                editor.message = format!("Zen mode {}", if editor.zen { "on" } else { "off" });
+            }
+            _ => editor.message = "usage: zen [dim]".to_string(),
+        },
+        "typewriter" => {
+            editor.config.typewriter_scrolling = !editor.config.typewriter_scrolling;
+            editor.message = format!("Typewriter scrolling {}", if editor.config.typewriter_scrolling { "on" } else { "off" });
+        }
+        "messages" => {
+            let lines = editor.log.iter().cloned().collect::<Vec<String>>();
+            if lines.is_empty() {
+                editor.message = "The message log is empty".to_string();
+            } else if let Err(err) = editor.show_output(Output { title: "[messages]".to_string(), lines }) {
+                editor.message = err;
+            }
+        }
+        "next" | "prev" | "first" | "last" if args.is_empty() => {
+            let last = editor.arg_list.len().saturating_sub(1);
+            match (name, editor.arg_index) {
+                ("next", index) if index < last => open_arg(editor, index + 1),
+                ("prev", index) if index > 0 => open_arg(editor, index - 1),
+                ("first", _) => open_arg(editor, 0),
+                ("last", _) => open_arg(editor, last),
+                ("next", _) => editor.message = "Already at the last file".to_string(),
+                _ => editor.message = "Already at the first file".to_string(),
+            }
+        }
+        "args" => {
+            let names = editor.arg_list.iter().enumerate().map(|(index, path)| {
+                if index == editor.arg_index { format!("[{}]", path.display()) } else { path.display().to_string() }
+            }).collect::<Vec<String>>();
+            editor.message = if names.is_empty() { "No files were given on the command line".to_string() } else { names.join(" ") };
+        }
+        "argdo" => argdo(editor, command.trim_start()["argdo".len()..].trim()),
+        "split" | "vsplit" => {
+            let beside = name == "vsplit";
+            let result = match args[..] {
+                [] => editor.split(beside),
+                [path] => editor.split_file(beside, PathBuf::from(path)),
+                _ => Err(format!("usage: {} [file]", name)),
+            };
+            if let Err(err) = result {
+                editor.message = err;
+            }
+        }
+        "close" => {
+            if let Err(err) = editor.close_pane() {
+                editor.message = err;
+            }
+        }
+        "numbers" => {
+            let options = &mut editor.panes[editor.focused].options;
+            options.numbers = !options.numbers;
+            editor.message = format!("Line numbers {}", if options.numbers { "on" } else { "off" });
+        }
+        "tree" => match &mut editor.tree {
+            Some(tree) if tree.focused => editor.tree = None,
+            Some(tree) => tree.focused = true,
+            None => {
+                let root = editor.project_root.clone().or_else(|| std::env::current_dir().ok()).unwrap_or_else(|| PathBuf::from("."));
+                editor.tree = Some(FileTree::new(root));
+            }
+        },
+        "wrap" => {
+            let view = &mut editor.panes[editor.focused];
+            view.options.wrap = !view.options.wrap;
+            view.start_row = 0;
+            editor.message = format!("Wrapping {}", if view.options.wrap { "on" } else { "off" });
+        }
+        "whitespace" => {
+            let options = &mut editor.panes[editor.focused].options;
+            options.whitespace = !options.whitespace;
+            editor.message = format!("Whitespace {}", if options.whitespace { "shown" } else { "hidden" });
+        }
+        "invisibles" => {
+            let options = &mut editor.panes[editor.focused].options;
+            options.invisibles = !options.invisibles;
+            editor.message = format!("Invisible chars {}", if options.invisibles { "shown" } else { "hidden" });
+        }
+        "ruler" => {
+            editor.show_ruler = !editor.show_ruler;
+            editor.message = format!("Ruler {}", if editor.show_ruler { "on" } else { "off" });
+        }
+        "build" => build(editor),
+        "tasks" => {
+            let running = editor.tasks.describe();
+            editor.message = if running.is_empty() { "No tasks running".to_string() } else { running.join(", ") };
+        }
+        "run" => run_file(editor),
+        "number" => number(editor, &args),
+        "replace" => replace(editor, &args),
+        "mark" => mark(editor, &args),
+        "jump" => jump(editor, &args),
+        "lineending" => match args[..] {
+            [name] if let Some(line_ending) = LineEnding::parse(name) => {
+                editor.line_ending = line_ending;
+                editor.unsaved = true;
+                editor.message = format!("Line endings converted to {}", line_ending);
+            }
+            _ => editor.message = "usage: lineending lf|crlf".to_string(),
+        },
+        "encoding" => match args[..] {
+            [name] if let Some(encoding) = Encoding::parse(name) => {
+                editor.encoding = encoding;
+                editor.unsaved = true;
+                editor.message = format!("Encoding converted to {}", encoding);
+            }
+            _ => editor.message = "usage: encoding utf-8|latin-1".to_string(),
+        },
+        "filter" => match args[..] {
+            [name] if let Some(name) = filters::find(name) => {
+                let enabled = !editor.filters.contains(&name);
+                editor.filters.retain(|filter| *filter != name);
+                if enabled {
+                    editor.filters.push(name);
+                }
+                editor.message = format!("{} {}", name, if enabled { "on" } else { "off" });
+            }
+            _ => editor.message = format!("usage: filter {}", filters::FILTERS.iter().map(|(name, _, _)| *name).collect::<Vec<&str>>().join("|")),
+        },
+        "set" => match args[..] {
+            [] => editor.message = editor.all_vars().iter().map(|(name, value)| format!("{}={}", name, value)).collect::<Vec<String>>().join(" "),
+            [name] => editor.message = match editor.var(name) {
+                Some(value) => format!("{}={}", name, value),
+                None => format!("{} is not set", name),
+            },
+            [name, ref value @ ..] => editor.message = match editor.set_var(name, vars::parse(&value.join(" "))) {
+                Ok(()) => format!("{}={}", name, editor.var(name).unwrap_or(Value::String(String::new()))),
+                Err(err) => err,
+            },
+        },
+        "unset" => match args[..] {
+            [name] if editor.vars.remove(name).is_some() => editor.message = format!("{} unset", name),
+            [name] => editor.message = format!("{} is not set on this buffer", name),
+            _ => editor.message = "usage: unset name".to_string(),
+        },
+        "trash" => trash(editor),
+        "search" => search(editor, &args),
+        "conflict" => conflicts::command(editor, &args),
+        "saveas" => match args[..] {
+            [path] => {
+                if let Err(err) = editor.save_as(PathBuf::from(path)) {
+                    editor.message = err;
+                }
+            }
+            _ => editor.message = "usage: saveas path".to_string(),
+        },
+        "columns" => match (csv::separator(editor.filetype), &args[..]) {
+            (None, _) => editor.message = "Not a CSV or TSV file".to_string(),
+            (Some(_), []) => {
+                let on = !editor.var_is("align_columns");
+                editor.vars.set("align_columns", Value::Boolean(on)).expect("align_columns is a boolean");
+                editor.message = format!("Columns {}", if on { "aligned" } else { "as written" });
+            }
+            (Some(separator), [direction @ ("next" | "prev")]) => editor.step_column(separator, *direction == "next"),
+            _ => editor.message = "usage: columns [next|prev]".to_string(),
+        },
+        "rebase" => {
+            let on = !editor.var_is("rebase_keys");
+            editor.vars.set("rebase_keys", Value::Boolean(on)).expect("rebase_keys is a boolean");
+            editor.message = format!("Rebase keys {}", if on { "on" } else { "off, letters type" });
+        }
+        "cursors" => cursors(editor),
+        "conceal" => {
+            editor.conceal = !editor.conceal;
+            editor.message = format!("Conceal mode {}", if editor.conceal { "on" } else { "off" });
+        }
+        _ => editor.message = format!("Unknown command: {}", name),
+    }
+}
+
+// Pipes the buffer through the configured formatter and replaces it with the output
+fn format(editor: &mut Editor) {
+    let Some(formatter) = editor.config.formatter.clone() else {
+        editor.message = "No formatter configured".to_string();
+        return;
+    };
+
+    match shell::run(&formatter, &editor.buffer.join("\n"), editor.project_root.as_deref()) {
+        Ok(output) if output.status.success() => {
+            let text = String::from_utf8_lossy(&output.stdout);
+            let formatted = text.split('\n').map(str::to_string).collect::<Vec<String>>();
+
+            // Follow the text the cursor was on, and keep it on the same screen row
+            let (line, column) = remap::remap_cursor(&editor.buffer, &formatted, (editor.line, editor.column));
+            let start_line = line.saturating_sub(editor.line.saturating_sub(editor.start_line));
+            let document = editor.panes[editor.focused].document;
+            for pane in editor.panes.iter_mut().filter(|pane| pane.document == document) {
+                let (line, column) = remap::remap_cursor(&editor.buffer, &formatted, (pane.line.min(editor.buffer.len() - 1), pane.column));
+                (pane.line, pane.column, pane.start_line) = (line, column, line.saturating_sub(pane.line.saturating_sub(pane.start_line)));
+            }
+
+            editor.history.seal();
+            editor.replace_lines(0, editor.buffer.len(), formatted, (line, column));
+            editor.history.seal();
+            editor.start_line = start_line;
+            editor.message = format!("Formatted with {}", formatter);
+        }
+        Ok(output) => editor.message = format!("{} failed: {}", formatter, shell::summary(&output)),
+        Err(err) => editor.message = format!("Unable to run {}: {}", formatter, err),
+    }
+}
+
+fn json(editor: &mut Editor, minify: bool) {
+    // Without a selection the whole buffer, leaving out blank lines at the end so the line break after the
+    // closing bracket survives
+    let (start, end) = editor.selection_range().unwrap_or_else(|| {
+        let last = editor.buffer.iter().rposition(|line| !line.trim().is_empty()).unwrap_or(0);
+        ((0, 0), (last, editor.buffer[last].chars().count()))
+    });
+    let text = selection::text(&editor.buffer, start, end);
+
+    let value = match json::parse(&text) {
+        Ok(value) => value,
+        Err(err) => {
+            let line = start.0 + err.line;
+            let column = err.column + if err.line == 0 { start.1 } else { 0 };
+            editor.selection = Some((line, 0));
+            editor.goto((line, usize::MAX));
+            editor.message = format!("JSON error on line {}, column {}: {}", line + 1, column + 1, err.message);
+            return;
+        }
+    };
+
+    // Lines after the first keep the indentation of the line the selection starts on
+    let base = editor.buffer[start.0].chars().take_while(|char| char.is_whitespace()).collect::<String>();
+    let formatted = if minify { json::minify(&value) } else { json::pretty(&value, &editor.indent_string(), 0) };
+    let before = editor.buffer[start.0].chars().take(start.1).collect::<String>();
+    let after = editor.buffer[end.0].chars().skip(end.1).collect::<String>();
+    let lines = (before + &formatted.replace('\n', &format!("\n{}", base)) + &after).split('\n').map(str::to_string).collect::<Vec<String>>();
+
+    let count = lines.len();
+    editor.selection = None;
+    editor.history.seal();
+    editor.replace_lines(start.0, end.0 - start.0 + 1, lines, start);
+    editor.history.seal();
+    editor.message = format!("{} into {} line{}", if minify { "Minified" } else { "Pretty-printed" }, count, if count == 1 { "" } else { "s" });
+}
+
+fn build(editor: &mut Editor) {
+    let Some(command) = editor.config.build_command.clone() else {
+        editor.message = "No build command configured".to_string();
+        return;
+    };
+
+    // Builds can take a while, so they run in the background and report when done
+    let root = editor.project_root.clone();
+    editor.message = format!("Building: {}", command);
+    tasks::spawn(editor, format!("build: {}", command), move || (shell::run(&command, "", root.as_deref()), command), |editor, (result, command)| {
+        editor.message = match result {
+            Ok(output) if output.status.success() => format!("Build succeeded: {}", command),
+            Ok(output) => match shell::summary(&output) {
+                summary if summary.is_empty() => format!("Build failed ({})", output.status),
+                summary => format!("Build failed ({}): {}", output.status, summary),
+            },
+            Err(err) => format!("Unable to run {}: {}", command, err),
+        };
+    });
+}
+
+// Runs the file in the background, e.g. `python3 file.py`, from its own directory. The output goes in the output
+// pane with the exit code in its status bar
+fn run_file(editor: &mut Editor) {
+    let Some(runner) = filetype::runner(editor.filetype) else {
+        editor.message = format!("No way to run {} files", if editor.filetype.is_empty() { "these" } else { editor.filetype });
+        return;
+    };
+
+    if editor.unsaved {
+        editor.save();
+    }
+
+    let command = runner.replace("{}", &shell::quote(&editor.path.to_string_lossy()));
+    let dir = editor.path.parent().filter(|dir| !dir.as_os_str().is_empty()).map(Path::to_path_buf);
+    editor.message = format!("Running: {}", command);
+    tasks::spawn(editor, format!("run: {}", command), move || (shell::run(&command, "", dir.as_deref()), command), |editor, (result, command)| {
+        let output = match result {
+            Ok(output) => output,
+            Err(err) => {
+                editor.message = format!("Unable to run {}: {}", command, err);
+                return;
+            }
+        };
+
+        let status = output.status.code().map_or(output.status.to_string(), |code| format!("exit {}", code));
+        let text = String::from_utf8_lossy(&output.stdout).to_string() + &String::from_utf8_lossy(&output.stderr);
+        let lines = text.trim_end().split('\n').map(str::to_string).collect();
+        editor.message = format!("{} finished: {}", command, status);
+        if let Err(err) = editor.show_output(Output { title: format!("[run] {}  {}", command, status), lines }) {
+            editor.message = format!("{} finished: {} ({})", command, status, err);
+        }
+    });
+}
+
+// Opens one of the files given on the command line
+fn open_arg(editor: &mut Editor, index: usize) {
+    let Some(path) = editor.arg_list.get(index).cloned() else {
+        editor.message = "No files were given on the command line".to_string();
+        return;
+    };
+
+    if index == editor.arg_index {
+        return;
+    }
+
+    match editor.open_file(path.clone()) {
+        Ok(()) => {
+            editor.arg_index = index;
+            editor.message = format!("File {} of {}: {}", index + 1, editor.arg_list.len(), path.display());
+        }
+        Err(err) => editor.message = err,
+    }
+}
+
+// Runs a command on every file of the argument list in turn, starting from the first, and saves each one it
+// changes. Stops at the first file that can't be opened
+fn argdo(editor: &mut Editor, command: &str) {
+    if command.is_empty() || editor.arg_list.is_empty() {
+        editor.message = "usage: argdo command, with files given on the command line".to_string();
+        return;
+    }
+
+    let mut messages = vec![];
+    for index in 0..editor.arg_list.len() {
+        if index != editor.arg_index {
+            let path = editor.arg_list[index].clone();
+            if let Err(err) = editor.open_file(path) {
+                editor.message = err;
+                return;
+            }
+            editor.arg_index = index;
+        }
+
+        editor.message.clear();
+        run(editor, command);
+        if editor.unsaved {
+            editor.save();
+            hooks::after_save(editor);
+        }
+        if !editor.message.is_empty() {
+            messages.push(format!("{}: {}", editor.path.display(), editor.message));
+        }
+    }
+
+    editor.log(&messages.join("\n"));
+    editor.message = format!("Ran `{}` on {} files, see messages for what it said", command, editor.arg_list.len());
+}
+
+// Numbers the lines of the selection as a block: each line gets the next number inserted at the selection's
+// leftmost column, padding short lines with spaces
+fn number(editor: &mut Editor, args: &[&str]) {
+    let Some(anchor) = editor.selection else {
+        editor.message = "number: select the lines to number first".to_string();
+        return;
+    };
+
+    let parse = |arg: Option<&&str>, default: i64| arg.map_or(Ok(default), |arg| arg.parse::<i64>());
+    let (Ok(start), Ok(step)) = (parse(args.first(), 1), parse(args.get(1), 1)) else {
+        editor.message = "number: start and step must be integers".to_string();
+        return;
+    };
+
+    let first_line = anchor.0.min(editor.line);
+    let last_line = anchor.0.max(editor.line);
+    let column = anchor.1.min(editor.column);
+
+    let lines = (first_line..=last_line).enumerate().map(|(idx, line)| {
+        let mut chars = editor.buffer[line].chars().collect::<Vec<char>>();
+        if chars.len() < column {
+            chars.resize(column, ' ');
+        }
+
+        let number = (start + step * idx as i64).to_string();
+        chars.splice(column..column, number.chars());
+        chars.into_iter().collect::<String>()
+    }).collect::<Vec<String>>();
+
+    let count = lines.len();
+    editor.selection = None;
+    editor.history.seal();
+    editor.replace_lines(first_line, count, lines, (first_line, column));
+    editor.history.seal();
+    editor.message = format!("Numbered {} lines", count);
+}
+
+fn replace(editor: &mut Editor, args: &[&str]) {
+    let mut options = ReplaceOptions::default();
+    let mut words = vec![];
+    let mut project = false;
+
+    for arg in args {
+        match *arg {
+            "-i" => options.ignore_case = true,
+            "-p" => options.preserve_case = true,
+            "-r" => options.regex = true,
+            "-a" => project = true,
+            _ => words.push(*arg),
+        }
+    }
+
+    let [pattern, replacement] = words[..] else {
+        editor.message = "usage: replace [-i] [-p] [-r] [-a] pattern replacement".to_string();
+        return;
+    };
+
+    let matcher = match Matcher::new(pattern, options) {
+        Ok(matcher) => matcher,
+        Err(err) => {
+            editor.message = format!("Invalid pattern: {}", err);
+            return;
+        }
+    };
+
+    if project {
+        project_replace::replace_in_project(editor, &matcher, replacement, options);
+        return;
+    }
+
+    let count = replace::replace_all(editor, &matcher, replacement, options);
+    editor.message = format!("Replaced {} occurrence{}", count, if count == 1 { "" } else { "s" });
+}
+
+fn mark_name(args: &[&str]) -> Option<char> {
+    let [name] = args else { return None };
+    let mut chars = name.chars();
+    chars.next().filter(|char| char.is_ascii_alphabetic() && chars.next().is_none())
+}
+
+fn mark(editor: &mut Editor, args: &[&str]) {
+    let Some(name) = mark_name(args) else {
+        editor.message = "usage: mark letter".to_string();
+        return;
+    };
+
+    let position = (editor.line, editor.column);
+    if name.is_ascii_lowercase() {
+        editor.marks.insert(name, position);
+    } else if editor.scratch {
+        editor.message = "Global marks need a named file".to_string();
+        return;
+    } else if let Err(err) = marks::set_global(name, &editor.path, position) {
+        editor.message = format!("Unable to save mark: {}", err);
+        return;
+    }
+
+    editor.message = format!("Mark {} set at line {}", name, position.0 + 1);
+}
+
+fn jump(editor: &mut Editor, args: &[&str]) {
+    let Some(name) = mark_name(args) else {
+        editor.message = "usage: jump letter".to_string();
+        return;
+    };
+
+    if name.is_ascii_lowercase() {
+        match editor.marks.get(&name) {
+            Some(position) => editor.goto(*position),
+            None => editor.message = format!("Mark {} is not set", name),
+        }
+        return;
+    }
+
+    let Some((path, position)) = marks::global(name) else {
+        editor.message = format!("Mark {} is not set", name);
+        return;
+    };
+
+    let same_file = !editor.scratch && fs::canonicalize(&editor.path).is_ok_and(|current| current == path);
+    if !same_file && let Err(err) = editor.open_file(path) {
+        editor.message = err;
+        return;
+    }
+
+    editor.goto(position);
+}
+
+fn trash(editor: &mut Editor) {
+    let items = editor.trash.entries().iter().map(|entry| entry.describe()).collect::<Vec<String>>();
+    if items.is_empty() {
+        editor.message = "The trash is empty".to_string();
+        return;
+    }
+
+    let Some(index) = picker::pick(editor, "Trash", &items) else { return };
+    let entry = &editor.trash.entries()[index];
+    let (text, linewise) = (entry.text.clone(), entry.linewise);
+
+    editor.history.seal();
+    if linewise {
+        let lines = text.split('\n').map(str::to_string).collect::<Vec<String>>();
+        editor.replace_lines(editor.line, 0, lines, (editor.line, 0));
+    } else {
+        editor.insert_at_cursor(&text);
+    }
+    editor.history.seal();
+    editor.message = format!("Restored {}", items[index]);
+}
+
+fn search(editor: &mut Editor, args: &[&str]) {
+    let mut options = ReplaceOptions::default();
+    let mut words = vec![];
+    for arg in args {
+        match *arg {
+            "-i" => options.ignore_case = true,
+            "-r" => options.regex = true,
+            _ => words.push(*arg),
+        }
+    }
+
+    match words[..] {
+        [] if editor.search.is_some() => {}
+        [pattern] => match Matcher::new(pattern, options) {
+            Ok(matcher) => editor.search = Some((pattern.to_string(), matcher)),
+            Err(err) => {
+                editor.message = format!("Invalid pattern: {}", err);
+                return;
+            }
+        },
+        _ => {
+            editor.message = "usage: search [-i] [-r] [pattern]".to_string();
+            return;
+        }
+    }
+
+    let Some((pattern, matcher)) = &editor.search else { return };
+    let count = editor.buffer.len();
+
+    // The first match after the cursor, wrapping around to the start of the buffer
+    for step in 0..=count {
+        let line = (editor.line + step) % count;
+        let chars = editor.buffer[line].chars().collect::<Vec<char>>();
+        let found = matcher.find_all(&chars).iter().filter_map(|captures| captures[0]).map(|(start, _)| start)
+            .find(|start| step > 0 || *start > editor.column);
+
+        if let Some(column) = found {
+            let wrapped = step == count || line < editor.line;
+            editor.message = format!("/{}{}", pattern, if wrapped { " (wrapped)" } else { "" });
+            editor.goto((line, column));
+            return;
+        }
+    }
+
+    editor.message = format!("Pattern not found: {}", pattern);
+}
+
+fn cursors(editor: &mut Editor) {
+    let Some((pattern, matcher)) = &editor.search else {
+        editor.message = "cursors: search for a pattern first".to_string();
+        return;
+    };
+
+    let range = editor.selection_range();
+    let (first, last) = range.map_or((0, editor.buffer.len() - 1), |(start, end)| (start.0, end.0));
+
+    let mut positions = vec![];
+    for line in first..=last {
+        let chars = editor.buffer[line].chars().collect::<Vec<char>>();
+        for (start, end) in matcher.find_all(&chars).iter().filter_map(|captures| captures[0]) {
+            let inside = range.is_none_or(|(from, to)| (line, start) >= from && (line, end) <= to);
+            if inside {
+                positions.push((line, start));
+            }
+        }
+    }
+
+    let Some(first_match) = positions.first().copied() else {
+        editor.message = format!("Pattern not found: {}", pattern);
+        return;
+    };
+
+    editor.selection = None;
+    editor.goto(first_match);
+    editor.cursors = positions[1..].to_vec();
+    editor.message = format!("{} cursors, type to edit them all, Esc to stop", positions.len());
+}
+
+fn goto(editor: &mut Editor, args: &[&str]) {
+    let position = match args[..] {
+        [flag @ ("-b" | "-c"), number] => offset::parse_number(number)
+            .map(|number| offset::position(&editor.buffer, number, flag == "-b", editor.line_ending, editor.encoding)),
+        [target] => {
+            let (line, column) = target.split_once(':').unwrap_or((target, "1"));
+            match (line.parse::<usize>(), column.parse::<usize>()) {
+                (Ok(line), Ok(column)) => Some((line.saturating_sub(1), column.saturating_sub(1))),
+                _ => None,
+            }
+        }
+        _ => None,
+    };
+
+    match position {
+        Some(position) => editor.goto(position),
+        None => editor.message = "usage: goto line[:column] | -b offset | -c offset".to_string(),
+    }
+}
+
+fn fold(editor: &mut Editor, args: &[&str]) {
+    let regions = folds::regions(&editor.buffer);
+
+    match args[..] {
+        // The block the cursor heads, or else the innermost one around it
+        [] => match regions.iter().rev().find(|(start, end, _)| (*start..=*end).contains(&editor.line)) {
+            Some((start, _, _)) if editor.folds.is_closed(*start) => editor.folds.open(*start),
+            Some((start, _, _)) => {
+                editor.folds.close(*start);
+                editor.goto((*start, editor.column));
+            }
+            None => editor.message = "Nothing to fold here".to_string(),
+        },
+        ["all"] => fold_level(editor, &regions, 0),
+        ["level", level] if let Ok(level) = level.parse::<usize>() => fold_level(editor, &regions, level),
+        _ => editor.message = "usage: fold [all | level n]".to_string(),
+    }
+}
+
+// Closes every fold at least `level` deep, so `level` levels of nesting stay visible
+fn fold_level(editor: &mut Editor, regions: &[(usize, usize, usize)], level: usize) {
+    editor.folds.clear();
+    for (start, _, depth) in regions {
+        if *depth >= level {
+            editor.folds.close(*start);
+        }
+    }
+
+    // Keep the cursor on a line that is still visible
+    let cursor = regions.iter().filter(|(start, end, depth)| *depth >= level && *start < editor.line && editor.line <= *end).map(|(start, _, _)| *start).min();
+    if let Some(header) = cursor {
+        editor.goto((header, editor.column));
+    }
+    editor.message = format!("Folded {} blocks", regions.iter().filter(|(_, _, depth)| *depth >= level).count());
+}
@@ -0,0 +1,26 @@
+use std::env;
+
+const DEFAULT_PATTERNS: [&str; 7] = ["password", "passwd", "secret", "token", "api_key", "apikey", "private_key"];
+
+// Patterns come from $TINYEDITOR_CONCEAL as a comma separated list, matched case-insensitively
+pub fn patterns() -> Vec<String> {
+    match env::var("TINYEDITOR_CONCEAL") {
+        Ok(patterns) if !patterns.trim().is_empty() => patterns.split(',').map(|pattern| pattern.trim().to_lowercase()).filter(|pattern| !pattern.is_empty()).collect(),
+        _ => DEFAULT_PATTERNS.iter().map(|pattern| pattern.to_string()).collect(),
+    }
+}
+
+// Masks the value of a matching line (everything after the first '=' or ':' past the match), or the whole
+// line if there is no separator. The masked line has the same number of chars so the cursor stays aligned
+pub fn conceal_line(line: &str, patterns: &[String]) -> Option<String> {
+    let lowercase = line.to_lowercase();
+    let found = patterns.iter().filter_map(|pattern| lowercase.find(pattern.as_str()).map(|idx| idx + pattern.len())).min()?;
+
+    let start = if lowercase.len() == line.len() {
+        line[found..].find(['=', ':']).map(|idx| line[..found + idx + 1].chars().count())
+    } else {
+        None
+    }.unwrap_or(line.chars().take_while(|char| char.is_whitespace()).count());
+
+    Some(line.chars().enumerate().map(|(idx, char)| if idx < start || char.is_whitespace() { char } else { '*' }).collect())
+}
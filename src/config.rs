@@ -0,0 +1,350 @@
+use std::{collections::HashMap, fmt, fs, path::{Path, PathBuf}, time::SystemTime};
+
+use crate::{backup, clipboard, keymap::{self, Action}, paths, vars::Vars};
+
+pub const PROJECT_CONFIG_NAME: &str = ".tinyeditor.toml";
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    String(String),
+    Integer(i64),
+    Boolean(bool),
+    Array(Vec<Value>),
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::String(string) => write!(f, "{}", string),
+            Value::Integer(integer) => write!(f, "{}", integer),
+            Value::Boolean(boolean) => write!(f, "{}", boolean),
+            Value::Array(values) => write!(f, "[{}]", values.iter().map(Value::to_string).collect::<Vec<String>>().join(", ")),
+        }
+    }
+}
+
+// A small subset of TOML: `key = value` pairs, [table] headers, # comments, and string, integer, boolean,
+// and single-line array values. Keys inside a table are returned as "table.key"
+pub fn parse(text: &str) -> Result<HashMap<String, Value>, String> {
+    let mut values = HashMap::new();
+    let mut table = String::new();
+
+    for (index, raw_line) in text.lines().enumerate() {
+        let line = strip_comment(raw_line).trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        if let Some(name) = line.strip_prefix('[') {
+            let Some(name) = name.strip_suffix(']') else {
+                return Err(format!("line {}: unterminated table header", index + 1));
+            };
+            table = name.trim().to_string();
+            continue;
+        }
+
+        let Some((key, value)) = line.split_once('=') else {
+            return Err(format!("line {}: expected `key = value`", index + 1));
+        };
+
+        let key = key.trim().trim_matches('"');
+        if key.is_empty() {
+            return Err(format!("line {}: missing key", index + 1));
+        }
+
+        let value = parse_value(value.trim()).map_err(|err| format!("line {}: {}", index + 1, err))?;
+        let full_key = if table.is_empty() { key.to_string() } else { format!("{}.{}", table, key) };
+        values.insert(full_key, value);
+    }
+
+    Ok(values)
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut in_string: Option<char> = None;
+    let mut escaped = false;
+
+    for (idx, char) in line.char_indices() {
+        match in_string {
+            Some('"') if escaped => escaped = false,
+            Some('"') if char == '\\' => escaped = true,
+            Some(quote) if char == quote => in_string = None,
+            Some(_) => {}
+            None if char == '"' || char == '\'' => in_string = Some(char),
+            None if char == '#' => return &line[..idx],
+            None => {}
+        }
+    }
+
+    line
+}
+
+fn parse_value(value: &str) -> Result<Value, String> {
+    if let Some(inner) = value.strip_prefix('[') {
+        let Some(inner) = inner.strip_suffix(']') else {
+            return Err("unterminated array".to_string());
+        };
+        return split_array(inner)?.iter().map(|item| parse_value(item)).collect::<Result<Vec<Value>, String>>().map(Value::Array);
+    }
+
+    if let Some(inner) = value.strip_prefix('"') {
+        let Some(inner) = inner.strip_suffix('"') else {
+            return Err("unterminated string".to_string());
+        };
+        return unescape(inner).map(Value::String);
+    }
+
+    if let Some(inner) = value.strip_prefix('\'') {
+        let Some(inner) = inner.strip_suffix('\'') else {
+            return Err("unterminated string".to_string());
+        };
+        return Ok(Value::String(inner.to_string()));
+    }
+
+    match value {
+        "true" => Ok(Value::Boolean(true)),
+        "false" => Ok(Value::Boolean(false)),
+        _ => value.replace('_', "").parse::<i64>().map(Value::Integer).map_err(|_| format!("invalid value `{}`", value)),
+    }
+}
+
+fn split_array(inner: &str) -> Result<Vec<String>, String> {
+    let mut items = vec![];
+    let mut current = String::new();
+    let mut in_string: Option<char> = None;
+    let mut escaped = false;
+
+    for char in inner.chars() {
+        match in_string {
+            Some('"') if escaped => escaped = false,
+            Some('"') if char == '\\' => escaped = true,
+            Some(quote) if char == quote => in_string = None,
+            Some(_) => {}
+            None if char == '"' || char == '\'' => in_string = Some(char),
+            None if char == ',' => {
+                items.push(current.trim().to_string());
+                current.clear();
+                continue;
+            }
+            None => {}
+        }
+        current.push(char);
+    }
+
+    if in_string.is_some() {
+        return Err("unterminated string in array".to_string());
+    }
+
+    if !current.trim().is_empty() {
+        items.push(current.trim().to_string());
+    }
+
+    Ok(items)
+}
+
+fn unescape(string: &str) -> Result<String, String> {
+    let mut out = String::new();
+    let mut chars = string.chars();
+
+    while let Some(char) = chars.next() {
+        if char != '\\' {
+            out.push(char);
+            continue;
+        }
+
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            Some('r') => out.push('\r'),
+            Some('"') => out.push('"'),
+            Some('\\') => out.push('\\'),
+            Some('u') => {
+                let hex = chars.by_ref().take(4).collect::<String>();
+                let code = u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32).ok_or(format!("invalid escape \\u{}", hex))?;
+                out.push(code);
+            }
+            other => return Err(format!("invalid escape \\{}", other.map(String::from).unwrap_or_default())),
+        }
+    }
+
+    Ok(out)
+}
+
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub indent_width: usize,
+    pub indent_with_tabs: bool,
+    pub smart_indent: bool, // Enter after a line opening a block, e.g. ending in `{`, indents one level deeper
+    pub final_newline: bool, // Saving adds a line break at the end of a file without one
+    pub formatter: Option<String>,
+    pub build_command: Option<String>,
+    pub on_open: Option<String>,
+    pub after_save: Vec<String>, // Commands run after each save
+    pub digraphs: HashMap<String, String>,
+    pub keys: HashMap<String, Vec<String>>, // Action name to the keys bound to it, from the [keys] table
+    pub physical_keys: HashMap<String, Vec<String>>, // The same by where keys are on the keyboard, from [physical_keys]
+    pub undo_steps: usize,
+    pub undo_memory: usize, // Bytes
+    pub zen_width: usize,
+    pub typewriter_scrolling: bool, // Keep the cursor line vertically centered
+    pub scroll_margin: usize, // Lines kept visible above and below the cursor
+    pub line_numbers: bool, // Whether panes start with line numbers on
+    pub wrap: bool, // Whether panes start with long lines wrapped
+    pub arrows_by_row: bool, // Whether up and down move by screen row through wrapped lines, with alt for a whole line
+    pub show_invisibles: bool, // Whether panes start with zero-width chars and soft hyphens shown as placeholders
+    pub autosave_interval: u64, // Seconds between saves of a scratch buffer, 0 to not autosave
+    pub large_file_size: u64, // Bytes; bigger files ask before being loaded
+    pub theme: String, // "terminal", a base16 file, or empty for the terminal's default colours
+    pub status_clock: bool, // The time in the focused pane's status bar
+    pub status_battery: bool, // The battery charge in the focused pane's status bar
+    pub mouse: bool, // Clicks and drags go to the editor rather than the terminal's own selection
+    pub clipboard: String, // "auto", "osc52", or a clipboard program: "wl-copy", "xclip" or "pbcopy"
+    pub task_bell: bool, // Ring the terminal bell when a long background task finishes
+    pub backup: String, // "off", "tilde" for file~, or "directory" for timestamped copies, see backup
+    pub filetypes: HashMap<String, Vars>, // Buffer variables for each filetype, from [filetype.name] tables
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            indent_width: 4,
+            indent_with_tabs: false,
+            smart_indent: true,
+            final_newline: false,
+            formatter: None,
+            build_command: None,
+            on_open: None,
+            after_save: vec![],
+            digraphs: HashMap::new(),
+            keys: HashMap::new(),
+            physical_keys: HashMap::new(),
+            undo_steps: 10_000,
+            undo_memory: 64 * 1024 * 1024,
+            zen_width: 80,
+            typewriter_scrolling: false,
+            scroll_margin: 8,
+            line_numbers: false,
+            wrap: false,
+            arrows_by_row: true,
+            show_invisibles: true,
+            autosave_interval: 5,
+            large_file_size: 50 * 1024 * 1024,
+            theme: String::new(),
+            status_clock: false,
+            status_battery: false,
+            mouse: true,
+            clipboard: "auto".to_string(),
+            task_bell: false,
+            backup: "off".to_string(),
+            filetypes: HashMap::new(),
+        }
+    }
+}
+
+impl Config {
+    // Applies the settings from a parsed file over the current ones. Unknown keys are ignored so
+    // newer config files keep working with older builds. Every valid key is applied even when others are invalid,
+    // in the order of their names so the result doesn't depend on the map's, and the errors are reported together
+    pub fn merge(&mut self, values: &HashMap<String, Value>) -> Result<(), String> {
+        let mut keys = values.keys().collect::<Vec<&String>>();
+        keys.sort();
+        let errors = keys.into_iter().filter_map(|key| self.merge_value(key, &values[key]).err()).collect::<Vec<String>>();
+        if errors.is_empty() { Ok(()) } else { Err(errors.join("; ")) }
+    }
+
+    fn merge_value(&mut self, key: &str, value: &Value) -> Result<(), String> {
+        match (key, value) {
+            ("indent_width", Value::Integer(width)) if *width > 0 => self.indent_width = *width as usize,
+            ("undo_steps", Value::Integer(steps)) if *steps > 0 => self.undo_steps = *steps as usize,
+            ("undo_memory", Value::Integer(bytes)) if *bytes > 0 => self.undo_memory = *bytes as usize,
+            ("zen_width", Value::Integer(width)) if *width > 0 => self.zen_width = *width as usize,
+            ("scroll_margin", Value::Integer(lines)) if *lines >= 0 => self.scroll_margin = *lines as usize,
+            ("autosave_interval", Value::Integer(secs)) if *secs >= 0 => self.autosave_interval = *secs as u64,
+            ("large_file_size", Value::Integer(bytes)) if *bytes > 0 => self.large_file_size = *bytes as u64,
+            ("indent_with_tabs", Value::Boolean(tabs)) => self.indent_with_tabs = *tabs,
+            ("smart_indent", Value::Boolean(smart)) => self.smart_indent = *smart,
+            ("final_newline", Value::Boolean(newline)) => self.final_newline = *newline,
+            ("line_numbers", Value::Boolean(numbers)) => self.line_numbers = *numbers,
+            ("wrap", Value::Boolean(wrap)) => self.wrap = *wrap,
+            ("arrows_by_row", Value::Boolean(by_row)) => self.arrows_by_row = *by_row,
+            ("show_invisibles", Value::Boolean(invisibles)) => self.show_invisibles = *invisibles,
+            ("typewriter_scrolling", Value::Boolean(typewriter)) => self.typewriter_scrolling = *typewriter,
+            ("status_clock", Value::Boolean(clock)) => self.status_clock = *clock,
+            ("status_battery", Value::Boolean(battery)) => self.status_battery = *battery,
+            ("mouse", Value::Boolean(mouse)) => self.mouse = *mouse,
+            ("task_bell", Value::Boolean(bell)) => self.task_bell = *bell,
+            ("clipboard", Value::String(clipboard)) if clipboard::is_known(clipboard) => self.clipboard = clipboard.clone(),
+            ("backup", Value::String(backup)) if backup::is_known(backup) => self.backup = backup.clone(),
+            ("formatter", Value::String(command)) => self.formatter = Some(command.clone()).filter(|command| !command.is_empty()),
+            ("build_command", Value::String(command)) => self.build_command = Some(command.clone()).filter(|command| !command.is_empty()),
+            ("on_open", Value::String(command)) => self.on_open = Some(command.clone()).filter(|command| !command.is_empty()),
+            ("theme", Value::String(theme)) => self.theme = theme.clone(),
+            ("after_save", Value::String(command)) => self.after_save = vec![command.clone()],
+            ("after_save", Value::Array(commands)) if commands.iter().all(|command| matches!(command, Value::String(_))) => {
+                self.after_save = commands.iter().filter_map(|command| match command {
+                    Value::String(command) => Some(command.clone()),
+                    _ => None,
+                }).collect();
+            }
+            (key, Value::String(text)) if let Some(digraph) = key.strip_prefix("digraphs.") && digraph.chars().count() == 2 => {
+                self.digraphs.insert(digraph.to_string(), text.clone());
+            }
+            ("indent_width" | "indent_with_tabs" | "smart_indent" | "final_newline" | "formatter" | "build_command" | "on_open" | "after_save" | "undo_steps" | "undo_memory" | "zen_width" | "typewriter_scrolling"
+                | "scroll_margin" | "line_numbers" | "wrap" | "arrows_by_row" | "show_invisibles" | "autosave_interval" | "large_file_size" | "theme"
+                | "status_clock" | "status_battery" | "mouse" | "clipboard" | "task_bell" | "backup", _) => return Err(format!("invalid value for `{}`", key)),
+            (key, value) if let Some((table, action)) = key.split_once('.') && matches!(table, "keys" | "physical_keys") => {
+                let keys = match value {
+                    Value::String(key) => vec![key.clone()],
+                    Value::Array(keys) => keys.iter().map(|key| match key {
+                        Value::String(key) => Ok(key.clone()),
+                        _ => Err(format!("keys for `{}` must be strings", action)),
+                    }).collect::<Result<Vec<String>, String>>()?,
+                    _ => return Err(format!("keys for `{}` must be a string or an array of strings", action)),
+                };
+                if Action::parse(action).is_none() {
+                    return Err(format!("unknown action `{}`", action));
+                }
+                if let Some(bad) = keys.iter().find(|key| keymap::parse_key(key).is_none()) {
+                    return Err(format!("unknown key `{}` for `{}`", bad, action));
+                }
+                let bindings = if table == "keys" { &mut self.keys } else { &mut self.physical_keys };
+                bindings.insert(action.to_string(), keys);
+            }
+            (key, value) if let Some((filetype, name)) = key.strip_prefix("filetype.").and_then(|rest| rest.split_once('.')) => {
+                self.filetypes.entry(filetype.to_string()).or_default().set(name, value.clone()).map_err(|err| format!("[filetype.{}]: {}", filetype, err))?;
+            }
+            (key, _) if key.starts_with("digraphs.") => return Err(format!("digraph `{}` must be two chars mapped to a string", key)),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    pub fn merge_file(&mut self, path: &Path) -> Result<(), String> {
+        let text = fs::read_to_string(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+        let values = parse(&text).map_err(|err| format!("{}: {}", path.display(), err))?;
+        self.merge(&values).map_err(|err| format!("{}: {}", path.display(), err))
+    }
+}
+
+// The user's own config, ~/.config/tinyeditor/config.toml, which project config is applied over
+pub fn global_config_path() -> Option<PathBuf> {
+    paths::config_dir().map(|dir| dir.join("config.toml"))
+}
+
+// When a config file was last changed, or None if it doesn't exist
+pub fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+// Builds the config again from the defaults and `files` in order, as when one of them changes. Errors are returned
+// with whatever could be applied
+pub fn reload(files: &[PathBuf]) -> (Config, Option<String>) {
+    let mut config = Config::default();
+    let errors = files.iter().filter(|path| path.is_file()).filter_map(|path| config.merge_file(path).err()).collect::<Vec<String>>();
+    (config, (!errors.is_empty()).then(|| errors.join("; ")))
+}
+
+// Walks up from `start` looking for a .tinyeditor.toml, returning the directory it was found in
+pub fn find_project_root(start: &Path) -> Option<PathBuf> {
+    start.ancestors().find(|dir| dir.join(PROJECT_CONFIG_NAME).is_file()).map(Path::to_path_buf)
+}
@@ -0,0 +1,116 @@
+use crate::{editor::Editor, text::TextBuffer};
+
+// Git's conflict markers, each seven chars at the start of a line. The base section only appears with
+// merge.conflictStyle = diff3 or zdiff3
+const OURS: &str = "<<<<<<<";
+const BASE: &str = "|||||||";
+const SEPARATOR: &str = "=======";
+const THEIRS: &str = ">>>>>>>";
+
+// Whether a line is a conflict marker, for highlighting
+pub fn is_marker(line: &str) -> bool {
+    [OURS, BASE, SEPARATOR, THEIRS].iter().any(|marker| {
+        line.strip_prefix(marker).is_some_and(|rest| rest.is_empty() || rest.starts_with(' ') || (*marker == SEPARATOR && rest.trim().is_empty()))
+    })
+}
+
+// A conflict's marker lines. Ours runs from start to base (or separator), theirs from separator to end
+pub struct Conflict {
+    pub start: usize,
+    pub base: Option<usize>,
+    pub separator: usize,
+    pub end: usize,
+}
+
+// Every complete conflict in the buffer, in order. Stray markers outside a full set are left alone
+pub fn find(buffer: &impl TextBuffer) -> Vec<Conflict> {
+    let mut conflicts = vec![];
+    let mut open: Option<(usize, Option<usize>, Option<usize>)> = None;
+    for (index, line) in buffer.iter().enumerate() {
+        if !is_marker(line) {
+            continue;
+        }
+        open = match (&line[..OURS.len()], open) {
+            (OURS, _) => Some((index, None, None)),
+            (BASE, Some((start, None, None))) => Some((start, Some(index), None)),
+            (SEPARATOR, Some((start, base, None))) => Some((start, base, Some(index))),
+            (THEIRS, Some((start, base, Some(separator)))) => {
+                conflicts.push(Conflict { start, base, separator, end: index });
+                None
+            }
+            (_, open) => open,
+        };
+    }
+    conflicts
+}
+
+// The lines a conflict is replaced with when resolved by taking one side, or both with ours first
+fn resolution(buffer: &impl TextBuffer, conflict: &Conflict, side: &str) -> Vec<String> {
+    let ours = buffer.lines(conflict.start + 1..conflict.base.unwrap_or(conflict.separator));
+    let theirs = buffer.lines(conflict.separator + 1..conflict.end);
+    match side {
+        "ours" => ours.cloned().collect(),
+        "theirs" => theirs.cloned().collect(),
+        _ => ours.chain(theirs).cloned().collect(),
+    }
+}
+
+// Jumps to the next or previous conflict, wrapping around the buffer
+fn jump(editor: &mut Editor, forward: bool) {
+    let conflicts = find(&editor.buffer);
+    let starts = conflicts.iter().map(|conflict| conflict.start).collect::<Vec<usize>>();
+    let target = if forward {
+        starts.iter().find(|start| **start > editor.line).or(starts.first())
+    } else {
+        starts.iter().rev().find(|start| **start < editor.line).or(starts.last())
+    };
+    match target {
+        Some(line) => {
+            let number = starts.iter().position(|start| start == line).unwrap_or(0) + 1;
+            editor.goto((*line, 0));
+            editor.message = format!("Conflict {} of {}", number, starts.len());
+        }
+        None => editor.message = "No conflicts".to_string(),
+    }
+}
+
+// The conflict command: with no arguments, how many conflicts there are; next and prev move between them; ours,
+// theirs and both resolve the one the cursor is in as a single undoable change
+pub fn command(editor: &mut Editor, args: &[&str]) {
+    match args[..] {
+        [] => {
+            let count = find(&editor.buffer).len();
+            editor.message = if count == 0 { "No conflicts".to_string() } else { format!("{} conflict{}", count, if count == 1 { "" } else { "s" }) };
+        }
+        ["next"] => jump(editor, true),
+        ["prev"] => jump(editor, false),
+        [side @ ("ours" | "theirs" | "both")] => {
+            let Some(conflict) = find(&editor.buffer).into_iter().find(|conflict| (conflict.start..=conflict.end).contains(&editor.line)) else {
+                editor.message = "The cursor isn't in a conflict".to_string();
+                return;
+            };
+            let mut lines = resolution(&editor.buffer, &conflict, side);
+            // The buffer always has a line
+            if lines.is_empty() && conflict.end - conflict.start + 1 == editor.buffer.len() {
+                lines.push(String::new());
+            }
+            let remaining = editor.buffer.len() - (conflict.end - conflict.start + 1) + lines.len();
+            let line = conflict.start.min(remaining - 1);
+            let left = find(&editor.buffer).len() - 1;
+            editor.selection = None;
+            editor.history.seal();
+            editor.replace_lines(conflict.start, conflict.end - conflict.start + 1, lines, (line, 0));
+            editor.history.seal();
+            editor.message = format!("Took {}, {} conflict{} left", side, left, if left == 1 { "" } else { "s" });
+        }
+        _ => editor.message = "usage: conflict [next|prev|ours|theirs|both]".to_string(),
+    }
+}
+
+// Points out conflicts in a file just opened
+pub fn announce(editor: &mut Editor) {
+    let count = find(&editor.buffer).len();
+    if count > 0 {
+        editor.message = format!("{} merge conflict{}, see the conflict command", count, if count == 1 { "" } else { "s" });
+    }
+}
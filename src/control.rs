@@ -0,0 +1,175 @@
+use std::{ffi::CString, fs, io::{self, BufRead, BufReader, Write}, os::unix::{ffi::OsStrExt, fs::FileTypeExt, net::{UnixListener, UnixStream}}, path::{Path, PathBuf}, sync::mpsc, thread};
+
+use crate::{commands, editor::Editor, event_loop, paths, text::TextBuffer, vars};
+
+// Commands other programs can send a running editor over its control socket, one per line. Each gets one line
+// back: "ok", or "error: " and why. They can also be written to the pipe --fifo makes, which has no replies
+pub const COMMANDS: &[(&str, &str)] = &[
+    ("open path [line[:column]]", "Open a file, at a line; relative paths are from the editor's working directory"),
+    ("goto line[:column]", "Move the cursor in the focused buffer, e.g. to where a debugger stopped"),
+    ("highlight first[-last] | clear", "Highlight lines of the focused buffer, e.g. the one a debugger is on, or stop highlighting"),
+    ("reload", "Read the focused buffer's file again, as a change undo can take back"),
+    ("save-all", "Save every open buffer with unsaved changes"),
+    ("set name value", "Set a variable on the focused buffer, see the set command"),
+];
+
+// Where --listen puts the socket unless given a path
+pub fn default_path() -> Option<PathBuf> {
+    paths::runtime_dir().map(|dir| dir.join("control.sock"))
+}
+
+// Starts accepting connections on a thread of its own. A socket left behind by an editor that crashed is replaced,
+// one another editor is still listening on is not
+pub fn listen(path: &Path) -> io::Result<()> {
+    if UnixStream::connect(path).is_ok() {
+        return Err(io::Error::new(io::ErrorKind::AddrInUse, "another editor is listening on it"));
+    }
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let _ = fs::remove_file(path);
+    let listener = UnixListener::bind(path)?;
+
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            thread::spawn(move || serve(stream));
+        }
+    });
+    Ok(())
+}
+
+// Makes a named pipe that other programs write commands to, for --fifo, and reads it on a thread of its own. A
+// debugger or test runner can then `echo goto 12 > pipe` without a socket. Errors show in the message row
+pub fn listen_fifo(path: &Path) -> io::Result<()> {
+    match fs::metadata(path) {
+        Ok(metadata) if metadata.file_type().is_fifo() => {}
+        Ok(_) => return Err(io::Error::new(io::ErrorKind::AlreadyExists, "it exists and isn't a named pipe")),
+        Err(_) => {
+            let c_path = CString::new(path.as_os_str().as_bytes()).map_err(io::Error::other)?;
+            if unsafe { libc::mkfifo(c_path.as_ptr(), 0o600) } != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+    }
+
+    let path = path.to_path_buf();
+    thread::spawn(move || {
+        // Each writer closing the pipe ends the file, so it is opened again for the next one
+        while let Ok(file) = fs::File::open(&path) {
+            for line in BufReader::new(file).lines() {
+                let Ok(line) = line else { break };
+                if line.trim().is_empty() {
+                    continue;
+                }
+                event_loop::send(Box::new(move |editor: &mut Editor| {
+                    if let Err(err) = run(editor, &line) {
+                        editor.message = err;
+                    }
+                }));
+            }
+        }
+    });
+    Ok(())
+}
+
+// Runs each command on the main thread, since that is where the editor lives, and waits for it to finish
+fn serve(stream: UnixStream) {
+    let Ok(mut writer) = stream.try_clone() else { return };
+    for line in BufReader::new(stream).lines() {
+        let Ok(line) = line else { return };
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let (sender, reply) = mpsc::channel();
+        event_loop::send(Box::new(move |editor: &mut Editor| {
+            let _ = sender.send(run(editor, &line));
+        }));
+        let reply = match reply.recv() {
+            Ok(Ok(())) => "ok".to_string(),
+            Ok(Err(err)) => format!("error: {}", err),
+            Err(_) => return,
+        };
+        if writeln!(writer, "{}", reply).is_err() {
+            return;
+        }
+    }
+}
+
+// Asks the editor listening on the socket to open files, for --remote. None when no editor is listening there
+pub fn hand_off(socket: &Path, paths: &[PathBuf]) -> Option<Result<(), String>> {
+    let stream = UnixStream::connect(socket).ok()?;
+    let mut writer = stream.try_clone().ok()?;
+    let mut replies = BufReader::new(stream).lines();
+    for path in paths {
+        // Quoted the way split_words reads it, so paths with spaces survive
+        let quoted = path.to_string_lossy().chars().flat_map(|char| {
+            let escape = char.is_whitespace() || char == '"' || char == '\'';
+            escape.then_some('\\').into_iter().chain([char])
+        }).collect::<String>();
+        if let Err(err) = writeln!(writer, "open {}", quoted) {
+            return Some(Err(err.to_string()));
+        }
+        match replies.next() {
+            Some(Ok(reply)) if reply == "ok" => {}
+            Some(Ok(reply)) => return Some(Err(reply.strip_prefix("error: ").unwrap_or(&reply).to_string())),
+            Some(Err(err)) => return Some(Err(err.to_string())),
+            None => return Some(Err("the editor closed the connection".to_string())),
+        }
+    }
+    Some(Ok(()))
+}
+
+fn run(editor: &mut Editor, command: &str) -> Result<(), String> {
+    let words = commands::split_words(command)?;
+    match words.iter().map(String::as_str).collect::<Vec<&str>>()[..] {
+        ["open", path] => open(editor, Path::new(path), None),
+        ["open", path, position] => open(editor, Path::new(path), Some(parse_position(position)?)),
+        ["goto", position] => {
+            let (line, column) = parse_position(position)?;
+            editor.goto((line.min(editor.buffer.len() - 1), column));
+            Ok(())
+        }
+        ["highlight", "clear"] => {
+            editor.anchor = None;
+            Ok(())
+        }
+        ["highlight", range] => {
+            let parse = |number: &str| number.parse::<usize>().ok().filter(|number| *number > 0).ok_or(format!("invalid range '{}'", range));
+            let (first, last) = match range.split_once('-') {
+                Some((first, last)) => (parse(first)?, parse(last)?),
+                None => (parse(range)?, parse(range)?),
+            };
+            editor.anchor = Some((editor.path.clone(), first.min(last) - 1, first.max(last) - 1));
+            Ok(())
+        }
+        ["reload"] => editor.reload(),
+        ["set", name, ref value @ ..] if !value.is_empty() => editor.set_var(name, vars::parse(&value.join(" "))),
+        ["save-all"] => {
+            editor.each_document(|editor| {
+                if editor.unsaved {
+                    editor.save();
+                }
+            });
+            Ok(())
+        }
+        _ => Err(format!("unknown command '{}', expected one of: {}", command.trim(), COMMANDS.iter().map(|(usage, _)| *usage).collect::<Vec<&str>>().join(", "))),
+    }
+}
+
+// A line[:column] position counted from 1, as a zero-based line and column
+fn parse_position(position: &str) -> Result<(usize, usize), String> {
+    let mut parts = position.splitn(2, ':').map(|part| part.parse::<usize>().ok());
+    let line = parts.next().flatten().filter(|line| *line > 0).ok_or(format!("invalid line '{}'", position))?;
+    let column = parts.next().map_or(Some(1), |column| column.filter(|column| *column > 0)).ok_or(format!("invalid column '{}'", position))?;
+    Ok((line - 1, column - 1))
+}
+
+// Opens a file the way the file tree does, see Editor::show_file
+fn open(editor: &mut Editor, path: &Path, position: Option<(usize, usize)>) -> Result<(), String> {
+    editor.show_file(path)?;
+    if let Some(position) = position {
+        editor.goto(position);
+    }
+    Ok(())
+}
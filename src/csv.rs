@@ -0,0 +1,55 @@
+use crate::width;
+
+// Column mode for CSV and TSV files: fields line up under each other on screen, by drawing each separator as wide as
+// it takes to reach the start of the next column, see width::Stops. Nothing is added to the text itself. Only the
+// lines on screen are measured, so columns can shift as the view scrolls. Separators inside quoted fields aren't
+// told apart from the others
+
+// The gap between the widest field of a column and the next, counting the separator
+const GAP: usize = 2;
+
+// The separator of a filetype with columns
+pub fn separator(filetype: &str) -> Option<char> {
+    match filetype {
+        "csv" => Some(','),
+        "tsv" => Some('\t'),
+        _ => None,
+    }
+}
+
+// The cell each field after the first starts at, lining up the fields of all the lines given. Invisible chars
+// take a cell when their placeholders are shown
+pub fn starts<'a>(lines: impl Iterator<Item = &'a String>, separator: char, invisibles: bool) -> Vec<usize> {
+    let mut widths: Vec<usize> = vec![];
+    for line in lines {
+        for (index, field) in line.split(separator).enumerate() {
+            let width = width::text_width(field) + if invisibles { field.chars().filter(|char| width::is_invisible(*char)).count() } else { 0 };
+            match widths.get_mut(index) {
+                Some(widest) => *widest = (*widest).max(width),
+                None => widths.push(width),
+            }
+        }
+    }
+
+    let mut cell = 0;
+    widths.iter().take(widths.len().saturating_sub(1)).map(|width| {
+        cell += width + GAP;
+        cell
+    }).collect()
+}
+
+// Which field of a line a column is in
+pub fn field_at(line: &str, column: usize, separator: char) -> usize {
+    line.chars().take(column).filter(|char| *char == separator).count()
+}
+
+// The column the field after or before the one at `column` starts at, if there is one
+pub fn step(line: &str, column: usize, separator: char, forward: bool) -> Option<usize> {
+    let mut starts = std::iter::once(0).chain(line.chars().enumerate().filter(|(_, char)| *char == separator).map(|(index, _)| index + 1));
+    if forward {
+        starts.find(|start| *start > column)
+    } else {
+        let current = field_at(line, column, separator);
+        starts.nth(current.checked_sub(1)?)
+    }
+}
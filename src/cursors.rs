@@ -0,0 +1,36 @@
+use crate::{graphemes, text::TextBuffer};
+
+// Applies the same edit at several cursors at once: at each one, `delete` grapheme clusters before it are removed and
+// `insert` is typed. Edits never join lines, so a cursor at the start of a line deletes nothing. Returns the first
+// changed line, the new text of the lines from there to the last cursor, and where each cursor ends up
+pub fn edit(buffer: &impl TextBuffer, cursors: &[(usize, usize)], delete: usize, insert: &str) -> (usize, Vec<String>, Vec<(usize, usize)>) {
+    let first = cursors.iter().map(|cursor| cursor.0).min().unwrap_or(0);
+    let last = cursors.iter().map(|cursor| cursor.0).max().unwrap_or(0);
+    let mut lines = buffer.lines(first..=last).cloned().collect::<Vec<String>>();
+    let mut moved = cursors.to_vec();
+
+    for (offset, line) in lines.iter_mut().enumerate() {
+        let mut on_line = (0..cursors.len()).filter(|index| cursors[*index].0 == first + offset).collect::<Vec<usize>>();
+        if on_line.is_empty() {
+            continue;
+        }
+        on_line.sort_by_key(|index| cursors[*index].1);
+
+        let chars = line.chars().collect::<Vec<char>>();
+        let mut new_line = String::new();
+        let mut done = 0; // Chars of the old line already copied or deleted
+        for index in on_line {
+            let column = cursors[index].1.min(chars.len()).max(done);
+            let start = (0..delete).fold(column, |start, _| graphemes::previous(line, start));
+            new_line.extend(&chars[done..start.max(done)]);
+            new_line += insert;
+            done = column;
+            moved[index] = (first + offset, new_line.chars().count());
+        }
+        new_line.extend(&chars[done..]);
+        *line = new_line;
+    }
+
+    (first, lines, moved)
+}
+
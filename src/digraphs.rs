@@ -0,0 +1,32 @@
+use std::collections::HashMap;
+
+// Two-char mnemonics after RFC 1345, as in vim: the letter followed by a sign that looks like the accent
+const DIGRAPHS: &[(&str, char)] = &[
+    ("a'", 'á'), ("e'", 'é'), ("i'", 'í'), ("o'", 'ó'), ("u'", 'ú'), ("y'", 'ý'),
+    ("A'", 'Á'), ("E'", 'É'), ("I'", 'Í'), ("O'", 'Ó'), ("U'", 'Ú'), ("Y'", 'Ý'),
+    ("a!", 'à'), ("e!", 'è'), ("i!", 'ì'), ("o!", 'ò'), ("u!", 'ù'),
+    ("A!", 'À'), ("E!", 'È'), ("I!", 'Ì'), ("O!", 'Ò'), ("U!", 'Ù'),
+    ("a>", 'â'), ("e>", 'ê'), ("i>", 'î'), ("o>", 'ô'), ("u>", 'û'),
+    ("A>", 'Â'), ("E>", 'Ê'), ("I>", 'Î'), ("O>", 'Ô'), ("U>", 'Û'),
+    ("a:", 'ä'), ("e:", 'ë'), ("i:", 'ï'), ("o:", 'ö'), ("u:", 'ü'), ("y:", 'ÿ'),
+    ("A:", 'Ä'), ("E:", 'Ë'), ("I:", 'Ï'), ("O:", 'Ö'), ("U:", 'Ü'),
+    ("a?", 'ã'), ("o?", 'õ'), ("n?", 'ñ'), ("A?", 'Ã'), ("O?", 'Õ'), ("N?", 'Ñ'),
+    ("c,", 'ç'), ("C,", 'Ç'), ("aa", 'å'), ("AA", 'Å'), ("o/", 'ø'), ("O/", 'Ø'),
+    ("ae", 'æ'), ("AE", 'Æ'), ("oe", 'œ'), ("OE", 'Œ'), ("ss", 'ß'), ("th", 'þ'), ("TH", 'Þ'),
+    ("Eu", '€'), ("Pd", '£'), ("Ye", '¥'), ("Ct", '¢'), ("Co", '©'), ("Rg", '®'), ("TM", '™'),
+    ("SE", '§'), ("PI", '¶'), ("DG", '°'), ("My", 'µ'), ("+-", '±'), ("*X", '×'), ("-:", '÷'),
+    ("!I", '¡'), ("?I", '¿'), ("<<", '«'), (">>", '»'), ("12", '½'), ("14", '¼'), ("34", '¾'),
+    ("1S", '¹'), ("2S", '²'), ("3S", '³'), ("NS", '\u{a0}'), ("-N", '–'), ("-M", '—'), (",.", '…'),
+    ("'6", '‘'), ("'9", '’'), ("\"6", '“'), ("\"9", '”'),
+    ("->", '→'), ("<-", '←'), ("-!", '↑'), ("-v", '↓'), ("!=", '≠'), ("=<", '≤'), (">=", '≥'), ("OK", '✓'), ("XX", '✗'),
+    ("a*", 'α'), ("b*", 'β'), ("g*", 'γ'), ("d*", 'δ'), ("e*", 'ε'), ("l*", 'λ'), ("m*", 'μ'), ("p*", 'π'),
+    ("s*", 'σ'), ("t*", 'τ'), ("f*", 'φ'), ("W*", 'Ω'), ("D*", 'Δ'), ("S*", 'Σ'),
+];
+
+// User digraphs from the [digraphs] table of the config take priority, and either order of the two chars works
+pub fn lookup(user: &HashMap<String, String>, first: char, second: char) -> Option<String> {
+    let find = |digraph: String| user.get(&digraph).cloned()
+        .or_else(|| DIGRAPHS.iter().find(|(known, _)| *known == digraph).map(|(_, char)| char.to_string()));
+
+    find(format!("{}{}", first, second)).or_else(|| find(format!("{}{}", second, first)))
+}
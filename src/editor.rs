@@ -0,0 +1,2053 @@
+use std::{borrow::Cow, cell::{OnceCell, RefCell}, collections::{HashMap, VecDeque}, fs, io::{self, Write}, ops::Range, path::{Path, PathBuf}, time::{Duration, Instant, SystemTime}};
+
+use crate::{backup, clipboard, collab, commands, conceal, conflicts, csv, hooks, line_edit::LineEdit, motions::{self, CharFind}, mouse, cursors, digraphs, folds::{self, Folds}, offset, operations::{EditKind, Operation}, panes::{self, Document, Output, Rect, View}, replace::{self, Matcher}, metrics::Latency, swap, templates, trash::Trash, urls, encoding::{self, Encoding, LineEnding}, filetype, filters, gitcommit, graphemes, rebase, marks, config::{Config, Value}, selection::{self, SelectionStats}, executable::{self, ExecutablePolicy}, history::{Change, History}, input::{Key, KeyDecoder, Modifiers}, keymap::{Action, Keymap}, killring::{KillRing, Yank}, screen::Screen, terminal::{get_screen_size, set_cursor_pos, set_kitty_keys, set_mouse_reporting}, swatches, tasks::Tasks, text::{Lines, TextBuffer}, theme::{self, Rgb, Style, Theme}, tree::{self, FileTree}, vars::{self, Vars}, width::{self, Stops}, wrap};
+
+// Writes to a temporary file beside the target and renames it over the target once it is safely on disk, so a crash
+// or a full disk partway through leaves the old contents rather than a truncated file. A symlink is followed so the
+// file it points to is replaced rather than the link, and an existing file's permissions are kept
+pub fn write(file: PathBuf, bytes: &[u8]) -> std::io::Result<()> {
+    let file = fs::canonicalize(&file).unwrap_or(file);
+    let name = file.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+    let temp = file.with_file_name(format!(".{}.tinyeditor-{}.tmp", name, std::process::id()));
+
+    let result = (|| {
+        let mut out = fs::File::create(&temp)?;
+        out.write_all(bytes)?;
+        if let Ok(metadata) = fs::metadata(&file) {
+            out.set_permissions(metadata.permissions())?;
+        }
+        out.sync_all()?;
+        fs::rename(&temp, &file)
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result
+}
+
+// The byte offset and char column of a column on a line, clamped to its end. An ASCII prefix, as in minified
+// JSON, is counted without decoding it
+pub fn byte_index(line: &str, column: usize) -> (usize, usize) {
+    let prefix = column.min(line.len());
+    if line.as_bytes()[..prefix].is_ascii() {
+        return (prefix, prefix);
+    }
+
+    match line.char_indices().nth(column) {
+        Some((byte, _)) => (byte, column),
+        None => (line.len(), line.chars().count()),
+    }
+}
+
+// How many lines the message log keeps
+const LOG_LINES: usize = 1000;
+
+// How many keys the screencast overlay shows, and for how long after they were pressed
+const RECENT_KEYS: usize = 8;
+pub const RECENT_KEYS_TIMEOUT: Duration = Duration::from_secs(3);
+
+pub struct Editor {
+    pub buffer: Lines,
+    pub path: PathBuf,
+    pub scratch: bool,
+    pub line: usize,
+    pub column: usize,
+    pub start_line: usize,
+    pub unsaved: bool,
+    pub last_autosave: Instant,
+    pub message: String,
+    pub quit: bool,
+    pub conceal: bool,
+    pub conceal_patterns: OnceCell<Vec<String>>, // Read on first use
+    pub executable_policy: ExecutablePolicy,
+    pub executable_checked: bool,
+    pub config: Config,
+    pub project_root: Option<PathBuf>,
+    pub keys: KeyDecoder,
+    pub kitty_keys: bool, // Whether the terminal was asked for kitty protocol keys, for physical key bindings
+    pub keymap: Keymap,
+    pub history: History,
+    pub show_keys: bool,
+    pub recent_keys: VecDeque<(String, Instant)>,
+    pub selection: Option<(usize, usize)>, // The anchor; the selection runs from here to the cursor
+    pub selecting: bool, // Toggled on, so plain movement extends the selection like shift+arrows
+    pub marks: HashMap<char, (usize, usize)>, // Lowercase marks, which only last for the session
+    pub line_ending: LineEnding,
+    pub encoding: Encoding,
+    pub filetype: &'static str,
+    pub filters: Vec<&'static str>, // Enabled input filters
+    pub vars: Vars, // Set on this buffer, see var
+    pub trash: Trash,
+    pub kills: KillRing,
+    pub last_input: Instant,
+    pub swap_stale: bool, // The buffer changed since the swap file was last written
+    pub show_debug: bool,
+    pub latency: Latency,
+    pub tasks: Tasks,
+    pub anchor: Option<(PathBuf, usize, usize)>, // Lines highlighted from outside, see control, as a file and its first and last line
+    pub search: Option<(String, Matcher)>, // The last search pattern
+    pub cursors: Vec<(usize, usize)>, // Extra cursors that typing and backspace also apply at
+    pub session: Option<collab::Session>, // Sharing a buffer with other editors, see collab
+    pub tree: Option<FileTree>, // The file tree sidebar, when shown
+    screen: RefCell<Screen>, // What the last frame left on the terminal
+    cursor_rows: RefCell<Vec<Option<usize>>>, // Each pane's cursor row on the last frame, kept when panes change size
+    pub show_ruler: bool,
+    pub show_offset: bool, // Byte and char offsets in the status bar
+    pub folds: Folds,
+    pub zen: bool,
+    pub zen_dim: bool, // Dim everything but the paragraph being written
+    pub panes: Vec<View>, // By column from the left, then top to bottom. The focused pane's view is kept in line, column and start_line instead
+    pub focused: usize,
+    pub documents: Vec<Option<Document>>, // The buffers of the panes, None for the focused pane's which is in the fields above
+    pub output: Option<Output>, // Shown in a pane under the others
+    pub log: VecDeque<String>, // Output of hooks, shown with the messages command
+    pub arg_list: Vec<PathBuf>, // The files given on the command line
+    pub arg_index: usize, // Which of them is open
+    pub config_files: Vec<(PathBuf, Option<SystemTime>)>, // Config files in use, with when they were loaded
+    pub config_checked: Instant,
+    pub theme: Option<Theme>,
+    pub segments: String, // Clock and battery text for the status bar, updated by an idle task
+    pub segments_due: Instant,
+    pub mouse_anchor: Option<(usize, usize)>, // Where the left button went down, for selecting by dragging
+    pub last_find: Option<CharFind>, // For repeating character jumps
+}
+
+impl Editor {
+    pub fn new(buffer: Vec<String>, path: PathBuf, scratch: bool, config: Config, project_root: Option<PathBuf>) -> Editor {
+        let filetype = filetype::detect(&path, scratch);
+        let keymap = Keymap::with_config(&config.keys, &config.physical_keys, config.arrows_by_row);
+        Editor {
+            buffer: buffer.into(),
+            path,
+            scratch,
+            line: 0,
+            column: 0,
+            start_line: 0,
+            unsaved: false,
+            last_autosave: Instant::now(),
+            message: String::new(),
+            quit: false,
+            conceal: false,
+            conceal_patterns: OnceCell::new(),
+            executable_policy: executable::policy(),
+            executable_checked: false,
+            config,
+            project_root,
+            keys: KeyDecoder::default(),
+            kitty_keys: false,
+            keymap,
+            history: History::default(),
+            show_keys: false,
+            recent_keys: VecDeque::new(),
+            selection: None,
+            selecting: false,
+            marks: HashMap::new(),
+            line_ending: LineEnding::Lf,
+            encoding: Encoding::Utf8,
+            filetype,
+            filters: vec![],
+            vars: Vars::default(),
+            trash: Trash::default(),
+            kills: KillRing::default(),
+            last_input: Instant::now(),
+            swap_stale: false,
+            show_debug: false,
+            latency: Latency::default(),
+            tasks: Tasks::default(),
+            anchor: None,
+            search: None,
+            cursors: vec![],
+            session: None,
+            tree: None,
+            screen: RefCell::default(),
+            cursor_rows: RefCell::default(),
+            show_ruler: false,
+            show_offset: false,
+            folds: Folds::default(),
+            zen: false,
+            zen_dim: false,
+            panes: vec![View::default()],
+            focused: 0,
+            documents: vec![None],
+            output: None,
+            log: VecDeque::new(),
+            arg_list: vec![],
+            arg_index: 0,
+            theme: None,
+            segments: String::new(),
+            segments_due: Instant::now(),
+            mouse_anchor: None,
+            last_find: None,
+            config_files: vec![],
+            config_checked: Instant::now(),
+        }
+    }
+
+    // Moves the cursor, clamped to the buffer
+    pub fn goto(&mut self, (line, column): (usize, usize)) {
+        self.line = line.min(self.buffer.len() - 1);
+        self.column = column.min(self.buffer[self.line].chars().count());
+    }
+
+    // A buffer variable, see vars: set on this buffer, set for its filetype in the config, or the global setting
+    pub fn var(&self, name: &str) -> Option<Value> {
+        if name == "filetype" {
+            return Some(Value::String(self.filetype.to_string()));
+        }
+        if let Some(value) = self.vars.get(name).or_else(|| self.config.filetypes.get(self.filetype)?.get(name)) {
+            return Some(value.clone());
+        }
+
+        match name {
+            "indent_width" => Some(Value::Integer(self.config.indent_width as i64)),
+            "indent_with_tabs" => Some(Value::Boolean(self.config.indent_with_tabs)),
+            "smart_indent" => Some(Value::Boolean(self.config.smart_indent)),
+            "final_newline" => Some(Value::Boolean(self.config.final_newline)),
+            "rebase_keys" => Some(Value::Boolean(true)),
+            "align_columns" => Some(Value::Boolean(false)),
+            _ => None,
+        }
+    }
+
+    // Sets a buffer variable. The filetype is kept where everything else reads it, so it has to be one they know
+    pub fn set_var(&mut self, name: &str, value: Value) -> Result<(), String> {
+        match (name, &value) {
+            ("filetype", Value::String(filetype)) => {
+                self.filetype = filetype::find(filetype).ok_or(format!("unknown filetype `{}`", filetype))?;
+                Ok(())
+            }
+            _ => self.vars.set(name, value),
+        }
+    }
+
+    // Every variable with a value for this buffer, built-in ones first
+    pub fn all_vars(&self) -> Vec<(String, Value)> {
+        let mut names = vars::BUILTIN.iter().map(|(name, _, _)| name.to_string()).collect::<Vec<String>>();
+        for (name, _) in self.config.filetypes.get(self.filetype).into_iter().flat_map(Vars::iter).chain(self.vars.iter()) {
+            if !names.contains(name) {
+                names.push(name.clone());
+            }
+        }
+        names.into_iter().filter_map(|name| self.var(&name).map(|value| (name, value))).collect()
+    }
+
+    pub fn indent_width(&self) -> usize {
+        match self.var("indent_width") {
+            Some(Value::Integer(width)) => width as usize,
+            _ => self.config.indent_width,
+        }
+    }
+
+    pub fn indent_string(&self) -> String {
+        if self.var_is("indent_with_tabs") { "\t".to_string() } else { " ".repeat(self.indent_width()) }
+    }
+
+    // Where tabs stop in a pane, and separators too when its buffer is a CSV or TSV file with align_columns on
+    pub fn stops(&self, view: View) -> Stops {
+        let (buffer, _, document) = self.pane_buffer(view);
+        let (filetype, vars) = document.map_or((self.filetype, &self.vars), |document| (document.filetype, &document.vars));
+        let aligned = vars.get("align_columns").or_else(|| self.config.filetypes.get(filetype)?.get("align_columns")) == Some(&Value::Boolean(true));
+        let columns = csv::separator(filetype).filter(|_| aligned).map(|separator| {
+            let (_, height) = get_screen_size().unwrap_or((1, 1));
+            let start = view.start_line.min(buffer.len() - 1);
+            (separator, csv::starts(buffer.lines(start..(start + height).min(buffer.len())), separator, view.options.invisibles))
+        });
+        Stops { tab: self.indent_width(), columns, invisibles: view.options.invisibles }
+    }
+
+    // Moves to the start of the next or previous field of a CSV or TSV line
+    pub fn step_column(&mut self, separator: char, forward: bool) {
+        match csv::step(&self.buffer[self.line], self.column, separator, forward) {
+            Some(column) => self.column = column,
+            None => self.message = format!("Already in the {} column", if forward { "last" } else { "first" }),
+        }
+    }
+
+    pub fn var_is(&self, name: &str) -> bool {
+        self.var(name) == Some(Value::Boolean(true))
+    }
+
+    // Replaces the buffer with another file, returning to where it was last left
+    pub fn open_file(&mut self, path: PathBuf) -> Result<(), String> {
+        if self.unsaved {
+            return Err("Save changes before switching files".to_string());
+        }
+
+        let bytes = fs::read(&path).map_err(|err| format!("Unable to open {}: {}", path.display(), err))?;
+        self.remember_state();
+
+        let (buffer, line_ending, encoding) = encoding::decode(&bytes);
+        (self.buffer, self.line_ending, self.encoding) = (buffer.into(), line_ending, encoding);
+        self.filetype = filetype::detect(&path, false);
+        self.path = path;
+        self.scratch = false;
+        self.history = History::default();
+        self.selection = None;
+        self.marks.clear();
+        self.vars = Vars::default();
+        self.start_line = 0;
+        self.folds = folds::load(&self.path);
+        self.goto(marks::last_position(&self.path).unwrap_or((0, 0)));
+        self.apply_template();
+        conflicts::announce(self);
+        Ok(())
+    }
+
+    // Reads the file again, as a change that undo takes back out, for when something else rewrote it
+    pub fn reload(&mut self) -> Result<(), String> {
+        if self.scratch {
+            return Err("A scratch buffer has no file to reload".to_string());
+        }
+
+        let bytes = fs::read(&self.path).map_err(|err| format!("Unable to read {}: {}", self.path.display(), err))?;
+        let (lines, line_ending, encoding) = encoding::decode(&bytes);
+        let cursor = (self.line, self.column);
+        self.history.seal();
+        self.replace_lines(0, self.buffer.len(), lines, cursor);
+        self.history.seal();
+        (self.line_ending, self.encoding) = (line_ending, encoding);
+        self.goto(cursor);
+        self.unsaved = false;
+        Ok(())
+    }
+
+    // Fills an empty file from the template for its name or extension, as a change that undo takes back out
+    pub fn apply_template(&mut self) {
+        if self.scratch || self.unsaved || self.buffer.len() > 1 || !self.buffer[0].is_empty() {
+            return;
+        }
+
+        let Some((lines, cursor)) = templates::load(&self.path) else { return };
+        self.replace_lines(0, 1, lines, cursor);
+        self.history.seal();
+        self.message = "Started from a template".to_string();
+    }
+
+    // Writes the buffer to another path, which it belongs to from then on. The old file is left as it was
+    pub fn save_as(&mut self, path: PathBuf) -> Result<(), String> {
+        if path.is_dir() {
+            return Err(format!("{} is a directory", path.display()));
+        }
+
+        self.remember_state();
+        let old_path = std::mem::replace(&mut self.path, path);
+        let was_scratch = std::mem::replace(&mut self.scratch, false);
+        let message = std::mem::take(&mut self.message);
+        self.save();
+        if self.unsaved {
+            self.path = old_path;
+            self.scratch = was_scratch;
+            return Err(std::mem::replace(&mut self.message, message));
+        }
+
+        if !was_scratch {
+            swap::remove(&old_path);
+        }
+        self.filetype = filetype::detect(&self.path, false);
+        self.message = format!("Saved as {}", self.path.display());
+        hooks::after_save(self);
+        Ok(())
+    }
+
+    // Asks for the path to save under, making sure before writing over a file that is already there
+    pub fn prompt_save_as(&mut self) {
+        let Some(answer) = self.prompt("Save as: ") else { return };
+        let answer = answer.trim();
+        if answer.is_empty() {
+            return;
+        }
+        let path = match answer.strip_prefix("~/") {
+            Some(rest) if let Some(home) = std::env::var_os("HOME") => PathBuf::from(home).join(rest),
+            _ => PathBuf::from(answer),
+        };
+
+        if path.is_file() && path != self.path {
+            let overwrite = self.prompt(&format!("{} already exists. Overwrite it? (y/n): ", path.display())).unwrap_or_default();
+            if overwrite.trim().to_lowercase() != "y" {
+                return;
+            }
+        }
+        if let Err(err) = self.save_as(path) {
+            self.message = err;
+        }
+    }
+
+    // Quits, first asking whether to save files with unsaved changes in any pane or to throw the changes away.
+    // Scratch buffers have no file to ask about, so they are just saved. A file that couldn't be saved keeps the
+    // editor open
+    fn quit_asking(&mut self) {
+        let mut changed = vec![];
+        self.each_document(|editor| {
+            if editor.unsaved && !editor.scratch {
+                changed.push(editor.path.clone());
+            }
+        });
+
+        let save = match &changed[..] {
+            [] => true,
+            changed => {
+                let files = if let [path] = changed { path.display().to_string() } else { format!("{} files", changed.len()) };
+                let answer = self.prompt(&format!("Save changes to {}? (y/n/cancel): ", files)).unwrap_or_default();
+                match answer.trim().to_lowercase().as_str() {
+                    "y" | "yes" => true,
+                    "n" | "no" => false,
+                    _ => {
+                        self.message = String::new();
+                        return;
+                    }
+                }
+            }
+        };
+
+        let mut failed = false;
+        self.each_document(|editor| {
+            if editor.unsaved && (save || editor.scratch) {
+                editor.save();
+                failed |= editor.unsaved;
+            } else if editor.unsaved {
+                // Thrown away, so there's nothing left to recover
+                swap::remove(&editor.path);
+            }
+        });
+        self.quit = !failed;
+    }
+
+    // Saves the cursor position and folds so the file reopens the way it was left
+    pub fn remember_state(&mut self) {
+        if self.scratch {
+            return;
+        }
+
+        if let Err(err) = marks::remember_position(&self.path, (self.line, self.column)) {
+            self.message = format!("Unable to save cursor position: {}", err);
+        }
+        if let Err(err) = folds::save(&self.path, &self.folds) {
+            self.message = format!("Unable to save folds: {}", err);
+        }
+    }
+
+    // The line itself, or the header of the closed fold hiding it
+    fn visible_line(hidden: &[(usize, usize)], line: usize) -> usize {
+        hidden.iter().find(|(first, last)| (*first..=*last).contains(&line)).map_or(line, |(first, _)| first - 1)
+    }
+
+    // Moves `delta` visible lines from `line`, skipping closed folds and stopping at either end of the buffer
+    fn step_visible(&self, hidden: &[(usize, usize)], mut line: usize, delta: isize) -> usize {
+        for _ in 0..delta.unsigned_abs() {
+            if delta < 0 {
+                line = Editor::visible_line(hidden, line.saturating_sub(1));
+            } else {
+                let next = hidden.iter().find(|(first, _)| *first == line + 1).map_or(line + 1, |(_, last)| last + 1);
+                if next >= self.buffer.len() {
+                    break;
+                }
+                line = next;
+            }
+        }
+        line
+    }
+
+    // How many visible lines there are from `from` up to but not including `to`
+    fn rows_between(hidden: &[(usize, usize)], from: usize, to: usize) -> usize {
+        let folded = hidden.iter().map(|(first, last)| (last + 1).min(to).saturating_sub(*first.max(&from))).sum::<usize>();
+        to.saturating_sub(from) - folded
+    }
+
+    // Moves `delta` screen rows from a line and which of its wrapped rows, skipping closed folds and stopping at
+    // either end of the buffer
+    fn step_rows(&self, hidden: &[(usize, usize)], width: usize, (mut line, mut row): (usize, usize), delta: isize) -> (usize, usize) {
+        let stops = self.stops(self.view());
+        let mut left = delta.unsigned_abs();
+        while left > 0 {
+            let count = wrap::rows(&self.buffer[line], width, &stops).len();
+            if delta < 0 {
+                if row >= left {
+                    return (line, row - left);
+                }
+                let previous = self.step_visible(hidden, line, -1);
+                if previous == line {
+                    return (line, 0);
+                }
+                left -= row + 1;
+                line = previous;
+                row = wrap::rows(&self.buffer[line], width, &stops).len() - 1;
+            } else {
+                if row + left < count {
+                    return (line, row + left);
+                }
+                let next = self.step_visible(hidden, line, 1);
+                if next == line {
+                    return (line, count - 1);
+                }
+                left -= count - row;
+                line = next;
+                row = 0;
+            }
+        }
+        (line, row)
+    }
+
+    // How many screen rows there are from `from` down to `to` when lines wrap, counting no further than `limit`
+    fn rows_from(&self, hidden: &[(usize, usize)], width: usize, (mut line, mut row): (usize, usize), to: (usize, usize), limit: usize) -> usize {
+        let stops = self.stops(self.view());
+        let mut count = 0;
+        while line < to.0 && count <= limit {
+            count += wrap::rows(&self.buffer[line], width, &stops).len() - row;
+            row = 0;
+            let next = self.step_visible(hidden, line, 1);
+            if next == line {
+                break;
+            }
+            line = next;
+        }
+        count + to.1.saturating_sub(row)
+    }
+
+    // The width lines wrap at in the focused pane, or None when they don't
+    fn focused_wrap_width(&self) -> Option<usize> {
+        let view = self.view();
+        view.options.wrap.then(|| self.wrap_width(view, self.layout(get_screen_size().unwrap_or((1, 1))).0[self.focused], self.buffer.len()))
+    }
+
+    // Moves the cursor `delta` screen rows up or down through wrapped lines, keeping its place along the row
+    fn move_rows(&mut self, width: usize, delta: isize) {
+        let hidden = self.folds.hidden(&self.buffer);
+        let tab = &self.stops(self.view());
+        let text = &self.buffer[self.line];
+        let rows = wrap::rows(text, width, tab);
+        let column = self.column.min(text.chars().count());
+        let row = wrap::row_of(&rows, column);
+        let offset = width::cell_of(text, column, tab) - width::cell_of(text, rows[row].0, tab);
+
+        let (line, row) = self.step_rows(&hidden, width, (self.line, row), delta);
+        let text = &self.buffer[line];
+        let rows = wrap::rows(text, width, tab);
+        let (start, end) = rows[row];
+        // Only the last row has room for the cursor past its end, the end of other rows is the next one's start
+        let end = if row + 1 < rows.len() { end.saturating_sub(1).max(start) } else { end };
+        self.line = line;
+        self.column = width::column_at(text, width::cell_of(text, start, tab) + offset, tab).min(end);
+    }
+
+    // Up to `count` lines from `start_line` down in a buffer of `len` lines, skipping closed folds
+    fn visible_lines(hidden: &[(usize, usize)], len: usize, start_line: usize, count: usize) -> Vec<usize> {
+        let mut lines = vec![start_line];
+        while lines.len() < count {
+            let last = lines[lines.len() - 1];
+            let next = hidden.iter().find(|(first, _)| *first == last + 1).map_or(last + 1, |(_, end)| end + 1);
+            if next >= len {
+                break;
+            }
+            lines.push(next);
+        }
+        lines
+    }
+
+    // The bottom row of the terminal is reserved for messages/prompts and the top row for the ruler when it is
+    // shown. The columns of panes share the rest side by side with a │ between them, and the panes in a column share
+    // its rows, each followed by its status bar. The output pane runs under all of them. The file tree, when shown,
+    // takes columns on the left before any of this. Zen mode hides the ruler and status bars. Rects may have no rows
+    // or columns when the screen is too small for the panes
+    fn areas(&self, panes: &[View], output: bool, (width, height): (usize, usize)) -> (Vec<Rect>, Option<Rect>) {
+        let status = if self.zen { 0 } else { 1 };
+        let top = self.text_top();
+        let mut rows = height.saturating_sub(1 + top);
+        let sidebar = self.tree_rect((width, height)).map_or(0, |rect| rect.width + 1);
+        let width = width - sidebar;
+        let stacks = panes.iter().map(|pane| pane.stack).max().unwrap_or(0) + 1;
+        let counts = (0..stacks).map(|stack| panes.iter().filter(|pane| pane.stack == stack).count()).collect::<Vec<usize>>();
+
+        let output = output.then(|| {
+            // As tall as a pane in the fullest column would be with one more pane in it
+            let output_rows = panes::shares(rows, counts.iter().max().unwrap_or(&1) + 1).pop().unwrap_or(0);
+            rows -= output_rows;
+            Rect { top: top + rows, left: sidebar, width, rows: output_rows.saturating_sub(status) }
+        });
+
+        let widths = panes::shares(width.saturating_sub(stacks - 1), stacks);
+        let lefts = widths.iter().scan(sidebar, |left, width| {
+            let this = *left;
+            *left += width + 1;
+            Some(this)
+        }).collect::<Vec<usize>>();
+
+        let mut tops = vec![top; stacks];
+        let mut seen = vec![0; stacks];
+        let rects = panes.iter().map(|pane| {
+            let stack = pane.stack;
+            let pane_rows = panes::shares(rows, counts[stack])[seen[stack]];
+            let rect = Rect { top: tops[stack], left: lefts[stack], width: widths[stack], rows: pane_rows.saturating_sub(status) };
+            tops[stack] += pane_rows;
+            seen[stack] += 1;
+            rect
+        }).collect();
+        (rects, output)
+    }
+
+    // The file tree's area, with its title row last
+    pub fn tree_rect(&self, (width, height): (usize, usize)) -> Option<Rect> {
+        let top = self.text_top();
+        let width = tree::WIDTH.min(width / 3);
+        self.tree.as_ref().filter(|_| width > 0).map(|_| Rect { top, left: 0, width, rows: height.saturating_sub(2 + top).max(1) })
+    }
+
+    // The areas of the panes and the output pane, each with at least one row of text
+    fn layout(&self, size: (usize, usize)) -> (Vec<Rect>, Option<Rect>) {
+        let (rects, output) = self.areas(&self.panes, self.output.is_some(), size);
+        let at_least_a_row = |rect: Rect| Rect { rows: rect.rows.max(1), width: rect.width.max(1), ..rect };
+        (rects.into_iter().map(at_least_a_row).collect(), output.map(at_least_a_row))
+    }
+
+    // Text rows in the focused pane
+    fn text_height(&self, size: (usize, usize)) -> usize {
+        self.layout(size).0[self.focused].rows
+    }
+
+    fn text_top(&self) -> usize {
+        if self.show_ruler && !self.zen { 1 } else { 0 }
+    }
+
+    // The first screen column of a pane's text and how many columns it gets: all of them, or a centered column in zen mode
+    fn text_columns(&self, rect: Rect) -> (usize, usize) {
+        if !self.zen || rect.width <= self.config.zen_width {
+            return (rect.left, rect.width);
+        }
+        (rect.left + (rect.width - self.config.zen_width) / 2, self.config.zen_width)
+    }
+
+    // The lines of the paragraph around the cursor, which runs between blank lines
+    fn paragraph(&self) -> std::ops::RangeInclusive<usize> {
+        let blank = |line: &usize| self.buffer[*line].trim().is_empty();
+        let first = (0..self.line).rev().find(blank).map_or(0, |line| line + 1);
+        let last = (self.line + 1..self.buffer.len()).find(blank).map_or(self.buffer.len() - 1, |line| line - 1);
+        first.min(self.line)..=last.max(self.line)
+    }
+
+    // Column numbers every 10 columns with + halfway between, e.g. "....+....10...."
+    fn ruler(width: usize) -> String {
+        let mut ruler = (1..=width).map(|column| if column % 5 == 0 { '+' } else { '.' }).collect::<Vec<char>>();
+        for column in (10..=width).step_by(10) {
+            let number = column.to_string();
+            ruler.splice(column - number.len()..column, number.chars());
+        }
+        ruler.into_iter().collect()
+    }
+
+    // The cursor's (byte, char) offset in the file
+    pub fn offsets(&self) -> (usize, usize) {
+        offset::offsets(&self.buffer, (self.line, self.column.min(self.buffer[self.line].chars().count())), self.line_ending, self.encoding)
+    }
+
+    pub fn selection_range(&self) -> Option<((usize, usize), (usize, usize))> {
+        self.selection.map(|anchor| selection::ordered(anchor, (self.line, self.column)))
+    }
+
+    pub fn selected_text(&self) -> Option<String> {
+        self.selection_range().map(|(start, end)| selection::text(&self.buffer, start, end))
+    }
+
+    fn view(&self) -> View {
+        View { line: self.line, column: self.column, start_line: self.start_line, ..self.panes[self.focused] }
+    }
+
+    // Moves input to another pane. The selection and extra cursors belong to the pane being left, so they go
+    pub fn focus(&mut self, index: usize) {
+        self.panes[self.focused] = self.view();
+        let (from, to) = (self.panes[self.focused].document, self.panes[index].document);
+        if from != to {
+            let mut document = self.documents[to].take().expect("Pane onto a missing document");
+            self.swap_document(&mut document);
+            self.documents[from] = Some(document);
+        }
+        self.load_view(index);
+    }
+
+    fn load_view(&mut self, index: usize) {
+        let view = self.panes[index];
+        self.focused = index;
+        self.start_line = view.start_line.min(self.buffer.len() - 1);
+        self.goto((view.line, view.column));
+        self.selection = None;
+        self.selecting = false;
+        self.cursors.clear();
+    }
+
+    // Exchanges the focused buffer for another one
+    fn swap_document(&mut self, document: &mut Document) {
+        std::mem::swap(&mut self.buffer, &mut document.buffer);
+        std::mem::swap(&mut self.path, &mut document.path);
+        std::mem::swap(&mut self.scratch, &mut document.scratch);
+        std::mem::swap(&mut self.unsaved, &mut document.unsaved);
+        std::mem::swap(&mut self.last_autosave, &mut document.last_autosave);
+        std::mem::swap(&mut self.history, &mut document.history);
+        std::mem::swap(&mut self.marks, &mut document.marks);
+        std::mem::swap(&mut self.line_ending, &mut document.line_ending);
+        std::mem::swap(&mut self.encoding, &mut document.encoding);
+        std::mem::swap(&mut self.filetype, &mut document.filetype);
+        std::mem::swap(&mut self.filters, &mut document.filters);
+        std::mem::swap(&mut self.folds, &mut document.folds);
+        std::mem::swap(&mut self.swap_stale, &mut document.swap_stale);
+        std::mem::swap(&mut self.executable_checked, &mut document.executable_checked);
+        std::mem::swap(&mut self.vars, &mut document.vars);
+    }
+
+    // Runs `action` with each open buffer focused in turn, then focuses the pane that was focused before
+    pub fn each_document(&mut self, mut action: impl FnMut(&mut Editor)) {
+        let focused = self.focused;
+        let mut done = vec![];
+        for index in 0..self.panes.len() {
+            if done.contains(&self.panes[index].document) {
+                continue;
+            }
+            done.push(self.panes[index].document);
+            self.focus(index);
+            action(self);
+        }
+        self.focus(focused);
+    }
+
+    // The buffer a pane shows: its lines, the lines hidden by its folds, and the document holding it unless it is
+    // the focused buffer
+    fn pane_buffer(&self, view: View) -> (&Lines, Vec<(usize, usize)>, Option<&Document>) {
+        match self.documents[view.document].as_ref() {
+            Some(document) => (&document.buffer, document.folds.hidden(&document.buffer), Some(document)),
+            None => (&self.buffer, self.folds.hidden(&self.buffer), None),
+        }
+    }
+
+    // Splits the focused pane in two, both looking at the same place, and focuses the new one
+    pub fn split(&mut self, beside: bool) -> Result<(), String> {
+        self.add_pane(beside, self.view(), self.cursor_row(self.focused))
+    }
+
+    fn cursor_row(&self, index: usize) -> Option<usize> {
+        self.cursor_rows.borrow().get(index).copied().flatten()
+    }
+
+    // The first pane onto the file at a canonical path
+    pub fn pane_showing(&self, canonical: &Path) -> Option<usize> {
+        (0..self.panes.len()).find(|index| {
+            let (path, scratch) = match self.pane_buffer(self.panes[*index]).2 {
+                Some(document) => (&document.path, document.scratch),
+                None => (&self.path, self.scratch),
+            };
+            !scratch && fs::canonicalize(path).is_ok_and(|path| path == canonical)
+        })
+    }
+
+    // A file already open in a pane is focused, keeping any unsaved changes to it. Others replace the focused buffer,
+    // or get a pane of their own beside it when it has unsaved changes
+    pub fn show_file(&mut self, path: &Path) -> Result<(), String> {
+        let canonical = fs::canonicalize(path).map_err(|err| format!("Unable to open {}: {}", path.display(), err))?;
+        match self.pane_showing(&canonical) {
+            Some(index) => self.focus(index),
+            None if self.unsaved => self.split_file(true, path.to_path_buf())?,
+            None => self.open_file(path.to_path_buf())?,
+        }
+        Ok(())
+    }
+
+    // Splits the focused pane with the new one onto another file. A file that is already open in a pane shares
+    // its buffer
+    pub fn split_file(&mut self, beside: bool, path: PathBuf) -> Result<(), String> {
+        let canonical = fs::canonicalize(&path).map_err(|err| format!("Unable to open {}: {}", path.display(), err))?;
+        match self.pane_showing(&canonical) {
+            Some(index) if index == self.focused => self.split(beside),
+            Some(index) => self.add_pane(beside, self.panes[index], self.cursor_row(index)),
+            None => {
+                let document = Document::open(path)?;
+                let (line, column) = marks::last_position(&document.path).unwrap_or((0, 0));
+                self.documents.push(Some(document));
+                let view = View { line, column, start_line: line, document: self.documents.len() - 1, ..self.view() };
+                self.add_pane(beside, view, None).inspect_err(|_| {
+                    self.documents.pop();
+                })
+            }
+        }
+    }
+
+    // Adds a pane below the focused one, or in a new column to its right when `beside`, and focuses it. Its cursor
+    // goes on `row` of it where there's room, and the panes made smaller keep theirs where they were
+    fn add_pane(&mut self, beside: bool, mut view: View, row: Option<usize>) -> Result<(), String> {
+        let mut panes = self.panes.clone();
+        let stack = self.panes[self.focused].stack;
+        let index = if beside {
+            panes.iter_mut().filter(|pane| pane.stack > stack).for_each(|pane| pane.stack += 1);
+            panes.iter().position(|pane| pane.stack > stack).unwrap_or(panes.len())
+        } else {
+            self.focused + 1
+        };
+        view.stack = if beside { stack + 1 } else { stack };
+        panes.insert(index, view);
+
+        let (rects, output) = self.areas(&panes, self.output.is_some(), get_screen_size().unwrap_or((1, 1)));
+        if rects.iter().chain(output.as_ref()).any(|rect| rect.rows == 0 || rect.width == 0) {
+            return Err("Not enough room for another pane".to_string());
+        }
+
+        self.panes = panes;
+        let mut rows = self.cursor_rows.take();
+        rows.resize(self.panes.len() - 1, None);
+        rows.insert(index, row);
+        self.cursor_rows.replace(rows);
+        self.focus(index);
+        self.keep_cursor_rows();
+        Ok(())
+    }
+
+    // Scrolls each pane so its cursor is back on the row it was on the last frame, or as near as the pane's size
+    // now allows, rather than wherever the next keypress's scrolling would put it
+    pub fn keep_cursor_rows(&mut self) {
+        let focused = self.focused;
+        let size = get_screen_size().unwrap_or((1, 1));
+        for index in 0..self.panes.len() {
+            self.focus(index);
+            if let Some(row) = self.cursor_row(index) {
+                let row = -(row.min(self.text_height(size).saturating_sub(1)) as isize);
+                let hidden = self.folds.hidden(&self.buffer);
+                match self.focused_wrap_width() {
+                    Some(width) => {
+                        let cursor = (self.line, wrap::row_of(&wrap::rows(&self.buffer[self.line], width, &self.stops(self.view())), self.column));
+                        (self.start_line, self.panes[index].start_row) = self.step_rows(&hidden, width, cursor, row);
+                    }
+                    None => self.start_line = self.step_visible(&hidden, self.line, row),
+                }
+            }
+            self.scroll();
+        }
+        self.focus(focused);
+    }
+
+    // Shows text in the output pane, opening it if needed
+    pub fn show_output(&mut self, output: Output) -> Result<(), String> {
+        if self.output.is_none() {
+            let (rects, output) = self.areas(&self.panes, true, get_screen_size().unwrap_or((1, 1)));
+            if rects.iter().chain(output.as_ref()).any(|rect| rect.rows == 0) {
+                return Err("Not enough room for another pane".to_string());
+            }
+        }
+        self.output = Some(output);
+        Ok(())
+    }
+
+    // Closes the output pane if it is open, otherwise the focused pane. The last pane onto a buffer can only be
+    // closed once it is saved
+    pub fn close_pane(&mut self) -> Result<(), String> {
+        if self.output.take().is_some() {
+            return Ok(());
+        }
+        if self.panes.len() == 1 {
+            return Err("This is the only pane".to_string());
+        }
+
+        let closing = self.panes[self.focused];
+        let last = self.panes.iter().filter(|pane| pane.document == closing.document).count() == 1;
+        if last && self.unsaved {
+            return Err("Save changes before closing the last pane onto this buffer".to_string());
+        }
+        if last {
+            self.remember_state();
+        }
+
+        // Move to the pane that takes its place, then drop it
+        let closed = self.focused;
+        let next = if closed + 1 < self.panes.len() { closed + 1 } else { closed - 1 };
+        self.focus(next);
+        self.panes.remove(closed);
+        if next > closed {
+            self.focused -= 1;
+        }
+
+        if !self.panes.iter().any(|pane| pane.stack == closing.stack) {
+            self.panes.iter_mut().filter(|pane| pane.stack > closing.stack).for_each(|pane| pane.stack -= 1);
+        }
+        if last {
+            self.documents.remove(closing.document);
+            self.panes.iter_mut().filter(|pane| pane.document > closing.document).for_each(|pane| pane.document -= 1);
+        }
+        Ok(())
+    }
+
+    // The gap between the left and right parts is filled with `fill`
+    fn status_bar(&self, width: usize, view: View, focused: bool, fill: char) -> String {
+        let (path, scratch, unsaved, trimmed, encoding, line_ending, lines) = match self.pane_buffer(view).2 {
+            Some(document) => (&document.path, document.scratch, document.unsaved, document.history.trimmed(), document.encoding, document.line_ending, document.buffer.len()),
+            None => (&self.path, self.scratch, self.unsaved, self.history.trimmed(), self.encoding, self.line_ending, self.buffer.len()),
+        };
+        let name = if scratch { "[scratch]".to_string() } else { path.display().to_string() };
+        let mut left = format!(" {}{}", name, if unsaved { " [+]" } else { "" });
+        if trimmed > 0 {
+            left += " [undo trimmed]";
+        }
+
+        if let Some(text) = self.selected_text().filter(|_| focused) {
+            left += &format!("  Sel: {}", SelectionStats::new(&text));
+        }
+        if focused && !self.cursors.is_empty() {
+            left += &format!("  {} cursors", self.cursors.len() + 1);
+        }
+
+        let mut right = format!("{} {}  {}/{}, col {} ", encoding, line_ending, view.line + 1, lines, view.column + 1);
+        if focused && !self.segments.is_empty() {
+            right = format!("{} {} ", right, self.segments);
+        }
+        if self.show_offset && focused {
+            let (bytes, chars) = self.offsets();
+            right = format!("byte {}, char {}  {}", bytes, chars, right);
+        }
+        let padding = match width.saturating_sub(width::text_width(&left) + width::text_width(&right)) {
+            padding if fill != ' ' && padding > 2 => format!(" {} ", fill.to_string().repeat(padding - 2)),
+            padding => " ".repeat(padding.max(1)),
+        };
+        width::truncate(&format!("{}{}{}", left, padding, right), width)
+    }
+
+    pub fn draw(&self) -> io::Result<()> {
+        self.draw_with_prompt_cursor(None)
+    }
+
+    fn draw_with_prompt_cursor(&self, prompt_cursor: Option<usize>) -> io::Result<()> {
+        let (width, height) = get_screen_size().unwrap_or((1, 1));
+        let text_height = self.text_height((width, height));
+        let top = self.text_top();
+
+        // Drawn into memory first, so only the rows that changed since the last frame are written out
+        let mut out = vec![];
+        if self.show_ruler {
+            set_cursor_pos(&mut out, 0, 0)?;
+            write!(out, "{}{}\x1b[0m", self.style(Style::Faint), Editor::ruler(width))?;
+        }
+
+        let mut cursor = (top, 0);
+        let (rects, output_rect) = self.layout((width, height));
+        let mut cursor_rows = vec![];
+        for (index, rect) in rects.iter().copied().enumerate() {
+            let focused = index == self.focused;
+            let view = if focused { self.view() } else { self.panes[index] };
+            let (row, column) = self.draw_pane(&mut out, view, focused, rect)?;
+            cursor_rows.push(Some(row));
+            if focused {
+                cursor = (rect.top + row, column);
+            }
+
+            if !self.zen {
+                // Only the focused pane's status bar is in full reverse video. Status bars with another pane
+                // below double as a ─ separator line
+                let below = self.panes[index + 1..].iter().any(|pane| pane.stack == view.stack) || self.output.is_some();
+                let fill = if below { '─' } else { ' ' };
+                let style = match (focused, fill) {
+                    (true, _) => self.style(Style::Status),
+                    (false, '─') => self.style(Style::Faint),
+                    (false, _) => self.style(Style::Faint) + "\x1b[4m",
+                };
+                set_cursor_pos(&mut out, rect.top + rect.rows, rect.left)?;
+                write!(out, "{}{}\x1b[0m", style, self.status_bar(rect.width, view, focused, fill))?;
+            }
+        }
+
+        self.cursor_rows.replace(cursor_rows);
+
+        // Columns of panes are drawn left to right, each clearing to the end of its rows, so the │ between them
+        // goes in last
+        let bottom = output_rect.map_or(height.saturating_sub(1), |rect| rect.top);
+        for rect in rects.iter().filter(|rect| rect.left > 0 && rect.top == top) {
+            for row in top..bottom {
+                set_cursor_pos(&mut out, row, rect.left - 1)?;
+                write!(out, "{}│\x1b[0m", self.style(Style::Faint))?;
+            }
+        }
+
+        if let (Some(output), Some(rect)) = (self.output.as_ref(), output_rect) {
+            self.draw_output(&mut out, output, rect, !self.zen)?;
+        }
+
+        if let (Some(tree), Some(rect)) = (self.tree.as_ref(), self.tree_rect((width, height))) {
+            let row = self.draw_tree(&mut out, tree, rect)?;
+            if tree.focused {
+                cursor = (rect.top + row, 0);
+            }
+        }
+
+        if self.show_keys {
+            self.draw_recent_keys(&mut out, width)?;
+        }
+
+        if self.show_debug {
+            let text = format!(" {} ", self.latency).chars().take(width).collect::<String>();
+            set_cursor_pos(&mut out, top + if self.show_keys { 1 } else { 0 }.min(text_height - 1), width - text.chars().count())?;
+            write!(out, "{}{}\x1b[0m", self.style(Style::Status), text)?;
+        }
+
+        set_cursor_pos(&mut out, height.saturating_sub(1), 0)?;
+        write!(out, "{}\x1b[K", width::truncate(&self.message, width))?;
+
+        let frame = out;
+        let mut out = std::io::stdout();
+        // A full redraw resets the terminal, mouse reporting and the keyboard protocol included
+        if self.screen.borrow_mut().update(&mut out, &frame, (width, height))? {
+            if self.config.mouse {
+                set_mouse_reporting(&mut out, true)?;
+            }
+            if self.kitty_keys {
+                set_kitty_keys(&mut out, true)?;
+            }
+        }
+        match prompt_cursor {
+            Some(column) => set_cursor_pos(&mut out, height.saturating_sub(1), column.min(width))?,
+            None => set_cursor_pos(&mut out, cursor.0, cursor.1)?,
+        }
+        out.flush()
+    }
+
+    // Draws the file tree and the │ after it, returning the row of its cursor. The selected entry is highlighted
+    // while the tree has focus
+    fn draw_tree(&self, out: &mut impl Write, tree: &FileTree, rect: Rect) -> io::Result<usize> {
+        let top = tree.first_shown(rect.rows);
+        for row in 0..rect.rows {
+            set_cursor_pos(out, rect.top + row, 0)?;
+            let Some(entry) = tree.entries.get(top + row) else {
+                write!(out, "{}\x1b[0m", " ".repeat(rect.width))?;
+                continue;
+            };
+            let style = if top + row == tree.cursor { self.style(if tree.focused { Style::Selection } else { Style::Faint }) } else { String::new() };
+            let text = tree.row(entry).chars().take(rect.width).collect::<String>();
+            write!(out, "{}{:<width$}\x1b[0m", style, text, width = rect.width)?;
+        }
+
+        let name = tree.root.file_name().map_or(tree.root.display().to_string(), |name| name.to_string_lossy().to_string());
+        let title = format!(" {}/", name).chars().take(rect.width).collect::<String>();
+        set_cursor_pos(out, rect.top + rect.rows, 0)?;
+        let style = if tree.focused { self.style(Style::Status) } else { self.style(Style::Faint) + "\x1b[4m" };
+        write!(out, "{}{:<width$}\x1b[0m", style, title, width = rect.width)?;
+
+        for row in rect.top..=rect.top + rect.rows {
+            set_cursor_pos(out, row, rect.width)?;
+            write!(out, "{}│\x1b[0m", self.style(Style::Faint))?;
+        }
+        Ok(tree.cursor - top)
+    }
+
+    // Draws one pane's text, returning the row and column of its cursor within it. Panes without focus are dimmed
+    // and don't show the selection or extra cursors
+    fn draw_pane(&self, out: &mut impl Write, view: View, focused: bool, rect: Rect) -> io::Result<(usize, usize)> {
+        let (buffer, hidden, document) = self.pane_buffer(view);
+        let hidden = &hidden[..];
+        let filetype = document.map_or(self.filetype, |document| document.filetype);
+        // Other panes may have been left pointing past the end of the buffer by edits in this one
+        let line = view.line.min(buffer.len() - 1);
+        let rows = self.screen_rows(view, rect);
+        let (left, _) = self.text_columns(rect);
+        let gutter = self.gutter(view, buffer.len());
+        let text_width = self.wrap_width(view, rect, buffer.len());
+        let paragraph = self.paragraph();
+        let tab = &self.stops(view);
+        let path = document.map_or(&self.path, |document| &document.path);
+        let anchored = self.anchor.as_ref().filter(|(anchored, _, _)| anchored == path).map(|(_, first, last)| (*first, *last));
+        // Tabs are drawn as the spaces up to the next tab stop, so the terminal's own tab stops don't matter
+        let show = |char: char, cell: usize| match char {
+            ' ' if view.options.whitespace => "·".to_string(),
+            '\t' if view.options.whitespace => "→".to_string() + &" ".repeat(width::width_at(char, cell, tab) - 1),
+            '\t' => " ".repeat(width::width_at(char, cell, tab)),
+            // A separator in column mode is padded out to the next column
+            char if tab.columns.as_ref().is_some_and(|(separator, _)| *separator == char) => char.to_string() + &" ".repeat(width::width_at(char, cell, tab) - 1),
+            // Left out rather than printed, so the terminal can't give them a cell the text doesn't count
+            char if width::is_invisible(char) && !view.options.invisibles => String::new(),
+            '\u{00AD}' => "\x1b[35m-\x1b[39m".to_string(),
+            char if width::is_invisible(char) => "\x1b[35m‸\x1b[39m".to_string(),
+            char => encoding::visible(char).unwrap_or(char.to_string()),
+        };
+
+        for (current_line, (line_index, segment)) in rows.iter().copied().enumerate() {
+            set_cursor_pos(out, rect.top + current_line, left)?;
+            // Rows a wrapped line continues on leave the line number blank
+            let first_row = segment.is_none_or(|(start, _)| start == 0);
+            if gutter > 0 && first_row {
+                write!(out, "{}{:>width$}\x1b[0m ", self.style(Style::Faint), line_index + 1, width = gutter - 1)?;
+            } else if gutter > 0 {
+                write!(out, "{}", " ".repeat(gutter))?;
+            }
+
+            // Borrowed unless concealing changes it, so drawing a huge line doesn't copy it every frame
+            let mut curr_str = Cow::Borrowed(buffer[line_index].as_str());
+            if self.conceal && let Some(concealed) = conceal::conceal_line(&curr_str, self.conceal_patterns.get_or_init(conceal::patterns)) {
+                curr_str = Cow::Owned(concealed);
+            }
+
+            if !focused || (self.zen_dim && !paragraph.contains(&line_index)) {
+                write!(out, "{}", self.style(Style::Faint))?;
+            } else if anchored.is_some_and(|(first, last)| (first..=last).contains(&line_index)) {
+                write!(out, "{}", self.style(Style::Selection))?;
+            } else if conflicts::is_marker(&buffer[line_index]) {
+                write!(out, "{}", self.style(Style::Status))?;
+            } else if matches!(filetype, "gitcommit" | "rebase") && gitcommit::is_comment(&buffer[line_index]) || filetype == "rebase" && rebase::is_dropped(&buffer[line_index]) {
+                write!(out, "{}", self.style(Style::Faint))?;
+            }
+            // Text past a commit message's 50 or 72 columns is marked, and a guide shows where they are
+            let limit = if filetype == "gitcommit" { gitcommit::limit(buffer, line_index) } else { None };
+
+            let (skip, end) = self.row_columns(view, &curr_str, line_index, segment, text_width, tab);
+            let skip_cell = width::cell_of(&curr_str, skip, tab);
+            let row_cells = width::cell_of(&curr_str, end, tab) - skip_cell;
+            let last_row = segment.is_none_or(|(_, end)| rows.get(current_line + 1).is_none_or(|(next, next_segment)| *next != line_index || next_segment.is_some_and(|(start, _)| start < end)));
+
+            let selected = self.selection_range().filter(|_| focused).and_then(|(start, end)| selection::columns_on_line(start, end, line_index, curr_str.chars().count()));
+            // Along with where the others sharing the buffer are
+            let remote_cursors = self.session.iter().filter(|session| session.document == view.document).flat_map(|session| session.cursors.values());
+            let extra_cursors = self.cursors.iter().filter(|_| focused).chain(remote_cursors).filter(|cursor| cursor.0 == line_index).map(|cursor| cursor.1).collect::<Vec<usize>>();
+            if selected.is_some() || !extra_cursors.is_empty() {
+                let (from, to) = selected.unwrap_or((0, 0));
+                let mut row = String::new();
+                let mut in_selection = false;
+                let selection_style = self.style(Style::Selection);
+                // The space past the end of the line shows a selected line break, when there is room for it
+                let count = end - skip + if last_row && row_cells < text_width { 1 } else { 0 };
+                let mut cell = skip_cell;
+                for (column, char) in curr_str.chars().chain(std::iter::once(' ')).enumerate().skip(skip).take(count) {
+                    if ((from..to).contains(&column) || extra_cursors.contains(&column)) != in_selection {
+                        in_selection = !in_selection;
+                        row += if in_selection { &selection_style } else { "\x1b[0m" };
+                    }
+                    row += &show(char, cell);
+                    cell += width::width_at(char, cell, tab);
+                }
+                write!(out, "{}\x1b[0m", row)?;
+            } else {
+                // URLs are underlined, see the open command. Only the visible part of the line is searched
+                let window = curr_str.chars().skip(skip).take(end - skip).collect::<String>();
+                let links = urls::find(&window);
+                let mut row = String::new();
+                let mut in_link = false;
+                let mut over = false;
+                // The command starting each line of a rebase todo list is in bold, unless the line is dimmed
+                let keyword = if filetype == "rebase" && focused && !self.zen_dim && !rebase::is_dropped(&curr_str) { rebase::keyword_len(&curr_str).filter(|length| *length > skip).map(|length| length - skip) } else { None };
+                if keyword.is_some() {
+                    row += "\x1b[1m";
+                }
+                // So is the cursor's column in column mode
+                let separator = tab.columns.as_ref().map(|(separator, _)| *separator).filter(|_| focused);
+                let current_field = separator.map(|separator| csv::field_at(&buffer[line], view.column, separator));
+                let mut field = separator.map_or(0, |separator| csv::field_at(&curr_str, skip, separator));
+                let mut in_field = false;
+                let mut cell = skip_cell;
+                for (column, char) in window.chars().enumerate() {
+                    if keyword == Some(column) {
+                        row += "\x1b[22m";
+                    }
+                    if (current_field == Some(field)) != in_field {
+                        in_field = !in_field;
+                        row += if in_field { "\x1b[1m" } else { "\x1b[22m" };
+                    }
+                    if Some(char) == separator {
+                        field += 1;
+                    }
+                    if links.iter().any(|(start, end)| (*start..*end).contains(&column)) != in_link {
+                        in_link = !in_link;
+                        row += if in_link { "\x1b[4m" } else { "\x1b[24m" };
+                    }
+                    if !over && limit.is_some_and(|limit| cell >= limit) {
+                        over = true;
+                        row += &self.style(Style::Selection);
+                    }
+                    row += &show(char, cell);
+                    cell += width::width_at(char, cell, tab);
+                }
+                write!(out, "{}\x1b[24m", row)?;
+            }
+
+            let mut shown = row_cells;
+            if let Some(limit) = limit && last_row && skip_cell + shown < limit && limit - skip_cell < text_width {
+                write!(out, "\x1b[0m{}{}│\x1b[0m", " ".repeat(limit - skip_cell - shown), self.style(Style::Faint))?;
+                shown = limit - skip_cell + 1;
+            }
+            if swatches::applies(filetype) && last_row && shown + 2 < text_width {
+                // After the text rather than beside each literal, so columns still line up with the cursor
+                for Rgb(r, g, b) in swatches::colors(&buffer[line_index]) {
+                    if shown + 2 >= text_width {
+                        break;
+                    }
+                    write!(out, "\x1b[0m \x1b[38;2;{};{};{}m■\x1b[0m", r, g, b)?;
+                    shown += 2;
+                }
+            }
+
+            if let Some((_, last)) = hidden.iter().find(|(first, _)| *first == line_index + 1).filter(|_| last_row) {
+                let marker = format!(" ··· {} line{}", last - line_index, if last - line_index == 1 { "" } else { "s" });
+                write!(out, "{}{}\x1b[0m", self.style(Style::Faint), marker.chars().take(text_width.saturating_sub(shown)).collect::<String>())?;
+            }
+
+            write!(out, "\x1b[0m\x1b[K")?;
+        }
+
+        if view.options.wrap {
+            // The cursor is on the first of its line's rows that ends past it, or the line's last row
+            let column = view.column.min(buffer[line].chars().count());
+            let on_line = rows.iter().enumerate().filter(|(_, (shown, _))| *shown == line).collect::<Vec<_>>();
+            let cursor = on_line.iter().find(|(_, (_, segment))| segment.is_some_and(|(_, end)| column < end)).or(on_line.last());
+            return match cursor {
+                Some((row, (_, Some((start, _))))) => {
+                    let cells = width::cell_of(&buffer[line], column, tab) - width::cell_of(&buffer[line], *start, tab);
+                    Ok((*row, left + gutter + cells.min(text_width)))
+                }
+                _ => Ok((0, left + gutter)),
+            };
+        }
+        let row = rows.iter().position(|(shown, _)| *shown == line).unwrap_or(0);
+        let (skip, _) = self.row_columns(view, &buffer[line], line, None, text_width, tab);
+        let cells = width::cell_of(&buffer[line], view.column, tab) - width::cell_of(&buffer[line], skip, tab);
+        Ok((row, left + gutter + cells.min(text_width)))
+    }
+
+    // The chars of a line a row shows, as a column range. Without wrapping, the cursor's line scrolls sideways to
+    // keep the cursor in view and the others show their start
+    fn row_columns(&self, view: View, text: &str, line: usize, segment: Option<(usize, usize)>, text_width: usize, tab: &Stops) -> (usize, usize) {
+        if let Some(segment) = segment {
+            return segment;
+        }
+        let skip = if line == view.line { width::scroll_start(text, view.column, text_width, tab) } else { 0 };
+        let end = width::column_at(text, width::cell_of(text, skip, tab) + text_width, tab);
+        (skip, end.min(text.chars().count()))
+    }
+
+    // What each of a pane's screen rows shows: a line, and when wrapping which part of it as a char range
+    fn screen_rows(&self, view: View, rect: Rect) -> Vec<(usize, Option<(usize, usize)>)> {
+        let (buffer, hidden, _) = self.pane_buffer(view);
+        let start_line = Editor::visible_line(&hidden, view.start_line.min(buffer.len() - 1));
+        let lines = Editor::visible_lines(&hidden, buffer.len(), start_line, rect.rows);
+        if !view.options.wrap {
+            return lines.into_iter().map(|line| (line, None)).collect();
+        }
+
+        let width = self.wrap_width(view, rect, buffer.len());
+        let stops = self.stops(view);
+        let mut rows = vec![];
+        for line in lines {
+            let segments = wrap::rows(&buffer[line], width, &stops);
+            let skip = if line == start_line { view.start_row.min(segments.len() - 1) } else { 0 };
+            rows.extend(segments.into_iter().skip(skip).map(|segment| (line, Some(segment))));
+            if rows.len() >= rect.rows {
+                break;
+            }
+        }
+        rows.truncate(rect.rows);
+        rows
+    }
+
+    // Columns of text in a pane after its line numbers, which is where wrapped lines break
+    fn wrap_width(&self, view: View, rect: Rect, len: usize) -> usize {
+        self.text_columns(rect).1.saturating_sub(self.gutter(view, len)).max(1)
+    }
+
+    // Columns taken by line numbers in a pane onto a buffer of `len` lines
+    fn gutter(&self, view: View, len: usize) -> usize {
+        if view.options.numbers && !self.zen { len.to_string().len() + 1 } else { 0 }
+    }
+
+    // The pane showing text at a screen position
+    pub fn pane_at(&self, (row, column): (usize, usize)) -> Option<usize> {
+        let (rects, _) = self.layout(get_screen_size().unwrap_or((1, 1)));
+        rects.iter().position(|rect| (rect.top..rect.top + rect.rows).contains(&row) && (rect.left..rect.left + rect.width).contains(&column))
+    }
+
+    // The line and column a screen position points at in a pane. Positions outside the pane are moved to its
+    // nearest edge, past the end of a line to its end, and below the last line onto it
+    pub fn position_in(&self, pane: usize, (row, column): (usize, usize)) -> (usize, usize) {
+        let rect = self.layout(get_screen_size().unwrap_or((1, 1))).0[pane];
+        let view = if pane == self.focused { self.view() } else { self.panes[pane] };
+        let (buffer, _, _) = self.pane_buffer(view);
+        let rows = self.screen_rows(view, rect);
+        let (line, segment) = rows[row.saturating_sub(rect.top).min(rows.len() - 1)];
+
+        let (left, width) = self.text_columns(rect);
+        let left = left + self.gutter(view, buffer.len());
+        let cell = column.clamp(left, left + width) - left;
+        let text = &buffer[line];
+        let tab = &self.stops(view);
+        let length = text.chars().count();
+        let (start, end) = self.row_columns(view, text, line, segment, self.wrap_width(view, rect, buffer.len()), tab);
+        let column = width::column_at(text, width::cell_of(text, start, tab) + cell, tab);
+        match segment {
+            // Past the end of a row that wraps is the row's last char, not the start of the next one
+            Some(_) if end < length => (line, column.min(end.saturating_sub(1).max(start))),
+            _ => (line, column.min(length)),
+        }
+    }
+
+    // The last lines of the output that fit, dimmed like any pane without focus, and its title in the status bar
+    fn draw_output(&self, out: &mut impl Write, output: &Output, Rect { top, rows: text_height, width, .. }: Rect, status: bool) -> io::Result<()> {
+        let first = output.lines.len().saturating_sub(text_height);
+        for (row, line) in output.lines.iter().skip(first).enumerate() {
+            let text = line.chars().filter(|char| !char.is_control()).take(width).collect::<String>();
+            set_cursor_pos(out, top + row, 0)?;
+            write!(out, "{}{}\x1b[0m\x1b[K", self.style(Style::Faint), text)?;
+        }
+
+        if status {
+            let title = format!(" {}", output.title).chars().take(width).collect::<String>();
+            set_cursor_pos(out, top + text_height, 0)?;
+            write!(out, "{}\x1b[4m{:<width$}\x1b[0m", self.style(Style::Faint), title, width = width)?;
+        }
+        Ok(())
+    }
+
+    // Screencast overlay: the most recent keypresses in reverse video in the top right corner
+    fn draw_recent_keys(&self, out: &mut impl Write, width: usize) -> io::Result<()> {
+        let mut text = String::new();
+        for (key, _) in self.recent_keys.iter().rev().filter(|(_, time)| time.elapsed() < RECENT_KEYS_TIMEOUT) {
+            let candidate = format!(" {}{}", key, text);
+            if candidate.chars().count() + 1 > width {
+                break;
+            }
+            text = candidate;
+        }
+
+        if text.is_empty() {
+            return Ok(());
+        }
+
+        text.push(' ');
+        set_cursor_pos(out, self.text_top(), width - text.chars().count())?;
+        write!(out, "{}{}\x1b[0m", self.style(Style::Status), text)
+    }
+
+    // The escape sequence for one of the UI styles in the current theme
+    pub fn style(&self, style: Style) -> String {
+        theme::escape(self.theme.as_ref(), style)
+    }
+
+    // Applies the `theme` setting, leaving the previous theme in place if it can't be loaded
+    pub fn load_theme(&mut self) {
+        match theme::load(&self.config.theme) {
+            Ok(theme) => self.theme = theme,
+            Err(err) => self.message = format!("Theme error: {}", err),
+        }
+    }
+
+    // Adds text to the message log, dropping the oldest lines past the limit
+    pub fn log(&mut self, text: &str) {
+        self.log.extend(text.lines().filter(|line| !line.trim().is_empty()).map(str::to_string));
+        while self.log.len() > LOG_LINES {
+            self.log.pop_front();
+        }
+    }
+
+    pub fn read_key(&mut self) -> Key {
+        let key = self.keys.read_key();
+        self.last_input = Instant::now();
+
+        if self.show_keys {
+            self.recent_keys.push_back((key.to_string(), Instant::now()));
+            if self.recent_keys.len() > RECENT_KEYS {
+                self.recent_keys.pop_front();
+            }
+        }
+
+        key
+    }
+
+    // Reads a line of input on the message row, edited with readline's keys. Returns None if the user cancels with
+    // Escape or ctrl+c, or the prompt can't be drawn, which the event loop's next draw finds again
+    pub fn prompt(&mut self, msg: &str) -> Option<String> {
+        let mut input = LineEdit::default();
+
+        loop {
+            // Input too long for the row scrolls to keep the cursor on screen
+            let (width, _) = get_screen_size().unwrap_or((1, 1));
+            let text = msg.chars().chain(input.text.iter().copied()).collect::<String>();
+            let column = msg.chars().count() + input.cursor;
+            let skip = width::scroll_start(&text, column, width, &Stops::tabs(1));
+            self.message = text.chars().skip(skip).collect();
+            let cursor = width::cell_of(&text, column, &Stops::tabs(1)) - width::cell_of(&text, skip, &Stops::tabs(1));
+            if self.draw_with_prompt_cursor(Some(cursor)).is_err() {
+                self.message.clear();
+                return None;
+            }
+
+            match self.read_key() {
+                Key::Char('\r' | '\n') => {
+                    self.message.clear();
+                    return Some(input.text.into_iter().collect());
+                }
+                Key::Char('\x03') | Key::Escape => {
+                    self.message.clear();
+                    return None;
+                }
+                key => {
+                    input.handle(&key);
+                }
+            }
+        }
+    }
+
+    pub fn save(&mut self) {
+        // Lines are split on line breaks, so a file ending in one has an empty last line, which saving keeps as it
+        // is. With final_newline, a file without one gets it, as an edit undo can take back out
+        if self.var_is("final_newline") && !self.buffer[self.buffer.len() - 1].is_empty() {
+            let cursor = (self.line, self.column);
+            self.history.seal();
+            self.replace_lines(self.buffer.len(), 0, vec![String::new()], cursor);
+            self.history.seal();
+        }
+        // Commit messages get their body wrapped at 72 columns, see gitcommit
+        if self.filetype == "gitcommit" && let Some(lines) = gitcommit::wrap_body(&self.buffer) {
+            let cursor = (self.line.min(lines.len() - 1), 0);
+            self.history.seal();
+            self.replace_lines(0, self.buffer.len(), lines, cursor);
+            self.history.seal();
+        }
+        let (bytes, lost) = encoding::encode(&self.buffer, self.line_ending, self.encoding);
+        // A backup that can't be made is reported, but doesn't stop the save
+        let backup_error = if self.scratch { None } else { backup::make(&self.path, &self.config.backup).err() };
+        // A new file is created by its first save, which needs its directory to be there already
+        if let Err(err) = write(self.path.clone(), &bytes) {
+            self.message = match self.path.parent().filter(|dir| !dir.as_os_str().is_empty() && !dir.is_dir()) {
+                Some(dir) => format!("Unable to save {}: {} doesn't exist", self.path.display(), dir.display()),
+                None => format!("Unable to save {}: {}", self.path.display(), err),
+            };
+            return;
+        }
+        self.unsaved = false;
+        self.swap_stale = false;
+        if !self.scratch {
+            swap::remove(&self.path);
+        }
+        if lost > 0 {
+            self.message = format!("{} chars can't be written as {} and were saved as '?'", lost, self.encoding);
+        }
+        if let Some(err) = backup_error {
+            self.message = format!("Saved, but unable to back up the previous version: {}", err);
+        }
+        executable::check_after_save(self);
+    }
+
+    // The single entry point for modifying whole lines: replaces `count` lines at `start` and records the change
+    pub fn replace_lines(&mut self, start: usize, count: usize, new: Vec<String>, cursor_after: (usize, usize)) {
+        // The old lines are moved out rather than copied, which matters for a multi-megabyte line
+        let old = self.buffer.splice_lines(start, count, new.clone());
+        self.record(Change { start, old, new, at: None, cursor_before: (self.line, self.column), cursor_after });
+    }
+
+    // Replaces a byte range within one line in place and records the change
+    pub fn replace_text(&mut self, line: usize, bytes: Range<usize>, text: String, cursor_after: (usize, usize)) {
+        let at = bytes.start;
+        let old = self.buffer.edit_line(line, bytes, &text);
+        self.record(Change { start: line, old: vec![old], new: vec![text], at: Some(at), cursor_before: (self.line, self.column), cursor_after });
+    }
+
+    fn record(&mut self, change: Change) {
+        collab::changed(self, change.start, &change.old, &change.new, change.at);
+        self.folds.shift(change.start, change.old.len(), change.new.len());
+        (self.line, self.column) = change.cursor_after;
+        self.unsaved = true;
+        self.swap_stale = true;
+
+        // Someone else's edit to a shared buffer, which local undo mustn't revert
+        if self.session.as_ref().is_some_and(|session| session.applying) {
+            let dropped = self.history.rebase(&change);
+            if dropped > 0 {
+                self.message = format!("Someone else edited text you changed, so your oldest {} change{} can no longer be undone", dropped, if dropped == 1 { "" } else { "s" });
+            }
+            return;
+        }
+        self.history.record(change);
+
+        // Each time, with how many have gone so far, as the status bar's [undo trimmed] doesn't say when more go
+        if self.history.trim(self.config.undo_steps, self.config.undo_memory) > 0 {
+            let trimmed = self.history.trimmed();
+            self.message = format!("Undo history is full, the oldest {} change{} can no longer be undone", trimmed, if trimmed == 1 { "" } else { "s" });
+        }
+    }
+
+    // Makes an edit through the same path as every other change, returning false when it doesn't fit the buffer
+    pub fn apply(&mut self, operation: Operation) -> bool {
+        let Some(edit) = operation.edit(&self.buffer) else { return false };
+        match edit.kind {
+            EditKind::Lines { start, count, new } => self.replace_lines(start, count, new, edit.cursor),
+            EditKind::Text { line, bytes, text } => self.replace_text(line, bytes, text, edit.cursor),
+        }
+        true
+    }
+
+    // Inserts text at the cursor, which may span several lines, leaving the cursor after it
+    pub fn insert_at_cursor(&mut self, text: &str) {
+        self.apply(Operation::InsertText { at: (self.line, self.column), text: text.to_string() });
+    }
+
+    // Removes the text between two positions, keeping a copy in the trash
+    pub fn delete_range(&mut self, start: (usize, usize), end: (usize, usize)) {
+        let text = selection::text(&self.buffer, start, end);
+        self.trash.push(text, false, start.0);
+        self.apply(Operation::DeleteRange { start, end });
+    }
+
+    // Deletes the selection, or the current line when nothing is selected
+    fn delete(&mut self) {
+        if let Some((start, end)) = self.selection_range() {
+            self.selection = None;
+            self.delete_range(start, end);
+            return;
+        }
+
+        self.trash.push(self.buffer[self.line].clone(), true, self.line);
+        if self.buffer.len() == 1 {
+            self.replace_lines(0, 1, vec![String::new()], (0, 0));
+        } else {
+            let line = self.line.min(self.buffer.len() - 2);
+            self.replace_lines(self.line, 1, vec![], (line, 0));
+        }
+    }
+
+    // The selection, or the current line with its line break when nothing is selected
+    fn copy(&mut self) {
+        let text = self.selected_text().unwrap_or_else(|| self.buffer[self.line].clone() + "\n");
+        self.kills.push(text.clone());
+        self.message = match clipboard::backend(&self.config.clipboard).and_then(|clipboard| clipboard.copy(&text)) {
+            // They'd go unseen wherever the text is pasted
+            Ok(()) => match text.chars().filter(|char| width::is_invisible(*char)).count() {
+                0 => format!("Copied {} chars", text.chars().count()),
+                invisible => format!("Copied {} chars, {} of them invisible", text.chars().count(), invisible),
+            },
+            Err(err) => format!("Unable to copy: {}", err),
+        };
+    }
+
+    // Whole lines, as copied when nothing was selected, go in above the current line rather than splitting it
+    fn paste(&mut self) {
+        let text = match clipboard::backend(&self.config.clipboard).and_then(|clipboard| clipboard.paste()) {
+            Ok(text) => text,
+            Err(err) => {
+                self.message = format!("Unable to paste: {}", err);
+                return;
+            }
+        };
+        if text.is_empty() {
+            self.message = "The clipboard is empty".to_string();
+            return;
+        }
+
+        if let Some((start, end)) = self.selection_range() {
+            self.selection = None;
+            self.delete_range(start, end);
+        }
+        if let Some(lines) = text.strip_suffix('\n') {
+            let lines = lines.split('\n').map(str::to_string).collect::<Vec<String>>();
+            let count = lines.len();
+            self.replace_lines(self.line, 0, lines, (self.line + count, self.column));
+        } else {
+            self.insert_at_cursor(&text);
+        }
+    }
+
+    // Like delete, but into the kill ring for yanking back
+    fn cut(&mut self) {
+        let text = self.selected_text().unwrap_or_else(|| self.buffer[self.line].clone() + "\n");
+        self.kills.push(text);
+        self.delete();
+    }
+
+    // From the cursor to the end of the line, or the line break when already there
+    fn kill_to_end(&mut self) {
+        let length = self.buffer[self.line].chars().count();
+        let end = if self.column < length { (self.line, length) } else if self.line + 1 < self.buffer.len() { (self.line + 1, 0) } else { return };
+        self.kills.push(selection::text(&self.buffer, (self.line, self.column), end));
+        self.delete_range((self.line, self.column), end);
+    }
+
+    fn kill_to_start(&mut self) {
+        let start = (self.line, 0);
+        self.kills.push(selection::text(&self.buffer, start, (self.line, self.column)));
+        self.delete_range(start, (self.line, self.column));
+    }
+
+    // Puts back the newest kill, replacing the selection
+    fn yank(&mut self) {
+        if self.kills.get(0).is_none() {
+            self.message = "Nothing has been cut or copied".to_string();
+            return;
+        }
+
+        if let Some((start, end)) = self.selection_range() {
+            self.selection = None;
+            self.delete_range(start, end);
+        }
+        self.put_kill(0);
+    }
+
+    // Swaps the text just yanked for the kill before it
+    fn yank_pop(&mut self) {
+        let Some(yank) = self.kills.yank else {
+            self.message = "Yank first, then cycle through older kills".to_string();
+            return;
+        };
+
+        let (line, column) = yank.start;
+        if yank.linewise {
+            self.replace_lines(line, yank.end.0 - line, vec![], (line, column));
+        } else {
+            self.apply(Operation::DeleteRange { start: yank.start, end: yank.end });
+        }
+        self.put_kill(self.kills.older(yank.index));
+    }
+
+    // Linewise kills go in above the current line, others at the cursor
+    fn put_kill(&mut self, index: usize) {
+        let Some(text) = self.kills.get(index).cloned() else { return };
+        let start = (self.line, self.column);
+        let linewise = text.ends_with('\n');
+        let end = if let Some(lines) = text.strip_suffix('\n') {
+            let lines = lines.split('\n').map(str::to_string).collect::<Vec<String>>();
+            let count = lines.len();
+            self.replace_lines(self.line, 0, lines, (self.line + count, self.column));
+            (self.line, 0)
+        } else {
+            self.insert_at_cursor(&text);
+            (self.line, self.column)
+        };
+        let start = if linewise { (start.0, 0) } else { start };
+        self.kills.yank = Some(Yank { index, start, end, linewise });
+    }
+
+    // Inserts a typed char, letting the enabled input filters rewrite it first
+    fn type_char(&mut self, typed: char) {
+        if self.filters.is_empty() {
+            return self.insert_at_cursor(&typed.to_string());
+        }
+
+        let before = self.buffer[self.line].chars().take(self.column).collect::<Vec<char>>();
+        let Some(rewrite) = filters::apply(&self.filters, self.filetype, &before, typed) else {
+            self.insert_at_cursor(&typed.to_string());
+            return;
+        };
+
+        let start = self.column.saturating_sub(rewrite.delete);
+        let mut chars = self.buffer[self.line].chars().collect::<Vec<char>>();
+        chars.splice(start..self.column, rewrite.insert.chars());
+        let column = start + rewrite.insert.chars().count();
+        self.replace_lines(self.line, 1, vec![chars.into_iter().collect()], (self.line, column));
+    }
+
+    // Types or backspaces at the cursor and every extra cursor as one change
+    fn edit_at_cursors(&mut self, delete: usize, insert: &str) {
+        let mut all = vec![(self.line, self.column)];
+        all.extend(&self.cursors);
+
+        let (first, lines, moved) = cursors::edit(&self.buffer, &all, delete, insert);
+        let count = lines.len();
+        self.replace_lines(first, count, lines, moved[0]);
+
+        self.cursors = moved[1..].iter().filter(|cursor| **cursor != moved[0]).copied().collect();
+        self.cursors.sort();
+        self.cursors.dedup();
+    }
+
+    // The whichkey lines for a key: the modes that are on and take it before the keymap, as process_key checks them,
+    // then what the keymap does with it
+    pub fn describe_key(&self, key: &Key) -> Vec<String> {
+        let mut lines = vec![];
+        if self.tree.as_ref().is_some_and(|tree| tree.focused) && !matches!(key, Key::Mouse(_)) {
+            lines.push(format!("file tree (focused): {}, before the keymap", tree::describe(self, key)));
+        }
+        if csv::separator(self.filetype).is_some() && self.var_is("align_columns")
+            && let Key::Left(modifiers) | Key::Right(modifiers) = key && *modifiers == (Modifiers { alt: true, ..Modifiers::NONE }) {
+            lines.push(format!("aligned columns: moves to the {} field, before the keymap", if matches!(key, Key::Right(_)) { "next" } else { "previous" }));
+        }
+        if self.filetype == "rebase" && self.var_is("rebase_keys") && let Key::Char(char) = key && let Some(description) = rebase::describe(*char) {
+            lines.push(format!("rebase todo list: {}, before the keymap", description));
+        }
+        lines.extend(self.keymap.describe(key));
+        lines
+    }
+
+    pub fn process_key(&mut self, key: Key) {
+        self.message.clear();
+
+        if let Key::Mouse(mouse) = key {
+            return mouse::handle(self, mouse);
+        }
+        if self.tree.as_ref().is_some_and(|tree| tree.focused) {
+            return tree::handle_key(self, key);
+        }
+        if let Some(separator) = csv::separator(self.filetype) && self.var_is("align_columns")
+            && let Key::Left(modifiers) | Key::Right(modifiers) = key && modifiers == (Modifiers { alt: true, ..Modifiers::NONE }) {
+            return self.step_column(separator, matches!(key, Key::Right(_)));
+        }
+        if self.filetype == "rebase" && self.var_is("rebase_keys") && let Key::Char(char) = key && rebase::handle_key(self, char) {
+            return;
+        }
+
+        let action = self.keymap.lookup_pressed(&key, self.keys.physical());
+        if !matches!(action, Some(Action::Yank | Action::YankPop)) {
+            self.kills.yank = None;
+        }
+
+        // With extra cursors, typing and backspace apply at all of them; anything else goes back to one cursor
+        if !self.cursors.is_empty() {
+            match (&key, action) {
+                (Key::Char(char_), None) if !char_.is_control() => return self.edit_at_cursors(0, &char_.to_string()),
+                (_, Some(Action::DeleteBack)) => return self.edit_at_cursors(1, ""),
+                (Key::Escape, _) => {
+                    self.cursors.clear();
+                    return;
+                }
+                _ => self.cursors.clear(),
+            }
+        }
+
+        // Shift+arrows extend the selection, as does any movement while selecting is toggled on. Anything else that
+        // moves or edits drops it
+        let shifted = key.modifiers().is_some_and(|modifiers| modifiers.shift);
+        if shifted || (self.selecting && action.is_some_and(|action| action.moves())) {
+            self.selection.get_or_insert((self.line, self.column));
+        } else if !action.is_some_and(|action| action.keeps_selection()) {
+            self.selection = None;
+            self.selecting = false;
+        }
+
+        match action {
+            Some(action) => self.run_action(action),
+            None => match key {
+                Key::Char(char_) if !char_.is_control() => self.type_char(char_),
+                _ => {
+                    // Uncomment this to figure out escape sequences for things like function keys
+                    // panic!("Unhandled key: {:?}", key);
+                }
+            }
+        }
+
+        // Deleting the selection also ends selecting
+        self.selecting &= self.selection.is_some();
+    }
+
+    fn undo_or_redo(&mut self, redo: bool) {
+        let change = if redo { self.history.redo(&mut self.buffer) } else { self.history.undo(&mut self.buffer) };
+
+        match change {
+            Some(change) => {
+                let (old_lines, new_lines) = if redo { (&change.old, &change.new) } else { (&change.new, &change.old) };
+                collab::changed(self, change.start, old_lines, new_lines, change.at);
+                let (old, new) = (old_lines.len(), new_lines.len());
+                self.folds.shift(change.start, old, new);
+                (self.line, self.column) = if redo { change.cursor_after } else { change.cursor_before };
+                self.line = self.line.min(self.buffer.len() - 1);
+                self.unsaved = true;
+                self.swap_stale = true;
+                self.message = format!("{} {}", if redo { "Redid" } else { "Undid" }, change.summary());
+            }
+            None => self.message = format!("Nothing to {}", if redo { "redo" } else { "undo" }),
+        }
+    }
+
+    // Reads the two chars of a digraph on the message row and inserts what they stand for
+    fn digraph(&mut self) {
+        let mut typed = Vec::<char>::new();
+        while typed.len() < 2 {
+            self.message = format!("Digraph: {}", typed.iter().collect::<String>());
+            if self.draw().is_err() {
+                self.message.clear();
+                return;
+            }
+
+            match self.read_key() {
+                Key::Char(char_) if !char_.is_control() => typed.push(char_),
+                _ => {
+                    self.message.clear();
+                    return;
+                }
+            }
+        }
+
+        match digraphs::lookup(&self.config.digraphs, typed[0], typed[1]) {
+            Some(text) => {
+                self.message.clear();
+                self.insert_at_cursor(&text);
+            }
+            None => self.message = format!("Unknown digraph {}{}", typed[0], typed[1]),
+        }
+    }
+
+    // Adds or removes one level of indentation on the selected lines, or the current line. A selection ending at the
+    // start of a line doesn't take that line in. The selection stays on the same text
+    fn indent_lines(&mut self, dedent: bool) {
+        let (first, last) = match self.selection_range() {
+            Some((start, end)) if end.0 > start.0 && end.1 == 0 => (start.0, end.0 - 1),
+            Some((start, end)) => (start.0, end.0),
+            None => (self.line, self.line),
+        };
+
+        self.history.seal();
+        let indent = self.indent_string();
+        let mut shifts = vec![];
+        let lines = self.buffer.lines(first..=last).map(|line| {
+            if dedent {
+                let spaces = line.chars().take(self.indent_width()).take_while(|char| *char == ' ').count();
+                let removed = if spaces == 0 && line.starts_with('\t') { 1 } else { spaces };
+                shifts.push(-(removed as isize));
+                line.chars().skip(removed).collect()
+            } else if line.trim().is_empty() {
+                // Blank lines don't get trailing whitespace
+                shifts.push(0);
+                line.clone()
+            } else {
+                shifts.push(indent.chars().count() as isize);
+                indent.clone() + line
+            }
+        }).collect::<Vec<String>>();
+
+        let shift = |(line, column): (usize, usize)| {
+            let by = if (first..=last).contains(&line) { shifts[line - first] } else { 0 };
+            (line, column.saturating_add_signed(by))
+        };
+        let (cursor, anchor) = (shift((self.line, self.column)), self.selection.map(shift));
+        self.replace_lines(first, last - first + 1, lines, cursor);
+        self.selection = anchor;
+    }
+
+    // Reads the char to jump to on the message row
+    fn find_char(&mut self, forward: bool, till: bool) {
+        self.message = format!("{} {}: ", if till { "Till" } else { "Find" }, if forward { "forward" } else { "back" });
+        if self.draw().is_err() {
+            self.message.clear();
+            return;
+        }
+        let key = self.read_key();
+        self.message.clear();
+        let Key::Char(target) = key.clone() else { return };
+        if target.is_control() {
+            return;
+        }
+
+        let find = CharFind { target, forward, till };
+        self.last_find = Some(find);
+        self.jump_to_char(find, false);
+    }
+
+    fn repeat_find(&mut self, reverse: bool) {
+        match self.last_find {
+            Some(find) => self.jump_to_char(if reverse { find.reversed() } else { find }, true),
+            None => self.message = "No character jump to repeat".to_string(),
+        }
+    }
+
+    fn jump_to_char(&mut self, find: CharFind, repeat: bool) {
+        match find.column(&self.buffer[self.line], self.column, repeat) {
+            Some(column) => self.column = column,
+            None => self.message = format!("No {:?} {} on this line", find.target, if find.forward { "after the cursor" } else { "before the cursor" }),
+        }
+    }
+
+    pub fn run_action(&mut self, action: Action) {
+        if !matches!(action, Action::DeleteBack | Action::DeleteForward | Action::DeleteWordBack | Action::Newline | Action::Indent) {
+            self.history.seal();
+        }
+
+        match action {
+            Action::DeleteBack => {
+                if self.column > 0 && self.buffer[self.line].chars().take(self.column).all(|char| char == ' ') {
+                    // In indentation made of spaces, go back to the previous indent stop rather than one space
+                    let width = self.indent_width();
+                    let column = (self.column - 1) / width * width;
+                    self.apply(Operation::DeleteRange { start: (self.line, column), end: (self.line, self.column) });
+                } else if self.column > 0 {
+                    let column = byte_index(&self.buffer[self.line], self.column).1;
+                    let start = graphemes::previous(&self.buffer[self.line], column);
+                    self.apply(Operation::DeleteRange { start: (self.line, start), end: (self.line, column) });
+                } else if self.line > 0 {
+                    self.apply(Operation::JoinLines { line: self.line - 1 });
+                }
+            },
+            Action::DeleteWordBack if self.column > 0 => {
+                let chars = self.buffer[self.line].chars().collect::<Vec<char>>();
+                let column = motions::word_back(&chars, self.column.min(chars.len()));
+                self.apply(Operation::DeleteRange { start: (self.line, column), end: (self.line, self.column) });
+            }
+            Action::DeleteForward => {
+                let length = self.buffer[self.line].chars().count();
+                if self.column < length {
+                    let end = graphemes::next(&self.buffer[self.line], self.column);
+                    self.apply(Operation::DeleteRange { start: (self.line, self.column), end: (self.line, end) });
+                } else {
+                    self.apply(Operation::JoinLines { line: self.line });
+                }
+            }
+            // At the start of a line it joins lines like backspace
+            Action::DeleteWordBack => self.run_action(Action::DeleteBack),
+            // The new line keeps the current one's indentation, as much of it as is before the cursor
+            Action::Newline => {
+                let current = &self.buffer[self.line];
+                let mut indent = current.chars().take_while(|char| *char == ' ' || *char == '\t').take(self.column).collect::<String>();
+                if self.var_is("smart_indent") && filetype::opens_block(self.filetype, &current[..byte_index(current, self.column).0]) {
+                    indent += &self.indent_string();
+                }
+                self.apply(Operation::SplitLine { at: (self.line, self.column), indent });
+            }
+            Action::Indent if self.selection.is_some() => self.indent_lines(false),
+            Action::Indent => {
+                let indent = self.indent_string();
+                self.insert_at_cursor(&indent);
+            }
+            Action::Dedent => self.indent_lines(true),
+            Action::ToggleSelection => {
+                self.selecting = !self.selecting;
+                self.selection = self.selecting.then_some((self.line, self.column));
+                self.message = if self.selecting { "Selecting, move to extend" } else { "Selection cleared" }.to_string();
+            }
+            Action::Undo => self.undo_or_redo(false),
+            Action::Redo => self.undo_or_redo(true),
+            Action::Command => {
+                if let Some(command) = self.prompt("Command: ") {
+                    commands::run(self, &command);
+                }
+            }
+            Action::Digraph => self.digraph(),
+            Action::Replace => replace::prompt_and_confirm(self),
+            // After the last pane comes the file tree, when it is shown
+            Action::NextPane => match &mut self.tree {
+                Some(tree) if self.focused + 1 == self.panes.len() => tree.focused = true,
+                _ => self.focus((self.focused + 1) % self.panes.len()),
+            },
+            Action::Delete => self.delete(),
+            Action::FindChar => self.find_char(true, false),
+            Action::FindCharBack => self.find_char(false, false),
+            Action::TillChar => self.find_char(true, true),
+            Action::TillCharBack => self.find_char(false, true),
+            Action::RepeatFind => self.repeat_find(false),
+            Action::RepeatFindBack => self.repeat_find(true),
+            Action::Copy => self.copy(),
+            Action::Paste => self.paste(),
+            Action::Cut => self.cut(),
+            Action::KillToEnd => self.kill_to_end(),
+            Action::KillToStart => self.kill_to_start(),
+            Action::Yank => self.yank(),
+            Action::YankPop => self.yank_pop(),
+            Action::Quit => self.quit_asking(),
+            // A scratch buffer has no path of its own yet, so saving it asks for one
+            Action::SaveAs => self.prompt_save_as(),
+            Action::Save if self.scratch => self.prompt_save_as(),
+            Action::Save => {
+                self.save();
+                if self.unsaved {
+                    return;
+                }
+                if self.message.is_empty() {
+                    self.message = format!("Saved {} line{}", self.buffer.len(), if self.buffer.len() == 1 { "" } else { "s" });
+                }
+                hooks::after_save(self);
+            }
+            // Wrapped lines are moved through a screen row at a time
+            Action::RowUp | Action::RowDown if let Some(width) = self.focused_wrap_width() => {
+                self.move_rows(width, if action == Action::RowUp { -1 } else { 1 });
+            }
+            // Keeping the cursor in the same place on screen, which is a different column past wide chars or tabs
+            Action::MoveUp | Action::MoveDown | Action::RowUp | Action::RowDown => {
+                let stops = self.stops(self.view());
+                let cell = width::cell_of(&self.buffer[self.line], self.column, &stops);
+                self.line = self.step_visible(&self.folds.hidden(&self.buffer), self.line, if matches!(action, Action::MoveUp | Action::RowUp) { -1 } else { 1 });
+                self.column = width::column_at(&self.buffer[self.line], cell, &stops);
+            },
+            // By grapheme cluster, so an accent or emoji modifier moves with the char it is on
+            Action::MoveRight => {
+                self.column = graphemes::next(&self.buffer[self.line], self.column);
+            },
+            Action::MoveLeft => {
+                self.column = graphemes::previous(&self.buffer[self.line], self.column);
+            },
+            Action::LineStart => self.column = 0,
+            Action::LineEnd => self.column = self.buffer[self.line].chars().count(),
+            // The view moves with the cursor, so the lines that were at the bottom end up at the top
+            Action::PageUp | Action::PageDown => {
+                let page = self.text_height(get_screen_size().unwrap_or((1, 1))).saturating_sub(1).max(1) as isize;
+                let delta = if action == Action::PageUp { -page } else { page };
+                let hidden = self.folds.hidden(&self.buffer);
+                if let Some(width) = self.focused_wrap_width() {
+                    let start = (self.start_line, self.panes[self.focused].start_row);
+                    (self.start_line, self.panes[self.focused].start_row) = self.step_rows(&hidden, width, start, delta);
+                    self.move_rows(width, delta);
+                    return;
+                }
+                self.line = self.step_visible(&hidden, self.line, delta);
+                self.start_line = self.step_visible(&hidden, self.start_line, delta);
+                self.column = self.column.min(self.buffer[self.line].chars().count());
+            }
+            // Past either end of a line onto the next or previous one
+            Action::WordLeft if self.column == 0 && self.line > 0 => {
+                self.line -= 1;
+                self.column = self.buffer[self.line].chars().count();
+            }
+            Action::WordLeft => {
+                let chars = self.buffer[self.line].chars().collect::<Vec<char>>();
+                self.column = motions::word_back(&chars, self.column.min(chars.len()));
+            }
+            Action::WordRight => {
+                let chars = self.buffer[self.line].chars().collect::<Vec<char>>();
+                if self.column >= chars.len() && self.line + 1 < self.buffer.len() {
+                    self.line += 1;
+                    self.column = 0;
+                } else {
+                    self.column = motions::word_forward(&chars, self.column);
+                }
+            }
+        }
+    }
+
+    pub fn scroll(&mut self) {
+        let height = self.text_height(get_screen_size().unwrap_or((1, 1)));
+        let margin = self.config.scroll_margin.min(height / 2);
+
+        // A cursor that ended up inside a closed fold (after a search or jump) opens it
+        let mut hidden = self.folds.hidden(&self.buffer);
+        while let Some((first, _)) = hidden.iter().find(|(first, last)| (*first..=*last).contains(&self.line)) {
+            self.folds.open(first - 1);
+            hidden = self.folds.hidden(&self.buffer);
+        }
+
+        if let Some(width) = self.focused_wrap_width() {
+            self.scroll_wrapped(&hidden, width, height, margin);
+            return;
+        }
+
+        if self.config.typewriter_scrolling {
+            // Rows left empty above the first line or below the last are fine here, the cursor line stays put
+            self.start_line = self.step_visible(&hidden, self.line, -((height / 2) as isize));
+            return;
+        }
+
+        let rows_above = Editor::rows_between(&hidden, self.start_line, self.line);
+        if self.line < self.start_line || rows_above < margin {
+            self.start_line = self.step_visible(&hidden, self.line, -(margin as isize));
+        } else if rows_above >= height - margin {
+            self.start_line = self.step_visible(&hidden, self.line, -((height - margin - 1) as isize));
+        }
+
+        // Don't leave empty rows at the bottom when the buffer could fill them
+        let last = Editor::visible_line(&hidden, self.buffer.len() - 1);
+        self.start_line = Editor::visible_line(&hidden, self.start_line.min(self.step_visible(&hidden, last, -(height as isize - 1))));
+    }
+
+    // Scrolling by screen rows rather than lines, since a wrapped line can be taller than the pane
+    fn scroll_wrapped(&mut self, hidden: &[(usize, usize)], width: usize, height: usize, margin: usize) {
+        let stops = self.stops(self.view());
+        let cursor = (self.line, wrap::row_of(&wrap::rows(&self.buffer[self.line], width, &stops), self.column));
+        let start_line = Editor::visible_line(hidden, self.start_line.min(self.buffer.len() - 1));
+        let mut start = (start_line, self.panes[self.focused].start_row.min(wrap::rows(&self.buffer[start_line], width, &stops).len() - 1));
+
+        if self.config.typewriter_scrolling {
+            start = self.step_rows(hidden, width, cursor, -((height / 2) as isize));
+        } else if cursor < start {
+            start = self.step_rows(hidden, width, cursor, -(margin as isize));
+        } else {
+            let rows_above = self.rows_from(hidden, width, start, cursor, height);
+            if rows_above < margin {
+                start = self.step_rows(hidden, width, cursor, -(margin as isize));
+            } else if rows_above >= height - margin {
+                start = self.step_rows(hidden, width, cursor, -((height - margin - 1) as isize));
+            }
+        }
+
+        if !self.config.typewriter_scrolling {
+            // Don't leave empty rows at the bottom when the buffer could fill them
+            let last = Editor::visible_line(hidden, self.buffer.len() - 1);
+            let last = (last, wrap::rows(&self.buffer[last], width, &stops).len() - 1);
+            start = start.min(self.step_rows(hidden, width, last, -(height as isize - 1)));
+        }
+        (self.start_line, self.panes[self.focused].start_row) = start;
+    }
+}
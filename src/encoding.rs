@@ -0,0 +1,163 @@
+use std::fmt;
+
+use crate::text::TextBuffer;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum LineEnding {
+    Lf,
+    Crlf,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Encoding {
+    Utf8,
+    Latin1,
+}
+
+impl LineEnding {
+    pub fn parse(name: &str) -> Option<LineEnding> {
+        match name.to_lowercase().as_str() {
+            "lf" | "unix" => Some(LineEnding::Lf),
+            "crlf" | "dos" => Some(LineEnding::Crlf),
+            _ => None,
+        }
+    }
+
+    fn separator(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::Crlf => "\r\n",
+        }
+    }
+}
+
+impl Encoding {
+    pub fn parse(name: &str) -> Option<Encoding> {
+        match name.to_lowercase().replace('_', "-").as_str() {
+            "utf-8" | "utf8" => Some(Encoding::Utf8),
+            "latin-1" | "latin1" | "iso-8859-1" => Some(Encoding::Latin1),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for LineEnding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", match self {
+            LineEnding::Lf => "LF",
+            LineEnding::Crlf => "CRLF",
+        })
+    }
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", match self {
+            Encoding::Utf8 => "utf-8",
+            Encoding::Latin1 => "latin-1",
+        })
+    }
+}
+
+// Bytes that aren't valid UTF-8 are kept in the buffer as chars from the end of the last private use plane,
+// so they survive editing and are written back unchanged
+const ESCAPED_BYTES: u32 = 0x10FF00;
+
+fn escape_byte(byte: u8) -> char {
+    char::from_u32(ESCAPED_BYTES + byte as u32).unwrap_or(char::REPLACEMENT_CHARACTER)
+}
+
+pub fn escaped_byte(char: char) -> Option<u8> {
+    (char as u32).checked_sub(ESCAPED_BYTES).and_then(|byte| u8::try_from(byte).ok())
+}
+
+// How a char is shown on screen when it can't be printed as is: control chars as their caret letter
+// (NUL is @) and escaped bytes as U+FFFD, in magenta so they stand out from the text
+pub fn visible(char: char) -> Option<String> {
+    let shown = match char {
+        '\t' => return None,
+        char if escaped_byte(char).is_some() => char::REPLACEMENT_CHARACTER,
+        char if (char as u32) < 0x20 => ((char as u8) ^ 0x40) as char,
+        '\x7f' => '?',
+        _ => return None,
+    };
+    Some(format!("\x1b[35m{}\x1b[39m", shown))
+}
+
+fn decode_escaped(mut bytes: &[u8]) -> String {
+    let mut text = String::new();
+    loop {
+        match std::str::from_utf8(bytes) {
+            Ok(valid) => return text + valid,
+            Err(err) => {
+                let (valid, rest) = bytes.split_at(err.valid_up_to());
+                text += std::str::from_utf8(valid).unwrap_or_default();
+                let invalid = err.error_len().unwrap_or(rest.len());
+                text.extend(rest[..invalid].iter().map(|byte| escape_byte(*byte)));
+                bytes = &rest[invalid..];
+            }
+        }
+    }
+}
+
+// How much of a file is looked at to guess whether it is binary
+pub const BINARY_SAMPLE: usize = 8192;
+
+// Latin-1 text only has printable high bytes; NUL or other control bytes mean a binary file
+fn looks_binary(bytes: &[u8]) -> bool {
+    bytes.iter().any(|byte| matches!(byte, 0..=8 | 14..=26 | 28..=31 | 0x7f..=0x9f))
+}
+
+// Whether the start of a file, `sample`, looks like binary data rather than text: it has NUL bytes, or isn't
+// UTF-8 and has control bytes. A char cut off at the end of the sample doesn't count as invalid
+pub fn sample_looks_binary(sample: &[u8]) -> bool {
+    sample.contains(&0) || match std::str::from_utf8(sample) {
+        Err(err) if err.error_len().is_some() => looks_binary(sample),
+        _ => false,
+    }
+}
+
+// Splits file contents into lines. Text that isn't valid UTF-8 is read as Latin-1 unless it looks binary, in which
+// case the invalid bytes are escaped. A file where most line breaks are \r\n is treated as CRLF with the \r left
+// out of the lines, so the odd bare \n in it is saved as \r\n too
+pub fn decode(bytes: &[u8]) -> (Vec<String>, LineEnding, Encoding) {
+    let (text, encoding) = match std::str::from_utf8(bytes) {
+        Ok(text) => (text.to_string(), Encoding::Utf8),
+        Err(_) if looks_binary(bytes) => (decode_escaped(bytes), Encoding::Utf8),
+        Err(_) => (bytes.iter().map(|byte| *byte as char).collect(), Encoding::Latin1),
+    };
+
+    let breaks = text.matches('\n').count();
+    let line_ending = if breaks > 0 && text.matches("\r\n").count() * 2 > breaks { LineEnding::Crlf } else { LineEnding::Lf };
+
+    let mut lines = text.split('\n').map(str::to_string).collect::<Vec<String>>();
+    if line_ending == LineEnding::Crlf {
+        let last = lines.len() - 1;
+        for line in &mut lines[..last] {
+            if line.ends_with('\r') {
+                line.pop();
+            }
+        }
+    }
+    (lines, line_ending, encoding)
+}
+
+// The bytes to write for a buffer, and how many chars the encoding couldn't represent (written as '?')
+pub fn encode(lines: &impl TextBuffer, line_ending: LineEnding, encoding: Encoding) -> (Vec<u8>, usize) {
+    let text = lines.join(line_ending.separator());
+    let mut bytes = Vec::with_capacity(text.len());
+    let mut lost = 0;
+
+    for char in text.chars() {
+        match (escaped_byte(char), encoding) {
+            (Some(byte), _) => bytes.push(byte),
+            (None, Encoding::Utf8) => bytes.extend(char.encode_utf8(&mut [0; 4]).as_bytes()),
+            (None, Encoding::Latin1) => bytes.push(u8::try_from(char).unwrap_or_else(|_| {
+                lost += 1;
+                b'?'
+            })),
+        }
+    }
+
+    (bytes, lost)
+}
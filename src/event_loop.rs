@@ -0,0 +1,104 @@
+use std::{sync::{OnceLock, atomic::{AtomicI32, Ordering}, mpsc::{self, Receiver, Sender}}, thread, time::Instant};
+
+use crate::{editor::Editor, idle, input::{Key, key_pending}, terminal::poll_readable};
+
+// Work finished on another thread, applied to the editor on the main thread
+pub type BackgroundResult = Box<dyn FnOnce(&mut Editor) + Send>;
+
+pub enum Event {
+    Key(Key, Instant), // With when the key arrived, for latency measurement
+    Idle,
+    Signal(i32),
+    Resize,
+    Background(BackgroundResult),
+}
+
+// Signals that end the editor; it writes a swap file so nothing unsaved is lost
+const SIGNALS: &[i32] = &[libc::SIGTERM, libc::SIGHUP];
+
+// Signal handlers and background threads wake the loop by writing to this pipe: the signal number, or 0 for a
+// background result
+static WAKE_WRITE: AtomicI32 = AtomicI32::new(-1);
+static RESULTS: OnceLock<Sender<BackgroundResult>> = OnceLock::new();
+
+extern "C" fn on_signal(signal: libc::c_int) {
+    let byte = signal as u8;
+    unsafe { libc::write(WAKE_WRITE.load(Ordering::Relaxed), &byte as *const u8 as *const libc::c_void, 1) };
+}
+
+fn wake(byte: u8) {
+    unsafe { libc::write(WAKE_WRITE.load(Ordering::Relaxed), &byte as *const u8 as *const libc::c_void, 1) };
+}
+
+// Runs `work` on another thread and hands its result to `done` on the main thread
+pub fn spawn<T: Send + 'static>(work: impl FnOnce() -> T + Send + 'static, done: impl FnOnce(&mut Editor, T) + Send + 'static) {
+    let Some(results) = RESULTS.get().cloned() else { return };
+
+    thread::spawn(move || {
+        let result = work();
+        if results.send(Box::new(move |editor: &mut Editor| done(editor, result))).is_ok() {
+            wake(0);
+        }
+    });
+}
+
+// Hands work to the main thread from a thread that keeps running, like the one serving the control socket
+pub fn send(result: BackgroundResult) {
+    if let Some(results) = RESULTS.get() && results.send(result).is_ok() {
+        wake(0);
+    }
+}
+
+pub struct EventLoop {
+    wake_read: i32,
+    results: Receiver<BackgroundResult>,
+    signals: Vec<i32>, // Read from the pipe but not yet returned
+}
+
+impl EventLoop {
+    pub fn new() -> EventLoop {
+        let mut fds = [-1; 2];
+        unsafe { libc::pipe(fds.as_mut_ptr()) };
+        WAKE_WRITE.store(fds[1], Ordering::Relaxed);
+
+        let (sender, results) = mpsc::channel();
+        let _ = RESULTS.set(sender);
+
+        // The terminal changing size is a signal too, so the next frame fits without waiting for a key
+        for signal in SIGNALS.iter().chain(&[libc::SIGWINCH]) {
+            unsafe { libc::signal(*signal, on_signal as *const () as libc::sighandler_t) };
+        }
+
+        EventLoop { wake_read: fds[0], results, signals: vec![] }
+    }
+
+    // Waits for the next thing to handle. Idle work runs when no input arrives before it is due
+    pub fn next(&mut self, editor: &mut Editor) -> Event {
+        loop {
+            if let Some(signal) = self.signals.pop() {
+                return if signal == libc::SIGWINCH { Event::Resize } else { Event::Signal(signal) };
+            }
+
+            if let Ok(result) = self.results.try_recv() {
+                return Event::Background(result);
+            }
+
+            if key_pending() {
+                return Event::Key(editor.read_key(), Instant::now());
+            }
+
+            let timeout = idle::timeout(editor);
+            let ready = poll_readable(&[libc::STDIN_FILENO, self.wake_read], timeout);
+            if ready[1] {
+                let mut bytes = [0u8; 64];
+                let count = unsafe { libc::read(self.wake_read, bytes.as_mut_ptr() as *mut libc::c_void, bytes.len()) };
+                self.signals.extend(bytes.iter().take(count.max(0) as usize).filter(|byte| **byte != 0).map(|byte| *byte as i32));
+            } else if ready[0] {
+                let arrived = Instant::now();
+                return Event::Key(editor.read_key(), arrived);
+            } else if timeout.is_some() {
+                return Event::Idle;
+            }
+        }
+    }
+}
@@ -0,0 +1,49 @@
+use std::{env, fs, os::unix::fs::PermissionsExt};
+
+use crate::editor::Editor;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ExecutablePolicy {
+    Always,
+    Never,
+    Ask,
+}
+
+// Chosen with $TINYEDITOR_SHEBANG_EXEC (always/never/ask), defaulting to ask
+pub fn policy() -> ExecutablePolicy {
+    match env::var("TINYEDITOR_SHEBANG_EXEC").unwrap_or_default().to_lowercase().as_str() {
+        "always" => ExecutablePolicy::Always,
+        "never" => ExecutablePolicy::Never,
+        _ => ExecutablePolicy::Ask,
+    }
+}
+
+// Called after a save: if the buffer starts with a shebang and the file isn't executable yet, set the
+// executable bit according to the policy. Asks at most once per session
+pub fn check_after_save(editor: &mut Editor) {
+    if editor.scratch || editor.executable_checked || !editor.buffer[0].starts_with("#!") {
+        return;
+    }
+
+    let Ok(metadata) = fs::metadata(&editor.path) else { return };
+    let mode = metadata.permissions().mode();
+    if mode & 0o111 != 0 {
+        return;
+    }
+
+    editor.executable_checked = true;
+    let make_executable = match editor.executable_policy {
+        ExecutablePolicy::Always => true,
+        ExecutablePolicy::Never => false,
+        ExecutablePolicy::Ask => editor.prompt("File has a shebang, make it executable? (y/n): ").is_some_and(|answer| answer.trim().eq_ignore_ascii_case("y")),
+    };
+
+    if make_executable {
+        // Grant execute wherever read is granted, like chmod +x does under the usual umask
+        let new_mode = mode | ((mode & 0o444) >> 2);
+        editor.message = match fs::set_permissions(&editor.path, fs::Permissions::from_mode(new_mode)) {
+            Ok(()) => format!("Made {} executable", editor.path.display()),
+            Err(err) => format!("Unable to make {} executable: {}", editor.path.display(), err),
+        };
+    }
+}
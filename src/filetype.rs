@@ -0,0 +1,68 @@
+use std::path::Path;
+
+use crate::{gitcommit, rebase};
+
+// Extension to filetype name, for anything that behaves differently per language
+const EXTENSIONS: &[(&str, &str)] = &[
+    ("txt", "text"),
+    ("md", "markdown"),
+    ("markdown", "markdown"),
+    ("rs", "rust"),
+    ("c", "c"),
+    ("h", "c"),
+    ("py", "python"),
+    ("sh", "shell"),
+    ("toml", "toml"),
+    ("json", "json"),
+    ("css", "css"),
+    ("scss", "css"),
+    ("yaml", "yaml"),
+    ("yml", "yaml"),
+    ("csv", "csv"),
+    ("tsv", "tsv"),
+];
+
+// How the run command runs a file of each filetype, with {} standing for its path
+const RUNNERS: &[(&str, &str)] = &[
+    ("python", "python3 {}"),
+    ("shell", "sh {}"),
+    ("rust", "cargo run"),
+];
+
+pub fn detect(path: &Path, scratch: bool) -> &'static str {
+    if scratch {
+        return "text";
+    }
+
+    if path.file_name().is_some_and(|name| gitcommit::NAMES.iter().any(|known| name == *known)) {
+        return "gitcommit";
+    }
+    if path.file_name().is_some_and(|name| name == rebase::NAME) {
+        return "rebase";
+    }
+    let extension = path.extension().map(|extension| extension.to_string_lossy().to_lowercase()).unwrap_or_default();
+    EXTENSIONS.iter().find(|(known, _)| *known == extension).map_or("", |(_, filetype)| filetype)
+}
+
+// A filetype by name, as the filetype variable is set. Empty is no filetype
+pub fn find(name: &str) -> Option<&'static str> {
+    EXTENSIONS.iter().map(|(_, filetype)| *filetype).chain(["gitcommit", "rebase", ""]).find(|filetype| *filetype == name)
+}
+
+// Filetypes that are written as prose rather than code
+pub fn is_prose(filetype: &str) -> bool {
+    matches!(filetype, "text" | "markdown")
+}
+
+// Whether a line ends by opening a block, so the line after it goes one level deeper
+pub fn opens_block(filetype: &str, line: &str) -> bool {
+    match line.trim_end().chars().last() {
+        Some('{' | '[' | '(') => !is_prose(filetype),
+        Some(':') => matches!(filetype, "python" | "yaml"),
+        _ => false,
+    }
+}
+
+pub fn runner(filetype: &str) -> Option<&'static str> {
+    RUNNERS.iter().find(|(known, _)| *known == filetype).map(|(_, command)| *command)
+}
@@ -0,0 +1,43 @@
+use crate::filetype;
+
+// What a filter does with a typed char: delete this many chars before the cursor, then insert the text
+pub struct Rewrite {
+    pub delete: usize,
+    pub insert: String,
+}
+
+// An input filter sees the text before the cursor and the char being typed, and can rewrite it
+pub type InputFilter = fn(before: &[char], typed: char) -> Option<Rewrite>;
+type FiletypeCheck = fn(&str) -> bool;
+
+// Every filter with the filetypes it applies to. Filters start off and are toggled with the filter command
+pub const FILTERS: &[(&str, FiletypeCheck, InputFilter)] = &[
+    ("smartquotes", filetype::is_prose, smart_quotes),
+];
+
+pub fn find(name: &str) -> Option<&'static str> {
+    FILTERS.iter().find(|(known, _, _)| *known == name).map(|(known, _, _)| *known)
+}
+
+// Runs the enabled filters that apply to the filetype, stopping at the first one that rewrites the char
+pub fn apply(enabled: &[&str], filetype: &str, before: &[char], typed: char) -> Option<Rewrite> {
+    FILTERS.iter()
+        .filter(|(name, applies, _)| enabled.contains(name) && applies(filetype))
+        .find_map(|(_, _, filter)| filter(before, typed))
+}
+
+// Straight quotes become curly ones, opening after whitespace or an opening bracket and closing otherwise.
+// Two hyphens become an en dash and a third turns it into an em dash
+fn smart_quotes(before: &[char], typed: char) -> Option<Rewrite> {
+    let previous = before.last().copied();
+    let opening = previous.is_none_or(|char| char.is_whitespace() || "([{“‘".contains(char));
+    let rewrite = |delete: usize, insert: char| Some(Rewrite { delete, insert: insert.to_string() });
+
+    match (typed, previous) {
+        ('"', _) => rewrite(0, if opening { '“' } else { '”' }),
+        ('\'', _) => rewrite(0, if opening { '‘' } else { '’' }),
+        ('-', Some('-')) => rewrite(1, '–'),
+        ('-', Some('–')) => rewrite(1, '—'),
+        _ => None,
+    }
+}
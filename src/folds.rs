@@ -0,0 +1,133 @@
+use std::{collections::BTreeSet, fs, path::{Path, PathBuf}};
+
+use crate::{paths, text::TextBuffer};
+
+// Folds follow indentation: a line followed by more deeply indented lines can be folded to hide them.
+// Only the header lines of closed folds are stored, the extent is worked out from the text when needed
+#[derive(Default)]
+pub struct Folds {
+    closed: BTreeSet<usize>,
+}
+
+fn indent(line: &str) -> Option<usize> {
+    if line.trim().is_empty() {
+        return None;
+    }
+
+    Some(line.chars().take_while(|char| char.is_whitespace()).map(|char| if char == '\t' { 4 } else { 1 }).sum())
+}
+
+// The last line of the fold that `line` heads, if the lines after it are indented deeper. Blank lines inside
+// the fold belong to it, trailing ones don't
+pub fn region_end(buffer: &impl TextBuffer, line: usize) -> Option<usize> {
+    let header = indent(&buffer[line])?;
+    let mut end = None;
+
+    for (index, text) in buffer.iter().enumerate().skip(line + 1) {
+        match indent(text) {
+            Some(depth) if depth > header => end = Some(index),
+            Some(_) => break,
+            None => {}
+        }
+    }
+
+    end
+}
+
+// Every fold in the buffer as (header, end, depth), where depth counts the folds around it
+pub fn regions(buffer: &impl TextBuffer) -> Vec<(usize, usize, usize)> {
+    let mut regions: Vec<(usize, usize, usize)> = vec![];
+    for line in 0..buffer.len() {
+        if let Some(end) = region_end(buffer, line) {
+            let depth = regions.iter().filter(|(start, outer_end, _)| *start < line && *outer_end >= end).count();
+            regions.push((line, end, depth));
+        }
+    }
+    regions
+}
+
+impl Folds {
+    pub fn is_closed(&self, line: usize) -> bool {
+        self.closed.contains(&line)
+    }
+
+    pub fn close(&mut self, line: usize) {
+        self.closed.insert(line);
+    }
+
+    pub fn open(&mut self, line: usize) {
+        self.closed.remove(&line);
+    }
+
+    pub fn clear(&mut self) {
+        self.closed.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.closed.is_empty()
+    }
+
+    // Line ranges (first, last) hidden by closed folds, sorted and not overlapping
+    pub fn hidden(&self, buffer: &impl TextBuffer) -> Vec<(usize, usize)> {
+        let mut hidden: Vec<(usize, usize)> = vec![];
+        for header in &self.closed {
+            if hidden.last().is_some_and(|(_, last)| header <= last) || *header >= buffer.len() {
+                continue;
+            }
+            if let Some(end) = region_end(buffer, *header) {
+                hidden.push((header + 1, end));
+            }
+        }
+        hidden
+    }
+
+    // Keeps folds on the same text when `old` lines at `start` are replaced by `new` lines. Folds whose header was
+    // replaced are dropped
+    pub fn shift(&mut self, start: usize, old: usize, new: usize) {
+        self.closed = self.closed.iter().filter_map(|line| match *line {
+            line if line < start => Some(line),
+            line if line < start + old => None,
+            line => Some(line + new - old),
+        }).collect();
+    }
+}
+
+fn folds_file() -> Option<PathBuf> {
+    paths::state_dir().map(|dir| dir.join("folds"))
+}
+
+// The folds file holds one "line,line,... /path" entry per file
+fn load_all() -> Vec<(Vec<usize>, PathBuf)> {
+    let Some(text) = folds_file().and_then(|file| fs::read_to_string(file).ok()) else { return vec![] };
+
+    text.lines().filter_map(|line| {
+        let (lines, path) = line.split_once(' ')?;
+        let lines = lines.split(',').filter(|line| !line.is_empty()).map(|line| line.parse().ok()).collect::<Option<Vec<usize>>>()?;
+        Some((lines, PathBuf::from(path)))
+    }).collect()
+}
+
+pub fn load(path: &Path) -> Folds {
+    let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let closed = load_all().into_iter().find(|(_, known)| *known == path).map(|(lines, _)| lines).unwrap_or_default();
+    Folds { closed: closed.into_iter().collect() }
+}
+
+pub fn save(path: &Path, folds: &Folds) -> std::io::Result<()> {
+    let file = folds_file().ok_or(std::io::Error::other("no state directory"))?;
+    let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+
+    let mut entries = load_all();
+    entries.retain(|(_, known)| *known != path);
+    if !folds.is_empty() {
+        entries.push((folds.closed.iter().copied().collect(), path));
+    }
+
+    if let Some(parent) = file.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let text = entries.iter().map(|(lines, path)| {
+        format!("{} {}\n", lines.iter().map(usize::to_string).collect::<Vec<String>>().join(","), path.display())
+    }).collect::<String>();
+    fs::write(file, text)
+}
@@ -0,0 +1,63 @@
+use crate::{text::TextBuffer, width};
+
+// Git's conventions for commit messages: a subject of at most 50 columns, a blank line, then a body wrapped at 72.
+// Lines starting with # are comments git strips, and everything after the scissors line is the diff from
+// `git commit -v`
+const SUBJECT: usize = 50;
+const BODY: usize = 72;
+const SCISSORS: &str = "# ------------------------ >8 ------------------------";
+
+// Files git opens in core.editor for a message
+pub const NAMES: &[&str] = &["COMMIT_EDITMSG", "MERGE_MSG", "TAG_EDITMSG", "SQUASH_MSG"];
+
+pub fn is_comment(line: &str) -> bool {
+    line.starts_with('#')
+}
+
+// The message lines, up to the scissors line
+fn message_end(buffer: &impl TextBuffer) -> usize {
+    buffer.iter().position(|line| line == SCISSORS).unwrap_or(buffer.len())
+}
+
+// How wide a line may be: the subject is the first line that isn't a comment, the line after it should be blank,
+// and the body follows. None for comments, empty lines and the diff
+pub fn limit(buffer: &impl TextBuffer, line: usize) -> Option<usize> {
+    if line >= message_end(buffer) || is_comment(&buffer[line]) || buffer[line].is_empty() {
+        return None;
+    }
+    let subject = buffer.iter().position(|line| !is_comment(line))?;
+    match line {
+        line if line == subject => Some(SUBJECT),
+        line if line == subject + 1 => None,
+        _ => Some(BODY),
+    }
+}
+
+// The body with its long lines broken at spaces to fit 72 columns, or None when nothing needs wrapping. Indented
+// lines are left as they are, as they tend to be code or output pasted in
+pub fn wrap_body(buffer: &impl TextBuffer) -> Option<Vec<String>> {
+    let end = message_end(buffer);
+    let mut wrapped = Vec::with_capacity(buffer.len());
+    let mut changed = false;
+    for (index, line) in buffer.lines(..end).enumerate() {
+        if limit(buffer, index) != Some(BODY) || width::text_width(line) <= BODY || line.starts_with([' ', '\t']) {
+            wrapped.push(line.clone());
+            continue;
+        }
+
+        let mut current = String::new();
+        for word in line.split(' ').filter(|word| !word.is_empty()) {
+            if !current.is_empty() && width::text_width(&current) + 1 + width::text_width(word) > BODY {
+                wrapped.push(std::mem::take(&mut current));
+            }
+            if !current.is_empty() {
+                current.push(' ');
+            }
+            current += word;
+        }
+        wrapped.push(current);
+        changed = true;
+    }
+    wrapped.extend(buffer.lines(end..).cloned());
+    changed.then_some(wrapped)
+}
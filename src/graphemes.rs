@@ -0,0 +1,96 @@
+// Where the cursor can stop within a line: between grapheme clusters, the chars that show as one, such as a letter
+// and the accents combined with it, an emoji and its skin tone or variation selector, emoji joined by a ZWJ, and
+// pairs of regional indicators making a flag. A cut-down version of Unicode's segmentation rules, looking only at
+// the chars around the cursor so it stays fast on huge lines
+
+const ZWJ: char = '\u{200D}';
+
+// Chars that attach to the one before: combining marks, variation selectors, emoji modifiers and tags
+const EXTEND: &[(u32, u32)] = &[
+    (0x0300, 0x036F), // Combining diacritical marks
+    (0x0483, 0x0489),
+    (0x0591, 0x05BD), // Hebrew points
+    (0x05BF, 0x05C7),
+    (0x0610, 0x061A), // Arabic marks
+    (0x064B, 0x065F),
+    (0x0670, 0x0670),
+    (0x06D6, 0x06ED),
+    (0x0900, 0x0903), // Devanagari signs
+    (0x093A, 0x094F),
+    (0x0951, 0x0957),
+    (0x0962, 0x0963),
+    (0x0E31, 0x0E31), // Thai vowels and tones
+    (0x0E34, 0x0E3A),
+    (0x0E47, 0x0E4E),
+    (0x1160, 0x11FF), // Hangul medial vowels and final consonants
+    (0x1AB0, 0x1AFF),
+    (0x1DC0, 0x1DFF),
+    (0x200C, 0x200C), // Zero width non-joiner
+    (0x20D0, 0x20FF), // Combining marks for symbols, e.g. the keycap
+    (0x302A, 0x302F),
+    (0x3099, 0x309A), // Kana voicing marks
+    (0xFE00, 0xFE0F), // Variation selectors
+    (0xFE20, 0xFE2F),
+    (0x1F3FB, 0x1F3FF), // Skin tones
+    (0xE0020, 0xE007F), // Tags, as in subdivision flags
+    (0xE0100, 0xE01EF),
+];
+
+pub fn is_extend(char: char) -> bool {
+    let code = char as u32;
+    if code < 0x0300 {
+        return false;
+    }
+    let index = EXTEND.partition_point(|(_, last)| *last < code);
+    EXTEND.get(index).is_some_and(|(first, _)| *first <= code)
+}
+
+fn is_regional_indicator(char: char) -> bool {
+    ('\u{1F1E6}'..='\u{1F1FF}').contains(&char)
+}
+
+// Whether `after` is part of the same cluster as `before`, leaving regional indicators to the callers
+fn continues(before: char, after: char) -> bool {
+    is_extend(after) || after == ZWJ || (before == ZWJ && (after as u32) >= 0x2600)
+}
+
+// The column after the cluster starting at `column`
+pub fn next(line: &str, column: usize) -> usize {
+    let (byte, column) = crate::editor::byte_index(line, column);
+    let mut chars = line[byte..].chars().peekable();
+    let Some(mut before) = chars.next() else { return column };
+    let mut end = column + 1;
+    // A regional indicator pairs with the one after it
+    if is_regional_indicator(before) && chars.next_if(|char| is_regional_indicator(*char)).is_some() {
+        end += 1;
+    }
+    while let Some(char) = chars.next_if(|char| continues(before, *char)) {
+        before = char;
+        end += 1;
+    }
+    end
+}
+
+// The column the cluster ending at `column` starts at
+pub fn previous(line: &str, column: usize) -> usize {
+    let (byte, column) = crate::editor::byte_index(line, column);
+    let mut chars = line[..byte].chars().rev().peekable();
+    let Some(mut after) = chars.next() else { return column };
+    let mut start = column - 1;
+    while let Some(&before) = chars.peek() {
+        let joined = if is_regional_indicator(before) && is_regional_indicator(after) {
+            // Indicators pair up from the start of their run, so this one is the second of a pair when an odd
+            // number come before it
+            line[..byte].chars().rev().skip(column - start).take_while(|char| is_regional_indicator(*char)).count() % 2 == 1
+        } else {
+            continues(before, after)
+        };
+        if !joined {
+            break;
+        }
+        chars.next();
+        after = before;
+        start -= 1;
+    }
+    start
+}
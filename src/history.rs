@@ -0,0 +1,205 @@
+use std::{collections::VecDeque, mem};
+
+use crate::text::TextBuffer;
+
+// Every edit is recorded as "lines start..start+old.len() were replaced by new", which covers typing, joins,
+// splits, and whole-buffer rewrites like formatting with one representation. Edits within a line keep only the
+// text that changed, as the single old and new line, so typing into a huge line doesn't copy it for each key
+#[derive(Clone, Debug)]
+pub struct Change {
+    pub start: usize,
+    pub old: Vec<String>,
+    pub new: Vec<String>,
+    pub at: Option<usize>, // The byte of line `start` that old and new are text at, when they aren't whole lines
+    pub cursor_before: (usize, usize),
+    pub cursor_after: (usize, usize),
+}
+
+impl Change {
+    fn char_delta(&self) -> isize {
+        let count = |lines: &[String]| lines.iter().map(|line| line.chars().count()).sum::<usize>() as isize;
+        count(&self.new) - count(&self.old)
+    }
+
+    // Whether the change grows or shrinks the text, without counting chars, which is slow on very long lines
+    fn byte_delta(&self) -> isize {
+        let count = |lines: &[String]| lines.iter().map(String::len).sum::<usize>() as isize;
+        count(&self.new) - count(&self.old)
+    }
+
+    fn is_single_line(&self) -> bool {
+        self.old.len() == 1 && self.new.len() == 1
+    }
+
+    // A short human readable description, e.g. "insert of 12 chars on line 34"
+    pub fn summary(&self) -> String {
+        let plural = |count: usize, word: &str| format!("{} {}{}", count, word, if count == 1 { "" } else { "s" });
+        let line = self.start + 1;
+
+        if self.is_single_line() {
+            return match self.char_delta() {
+                delta if delta > 0 => format!("insert of {} on line {}", plural(delta as usize, "char"), line),
+                delta if delta < 0 => format!("delete of {} on line {}", plural(delta.unsigned_abs(), "char"), line),
+                _ => format!("change on line {}", line),
+            };
+        }
+
+        if self.new.len() > self.old.len() {
+            format!("insert of {} at line {}", plural(self.new.len() - self.old.len(), "line"), line)
+        } else if self.new.len() < self.old.len() {
+            format!("delete of {} at line {}", plural(self.old.len() - self.new.len(), "line"), line)
+        } else {
+            format!("change of lines {}-{}", line, self.start + self.new.len())
+        }
+    }
+
+    fn inverted(&self) -> Change {
+        Change {
+            start: self.start,
+            old: self.new.clone(),
+            new: self.old.clone(),
+            at: self.at,
+            cursor_before: self.cursor_after,
+            cursor_after: self.cursor_before,
+        }
+    }
+
+    // Roughly how much memory the change holds
+    fn size(&self) -> usize {
+        let lines = |lines: &[String]| lines.iter().map(|line| line.len() + std::mem::size_of::<String>()).sum::<usize>();
+        std::mem::size_of::<Change>() + lines(&self.old) + lines(&self.new)
+    }
+
+    pub fn apply(&self, buffer: &mut impl TextBuffer) {
+        match self.at {
+            Some(at) => {
+                buffer.edit_line(self.start, at..at + self.old[0].len(), &self.new[0]);
+            }
+            None => {
+                buffer.splice_lines(self.start, self.old.len(), self.new.clone());
+            }
+        }
+    }
+
+    // Takes in an edit that carries straight on from this one, e.g. the next typed char, returning false when it
+    // doesn't. Whole lines are replaced by the newer ones; text within a line only joins up where the edits touch
+    fn absorb(&mut self, change: &mut Change) -> bool {
+        match (self.at, change.at) {
+            (None, None) => self.new = mem::take(&mut change.new),
+            // Typed after the text this inserted
+            (Some(at), Some(next)) if self.old[0].is_empty() && change.old[0].is_empty() && next == at + self.new[0].len() => {
+                self.new[0].push_str(&change.new[0]);
+            }
+            // Backspaced over the text before what this deleted
+            (Some(at), Some(next)) if self.new[0].is_empty() && change.new[0].is_empty() && next + change.old[0].len() == at => {
+                self.old[0].insert_str(0, &change.old[0]);
+                self.at = Some(next);
+            }
+            // Deleted forward from the same place
+            (Some(at), Some(next)) if self.new[0].is_empty() && change.new[0].is_empty() && next == at => {
+                self.old[0].push_str(&change.old[0]);
+            }
+            _ => return false,
+        }
+        self.cursor_after = change.cursor_after;
+        true
+    }
+}
+
+#[derive(Default)]
+pub struct History {
+    undo: VecDeque<Change>, // Oldest first, so trimming takes from the front
+    redo: Vec<Change>,
+    // Set by anything that isn't typing (cursor movement, saving) so the next edit starts a new undo step
+    sealed: bool,
+    bytes: usize, // Total size of both stacks
+    trimmed: usize, // How many of the oldest steps were dropped to stay within the limits
+}
+
+impl History {
+    pub fn seal(&mut self) {
+        self.sealed = true;
+    }
+
+    // Consecutive single-line edits of the same kind at the cursor are merged, so undo removes a typed word
+    // rather than a single character
+    pub fn record(&mut self, mut change: Change) {
+        self.bytes -= self.redo.drain(..).map(|change| change.size()).sum::<usize>();
+
+        if !self.sealed && let Some(last) = self.undo.back_mut()
+            && last.is_single_line() && change.is_single_line()
+            && last.start == change.start && last.cursor_after == change.cursor_before
+            && last.byte_delta().signum() == change.byte_delta().signum() {
+            let size = last.size();
+            if last.absorb(&mut change) {
+                self.bytes = self.bytes - size + last.size();
+                return;
+            }
+        }
+
+        self.sealed = false;
+        self.bytes += change.size();
+        self.undo.push_back(change);
+    }
+
+    // Drops the oldest undo steps until the history fits in `max_steps` and `max_bytes`, returning how many went
+    pub fn trim(&mut self, max_steps: usize, max_bytes: usize) -> usize {
+        let mut dropped = 0;
+        while !self.undo.is_empty() && (self.undo.len() > max_steps || self.bytes > max_bytes) {
+            self.bytes -= self.undo.pop_front().map_or(0, |change| change.size());
+            dropped += 1;
+        }
+
+        self.trimmed += dropped;
+        dropped
+    }
+
+    pub fn trimmed(&self) -> usize {
+        self.trimmed
+    }
+
+    // Fits the undo steps around an edit someone else made to the shared buffer, which isn't one of ours to undo.
+    // Steps wholly above or below it are moved to where their lines now are, and the first one it overlaps is
+    // dropped with everything older, returning how many went. Redo steps are dropped as after any new edit
+    pub fn rebase(&mut self, change: &Change) -> usize {
+        self.bytes -= self.redo.drain(..).map(|change| change.size()).sum::<usize>();
+        self.sealed = true;
+        let (mut start, old) = (change.start, change.old.len());
+        let delta = change.new.len() as isize - old as isize;
+        let shift = |line: usize, from: usize| if line >= from { line.saturating_add_signed(delta) } else { line };
+
+        for index in (0..self.undo.len()).rev() {
+            let step = &mut self.undo[index];
+            if start + old <= step.start {
+                step.start = step.start.saturating_add_signed(delta);
+                step.cursor_before.0 = shift(step.cursor_before.0, start + old);
+                step.cursor_after.0 = shift(step.cursor_after.0, start + old);
+            } else if start >= step.start + step.new.len() {
+                start = (start + step.old.len()) - step.new.len();
+            } else {
+                let dropped = self.undo.drain(..=index).map(|change| change.size()).collect::<Vec<usize>>();
+                self.bytes -= dropped.iter().sum::<usize>();
+                self.trimmed += dropped.len();
+                return dropped.len();
+            }
+        }
+        0
+    }
+
+    // Reverts the last change, returning it so the caller can restore the cursor and describe it
+    pub fn undo(&mut self, buffer: &mut impl TextBuffer) -> Option<Change> {
+        let change = self.undo.pop_back()?;
+        change.inverted().apply(buffer);
+        self.redo.push(change.clone());
+        self.sealed = true;
+        Some(change)
+    }
+
+    pub fn redo(&mut self, buffer: &mut impl TextBuffer) -> Option<Change> {
+        let change = self.redo.pop()?;
+        change.apply(buffer);
+        self.undo.push_back(change.clone());
+        self.sealed = true;
+        Some(change)
+    }
+}
@@ -0,0 +1,47 @@
+use std::fs;
+
+use crate::{editor::Editor, shell, tasks, vars};
+
+// Runs the project's after_save commands one after another in the background, from the project root. {} in a
+// command stands for the saved file's path, and the buffer's variables are in the environment, see vars::env. Their
+// output goes to the message log and the first failure is reported
+pub fn after_save(editor: &mut Editor) {
+    if editor.config.after_save.is_empty() || editor.scratch {
+        return;
+    }
+
+    // The commands run from the project root, so relative paths wouldn't work
+    let path = fs::canonicalize(&editor.path).unwrap_or(editor.path.clone());
+    let path = shell::quote(&path.to_string_lossy());
+    let commands = editor.config.after_save.iter().map(|command| command.replace("{}", &path)).collect::<Vec<String>>();
+    let root = editor.project_root.clone();
+    let env = vars::env(&editor.all_vars());
+
+    let name = if commands.len() == 1 { format!("after_save: {}", commands[0]) } else { format!("after_save ({} commands)", commands.len()) };
+    tasks::spawn(editor, name, move || {
+        let mut results = vec![];
+        for command in commands {
+            let result = shell::run_with_env(&command, "", root.as_deref(), &env);
+            let failed = !matches!(&result, Ok(output) if output.status.success());
+            results.push((command, result));
+            if failed {
+                break;
+            }
+        }
+        results
+    }, |editor, results| {
+        for (command, result) in results {
+            editor.log(&format!("$ {}", command));
+            match result {
+                Ok(output) => {
+                    editor.log(&String::from_utf8_lossy(&output.stdout));
+                    editor.log(&String::from_utf8_lossy(&output.stderr));
+                    if !output.status.success() {
+                        editor.message = format!("after_save hook `{}` failed ({}): {}", command, output.status, shell::summary(&output));
+                    }
+                }
+                Err(err) => editor.message = format!("Unable to run after_save hook `{}`: {}", command, err),
+            }
+        }
+    });
+}
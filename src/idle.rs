@@ -0,0 +1,102 @@
+use std::{path::PathBuf, time::{Duration, Instant}};
+
+use crate::{config, editor::{Editor, RECENT_KEYS_TIMEOUT}, keymap::Keymap, segments, swap};
+
+// How long typing has to pause before the swap file is brought up to date
+const SWAP_DELAY: Duration = Duration::from_secs(1);
+
+// How often config files are checked for changes
+const CONFIG_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+// Work that is deferred until the user stops typing, so it never adds latency to a keystroke
+#[derive(Clone, Copy)]
+enum Task {
+    Autosave,
+    WriteSwap,
+    ExpireKeys,
+    ReloadConfig,
+    UpdateSegments,
+}
+
+const TASKS: [Task; 5] = [Task::Autosave, Task::WriteSwap, Task::ExpireKeys, Task::ReloadConfig, Task::UpdateSegments];
+
+// When the task should next run, or None if it has nothing to do
+fn deadline(editor: &Editor, task: Task) -> Option<Instant> {
+    match task {
+        // Scratch buffers are saved every autosave_interval seconds while they have changes
+        Task::Autosave => (editor.scratch && editor.unsaved && editor.config.autosave_interval > 0)
+            .then(|| editor.last_autosave + Duration::from_secs(editor.config.autosave_interval)),
+        Task::WriteSwap => (!editor.scratch && editor.swap_stale).then(|| editor.last_input + SWAP_DELAY),
+        Task::ExpireKeys => editor.show_keys.then(|| editor.recent_keys.iter()
+            .map(|(_, time)| *time + RECENT_KEYS_TIMEOUT)
+            .find(|expiry| *expiry > Instant::now()))?,
+        Task::ReloadConfig => (!editor.config_files.is_empty()).then(|| editor.config_checked + CONFIG_CHECK_INTERVAL),
+        // Segments that were just turned off still need clearing
+        Task::UpdateSegments => (editor.config.status_clock || editor.config.status_battery || !editor.segments.is_empty())
+            .then_some(editor.segments_due),
+    }
+}
+
+// How long the main loop can wait for input before some idle work is due
+pub fn timeout(editor: &Editor) -> Option<Duration> {
+    TASKS.iter().filter_map(|task| deadline(editor, *task)).min().map(|deadline| deadline.saturating_duration_since(Instant::now()))
+}
+
+// Runs whatever is due. Returns true if the screen needs redrawing
+pub fn run(editor: &mut Editor) -> bool {
+    let now = Instant::now();
+    let mut redraw = false;
+
+    for task in TASKS {
+        if deadline(editor, task).is_none_or(|deadline| deadline > now) {
+            continue;
+        }
+
+        match task {
+            Task::Autosave => {
+                editor.save();
+                editor.last_autosave = Instant::now();
+            }
+            Task::WriteSwap => {
+                if let Err(err) = swap::write(editor) {
+                    editor.message = format!("Unable to write swap file: {}", err);
+                    redraw = true;
+                }
+                editor.swap_stale = false;
+            }
+            Task::ExpireKeys => redraw = true,
+            Task::UpdateSegments => {
+                editor.segments = segments::render(&editor.config);
+                editor.segments_due = segments::next_update();
+                redraw = true;
+            }
+            Task::ReloadConfig => {
+                editor.config_checked = Instant::now();
+                if editor.config_files.iter().any(|(path, loaded)| config::modified(path) != *loaded) {
+                    reload_config(editor);
+                    redraw = true;
+                }
+            }
+        }
+    }
+
+    redraw
+}
+
+// Applies config files that changed on disk, reporting errors rather than keeping the old config
+fn reload_config(editor: &mut Editor) {
+    let files = editor.config_files.iter().map(|(path, _)| path.clone()).collect::<Vec<PathBuf>>();
+    let (config, error) = config::reload(&files);
+    editor.keymap = Keymap::with_config(&config.keys, &config.physical_keys, config.arrows_by_row);
+    editor.config = config;
+    editor.config_files = files.into_iter().map(|path| {
+        let modified = config::modified(&path);
+        (path, modified)
+    }).collect();
+    editor.message = match error {
+        Some(err) => format!("Config error: {}", err),
+        None => "Reloaded config".to_string(),
+    };
+    editor.load_theme();
+    editor.segments_due = Instant::now();
+}
@@ -0,0 +1,107 @@
+use std::{fs, path::Path};
+
+use crate::regex::Regex;
+
+// The files .gitignore and .ignore files leave out, for walking a project. A rule with a / before its end is
+// anchored to the directory of the file it is in, and one without matches a name at any depth below it. A trailing
+// / matches directories only, a leading ! takes a match back, and the last rule to match a path decides. Globs
+// support * and ? within a name, ** across directories, and [classes]
+const FILES: &[&str] = &[".gitignore", ".ignore"];
+
+struct Rule {
+    base: String, // The directory of the ignore file, relative to where the walk started, with a trailing /
+    pattern: Regex,
+    negated: bool,
+    dir_only: bool,
+}
+
+#[derive(Default)]
+pub struct Ignores {
+    rules: Vec<Rule>,
+}
+
+// A glob as a regex matching the whole of a relative path
+fn glob_regex(glob: &str) -> String {
+    let chars = glob.chars().collect::<Vec<char>>();
+    let mut regex = String::from("^");
+    let mut index = 0;
+    while index < chars.len() {
+        match chars[index] {
+            // **/ is any number of directories, including none, and ** anywhere else anything at all
+            '*' if chars.get(index + 1) == Some(&'*') && chars.get(index + 2) == Some(&'/') => {
+                regex += "(?:.*/)?";
+                index += 2;
+            }
+            '*' if chars.get(index + 1) == Some(&'*') => {
+                regex += ".*";
+                index += 1;
+            }
+            '*' => regex += "[^/]*",
+            '?' => regex += "[^/]",
+            '[' if let Some(length) = chars[index + 1..].iter().skip(1).position(|char| *char == ']') => {
+                let class = &chars[index + 1..index + 2 + length];
+                regex.push('[');
+                regex.extend(class.iter().enumerate().map(|(position, char)| if position == 0 && *char == '!' { '^' } else { *char }));
+                regex.push(']');
+                index += length + 2;
+            }
+            '\\' if index + 1 < chars.len() => {
+                index += 1;
+                regex += &literal(chars[index]);
+            }
+            char => regex += &literal(char),
+        }
+        index += 1;
+    }
+    regex + "$"
+}
+
+// A char matched as itself, escaping everything that could mean something to the regex
+fn literal(char: char) -> String {
+    if char.is_alphanumeric() { char.to_string() } else { format!("\\{}", char) }
+}
+
+fn parse(line: &str, base: &str) -> Option<Rule> {
+    let line = line.trim_end();
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+    let (negated, line) = line.strip_prefix('!').map_or((false, line), |rest| (true, rest));
+    let (dir_only, line) = line.strip_suffix('/').map_or((false, line), |rest| (true, rest));
+    let glob = match line.strip_prefix('/') {
+        Some(anchored) => anchored.to_string(),
+        None if line.contains('/') => line.to_string(),
+        None => format!("**/{}", line),
+    };
+    let pattern = Regex::new(&glob_regex(&glob), false).ok()?;
+    Some(Rule { base: base.to_string(), pattern, negated, dir_only })
+}
+
+impl Ignores {
+    // Adds the rules of a directory's ignore files, for walking into it, returning how many rules there were before
+    // so leave can drop them again on the way out. `relative` is the directory's path from where the walk started,
+    // empty for the start itself
+    pub fn enter(&mut self, dir: &Path, relative: &str) -> usize {
+        let before = self.rules.len();
+        let base = if relative.is_empty() { String::new() } else { format!("{}/", relative) };
+        for name in FILES {
+            if let Ok(text) = fs::read_to_string(dir.join(name)) {
+                self.rules.extend(text.lines().filter_map(|line| parse(line, &base)));
+            }
+        }
+        before
+    }
+
+    pub fn leave(&mut self, before: usize) {
+        self.rules.truncate(before);
+    }
+
+    // Whether a path relative to where the walk started is left out
+    pub fn is_ignored(&self, relative: &str, is_dir: bool) -> bool {
+        self.rules.iter().rev().filter(|rule| is_dir || !rule.dir_only).find_map(|rule| {
+            let rest = relative.strip_prefix(&rule.base)?;
+            let chars = rest.chars().collect::<Vec<char>>();
+            rule.pattern.captures_from(&chars, 0).map(|_| rule.negated)
+        }).is_some_and(|negated| !negated)
+    }
+}
@@ -0,0 +1,465 @@
+use std::{cell::{Cell, OnceCell}, collections::HashMap, fmt, fs::File, io::Read, mem::ManuallyDrop, os::fd::FromRawFd, time::Duration};
+
+use crate::{terminal::wait_for_input, terminfo};
+
+// How long to wait after ESC before deciding the user pressed the Escape key on its own
+const ESCAPE_TIMEOUT: Duration = Duration::from_millis(25);
+// How long to wait for the rest of a sequence once it has started. Generous, since SSH can split a sequence across packets
+const SEQUENCE_TIMEOUT: Duration = Duration::from_millis(100);
+// No key we understand comes close to this; anything longer is garbage or a flood of parameters
+const MAX_SEQUENCE_LEN: usize = 32;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Modifiers {
+    pub shift: bool,
+    pub alt: bool,
+    pub ctrl: bool,
+}
+
+impl Modifiers {
+    pub const NONE: Modifiers = Modifiers { shift: false, alt: false, ctrl: false };
+
+    // xterm encodes modifiers as 1 + (shift | alt << 1 | ctrl << 2), e.g. the 5 in \x1b[1;5C
+    pub fn from_xterm(param: u8) -> Modifiers {
+        let bits = param.saturating_sub(1);
+        Modifiers { shift: bits & 1 != 0, alt: bits & 2 != 0, ctrl: bits & 4 != 0 }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum MouseButton {
+    Left,
+    Middle,
+    Right,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum MouseKind {
+    Press(MouseButton),
+    Drag(MouseButton), // Moving with the button held
+    Release,
+    ScrollUp,
+    ScrollDown,
+}
+
+// A mouse report, at a screen row and column counted from 0
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Mouse {
+    pub kind: MouseKind,
+    pub row: usize,
+    pub column: usize,
+    pub modifiers: Modifiers,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Key {
+    Char(char), // Printable characters and raw control characters (ctrl+s is '\x13')
+    Alt(char),
+    Up(Modifiers),
+    Down(Modifiers),
+    Left(Modifiers),
+    Right(Modifiers),
+    Home(Modifiers),
+    End(Modifiers),
+    PageUp(Modifiers),
+    PageDown(Modifiers),
+    Delete(Modifiers),
+    Escape,
+    BackTab, // Shift+Tab
+    Mouse(Mouse),
+    Unknown(String),
+}
+
+impl fmt::Display for Modifiers {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.ctrl {
+            write!(f, "Ctrl+")?;
+        }
+        if self.alt {
+            write!(f, "Alt+")?;
+        }
+        if self.shift {
+            write!(f, "Shift+")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Key::Char('\x7F') => write!(f, "Backspace"),
+            Key::Char('\r' | '\n') => write!(f, "Enter"),
+            Key::Char('\t') => write!(f, "Tab"),
+            Key::Char(' ') => write!(f, "Space"),
+            Key::Char('\x00') => write!(f, "Ctrl+Space"),
+            Key::Char(char) if (*char as u32) < 0x20 => write!(f, "Ctrl+{}", ((*char as u8) + b'@').to_ascii_uppercase() as char),
+            Key::Char(char) => write!(f, "{}", char),
+            Key::Alt('\x7F') => write!(f, "Alt+Backspace"),
+            Key::Alt(char) => write!(f, "Alt+{}", char),
+            Key::Up(modifiers) => write!(f, "{}Up", modifiers),
+            Key::Down(modifiers) => write!(f, "{}Down", modifiers),
+            Key::Left(modifiers) => write!(f, "{}Left", modifiers),
+            Key::Right(modifiers) => write!(f, "{}Right", modifiers),
+            Key::Home(modifiers) => write!(f, "{}Home", modifiers),
+            Key::End(modifiers) => write!(f, "{}End", modifiers),
+            Key::PageUp(modifiers) => write!(f, "{}PageUp", modifiers),
+            Key::PageDown(modifiers) => write!(f, "{}PageDown", modifiers),
+            Key::Delete(modifiers) => write!(f, "{}Delete", modifiers),
+            Key::Escape => write!(f, "Esc"),
+            Key::BackTab => write!(f, "Shift+Tab"),
+            Key::Mouse(mouse) => {
+                let kind = match mouse.kind {
+                    MouseKind::Press(MouseButton::Left) => "Click",
+                    MouseKind::Press(MouseButton::Middle) => "MiddleClick",
+                    MouseKind::Press(MouseButton::Right) => "RightClick",
+                    MouseKind::Drag(_) => "Drag",
+                    MouseKind::Release => "Release",
+                    MouseKind::ScrollUp => "ScrollUp",
+                    MouseKind::ScrollDown => "ScrollDown",
+                };
+                write!(f, "{}{}", mouse.modifiers, kind)
+            }
+            Key::Unknown(sequence) => write!(f, "{:?}", sequence),
+        }
+    }
+}
+
+// Builds a key that carries modifiers, e.g. Key::Left
+pub type KeyConstructor = fn(Modifiers) -> Key;
+
+impl Key {
+    // The modifiers held with a key that carries them, like the arrows
+    pub fn modifiers(&self) -> Option<Modifiers> {
+        match self {
+            Key::Up(modifiers) | Key::Down(modifiers) | Key::Left(modifiers) | Key::Right(modifiers) | Key::Home(modifiers)
+                | Key::End(modifiers) | Key::PageUp(modifiers) | Key::PageDown(modifiers) | Key::Delete(modifiers) => Some(*modifiers),
+            _ => None,
+        }
+    }
+
+    // A character typed with modifiers, the way terminals send it: ctrl+letter as a control character and alt+char
+    // as Key::Alt. None for combinations that have no such key, like ctrl+alt+x
+    pub fn from_char(char: char, modifiers: Modifiers) -> Option<Key> {
+        match (modifiers.ctrl, modifiers.alt, modifiers.shift) {
+            (false, false, false) => Some(Key::Char(char)),
+            (true, false, false) if char == ' ' => Some(Key::Char('\x00')),
+            (true, false, false) if char == '\x7F' => Some(Key::Char('\x08')),
+            (true, false, false) if char.is_ascii_alphabetic() || "@[\\]^_".contains(char) => Some(Key::Char((char.to_ascii_uppercase() as u8 & 0x1f) as char)),
+            (false, true, false) => Some(Key::Alt(char)),
+            _ => None,
+        }
+    }
+
+    // The same key with other modifiers held, or None for keys that don't carry them
+    pub fn with_modifiers(&self, modifiers: Modifiers) -> Option<Key> {
+        let key: KeyConstructor = match self {
+            Key::Up(_) => Key::Up,
+            Key::Down(_) => Key::Down,
+            Key::Left(_) => Key::Left,
+            Key::Right(_) => Key::Right,
+            Key::Home(_) => Key::Home,
+            Key::End(_) => Key::End,
+            Key::PageUp(_) => Key::PageUp,
+            Key::PageDown(_) => Key::PageDown,
+            Key::Delete(_) => Key::Delete,
+            _ => return None,
+        };
+        Some(key(modifiers))
+    }
+}
+
+// std::io::stdin() is buffered, which would hide pending bytes from poll() when timing out escape sequences
+fn unbuffered_stdin() -> ManuallyDrop<File> {
+    ManuallyDrop::new(unsafe { File::from_raw_fd(libc::STDIN_FILENO) })
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RawInput {
+    Char(char),
+    Escape(String),
+    Malformed(String), // A truncated, overlong, or otherwise invalid escape sequence
+}
+
+thread_local! {
+    // A byte read while decoding one key that actually starts the next, e.g. a lead byte arriving where a
+    // UTF-8 continuation byte was expected
+    static PENDING_BYTE: Cell<Option<u8>> = const { Cell::new(None) };
+}
+
+fn read_byte(stdin: &mut File) -> u8 {
+    if let Some(byte) = PENDING_BYTE.take() {
+        return byte;
+    }
+
+    // The terminal going away is taken as a hang-up, so the event loop keeps unsaved changes in swap files and
+    // exits. Until it gets there, Escape backs out of any prompt waiting for keys
+    let mut byte = [0];
+    if stdin.read_exact(&mut byte).is_err() {
+        unsafe { libc::raise(libc::SIGHUP) };
+        return 0x1b;
+    }
+    byte[0]
+}
+
+// A byte that has been read and pushed back counts as input that is ready
+pub fn key_pending() -> bool {
+    PENDING_BYTE.get().is_some()
+}
+
+// Like terminal::wait_for_input, but also counts a pushed back byte
+pub fn wait_for_key(timeout: Duration) -> bool {
+    key_pending() || wait_for_input(timeout)
+}
+
+// Waits for the next byte of an escape sequence, giving up if the rest of it never arrives
+fn read_sequence_byte(stdin: &mut File) -> Option<u8> {
+    if !wait_for_key(SEQUENCE_TIMEOUT) {
+        return None;
+    }
+
+    Some(read_byte(stdin))
+}
+
+// Reads the continuation bytes for a UTF-8 lead byte. If the sequence is invalid or cut short, the offending
+// byte is kept for the next read so decoding resynchronizes on it, and U+FFFD is returned
+fn read_utf8_char(stdin: &mut File, lead: u8) -> char {
+    let num_bytes = if lead < 0x80 {
+        1
+    } else if (lead & 0xE0) == 0xC0 {
+        2
+    } else if (lead & 0xF0) == 0xE0 {
+        3
+    } else if (lead & 0xF8) == 0xF0 {
+        4
+    } else {
+        return '\u{FFFD}'; // A stray continuation byte or an invalid lead byte
+    };
+
+    let mut buf: [u8; 4] = [lead, 0, 0, 0];
+    for slot in buf.iter_mut().take(num_bytes).skip(1) {
+        let Some(byte) = read_sequence_byte(stdin) else { return '\u{FFFD}' };
+
+        if byte & 0xC0 != 0x80 {
+            PENDING_BYTE.set(Some(byte));
+            return '\u{FFFD}';
+        }
+
+        *slot = byte;
+    }
+
+    std::str::from_utf8(&buf[0..num_bytes]).ok().and_then(|str| str.chars().next()).unwrap_or('\u{FFFD}')
+}
+
+pub fn read_utf8_or_escape() -> RawInput {
+    let mut stdin = unbuffered_stdin();
+    let lead = read_byte(&mut stdin);
+
+    if lead != 0x1B {
+        return RawInput::Char(read_utf8_char(&mut stdin, lead));
+    }
+
+    let mut str = "\x1b".to_string();
+    let Some(byte) = (if wait_for_key(ESCAPE_TIMEOUT) { Some(read_byte(&mut stdin)) } else { None }) else {
+        return RawInput::Escape(str);
+    };
+
+    match byte {
+        b'[' => { // CSI: parameters followed by a final byte
+            str.push('[');
+            let mut overlong = false;
+
+            loop {
+                let Some(byte) = read_sequence_byte(&mut stdin) else { return RawInput::Malformed(str) };
+
+                // Only printable ASCII can appear in a CSI sequence, anything else means it was cut short
+                if !(0x20..0x7F).contains(&byte) {
+                    PENDING_BYTE.set(Some(byte));
+                    return RawInput::Malformed(str);
+                }
+
+                // Keep consuming an overlong sequence so its tail isn't inserted as text, but don't keep it
+                if str.len() < MAX_SEQUENCE_LEN {
+                    str.push(byte as char);
+                } else {
+                    overlong = true;
+                }
+
+                if (64..127).contains(&byte) && byte != 91 {
+                    return if overlong { RawInput::Malformed(str) } else { RawInput::Escape(str) };
+                }
+            }
+        },
+        b'O' => { // SS3: an optional modifier digit followed by a single final byte
+            str.push('O');
+
+            loop {
+                let Some(byte) = read_sequence_byte(&mut stdin) else { return RawInput::Malformed(str) };
+
+                if !(0x20..0x7F).contains(&byte) || str.len() >= MAX_SEQUENCE_LEN {
+                    PENDING_BYTE.set(Some(byte));
+                    return RawInput::Malformed(str);
+                }
+
+                str.push(byte as char);
+
+                if !byte.is_ascii_digit() && byte != b';' {
+                    return RawInput::Escape(str);
+                }
+            }
+        },
+        _ => { // Alt+key, where the key may be any UTF-8 character
+            str.push(read_utf8_char(&mut stdin, byte));
+            RawInput::Escape(str)
+        }
+    }
+}
+
+// SGR mouse reports (mode 1006) look like \x1b[<0;12;5M: the button code, column and row, then M for a press or
+// movement and m for a release. The code's low bits are the button, then 4 shift, 8 alt, 16 ctrl, 32 movement and
+// 64 the wheel
+fn parse_sgr_mouse(sequence: &str) -> Option<Mouse> {
+    let body = sequence.strip_prefix("\x1b[<")?;
+    let release = body.ends_with('m');
+    let params = body.strip_suffix(['M', 'm'])?.split(';').map(|param| param.parse().ok()).collect::<Option<Vec<usize>>>()?;
+    let [code, column, row] = params[..] else { return None };
+
+    let button = match code & 3 {
+        0 => Some(MouseButton::Left),
+        1 => Some(MouseButton::Middle),
+        2 => Some(MouseButton::Right),
+        _ => None,
+    };
+    let kind = match (code & 64 != 0, code & 32 != 0, release) {
+        (true, _, _) if code & 1 == 0 => MouseKind::ScrollUp,
+        (true, _, _) => MouseKind::ScrollDown,
+        (false, _, true) => MouseKind::Release,
+        (false, true, false) => MouseKind::Drag(button?),
+        (false, false, false) => MouseKind::Press(button?),
+    };
+
+    let modifiers = Modifiers { shift: code & 4 != 0, alt: code & 8 != 0, ctrl: code & 16 != 0 };
+    Some(Mouse { kind, row: row.checked_sub(1)?, column: column.checked_sub(1)?, modifiers })
+}
+
+// Keys in the kitty keyboard protocol look like \x1b[122:90:97;5u: the key's code point, the code point with
+// shift held and the key in the same place on a US layout, then the modifiers as xterm encodes them. Returns the key
+// and the key in its place on a US layout, which physical key bindings go by. Only asked for when those are used,
+// see terminal::set_kitty_keys
+fn parse_kitty(sequence: &str) -> Option<(Key, Key)> {
+    let body = sequence.strip_prefix("\x1b[")?.strip_suffix('u')?;
+    let mut params = body.split(';');
+    let codes = params.next()?.split(':').map(|code| if code.is_empty() { Ok(None) } else { code.parse::<u32>().map(Some) }).collect::<Result<Vec<Option<u32>>, _>>().ok()?;
+    let modifier = params.next().and_then(|modifier| modifier.split(':').next()?.parse::<u8>().ok()).unwrap_or(1);
+    let modifiers = Modifiers::from_xterm(modifier);
+    let code = char::from_u32(codes.first().copied()??)?;
+    let shifted = codes.get(1).copied().flatten().and_then(char::from_u32);
+    let base = codes.get(2).copied().flatten().and_then(char::from_u32).unwrap_or(code);
+
+    // Shift is part of the character rather than a modifier of its own, as with legacy encodings
+    let key = |char: char, shifted: Option<char>| {
+        let unshifted = Modifiers { shift: false, ..modifiers };
+        let char = if modifiers.shift { shifted.unwrap_or(char.to_ascii_uppercase()) } else { char };
+        match char {
+            '\x1b' if modifiers == Modifiers::NONE => Key::Escape,
+            '\t' if modifiers.shift && !modifiers.ctrl && !modifiers.alt => Key::BackTab,
+            char => Key::from_char(char, unshifted).unwrap_or(Key::Unknown(sequence.to_string())),
+        }
+    };
+    Some((key(code, shifted), key(base, None)))
+}
+
+// Maps escape sequences to keys. Built from the encodings common terminals use, then overridden by
+// whatever terminfo says the current $TERM sends
+// The sequence table needs terminfo, which is only read once the first escape sequence arrives so it
+// doesn't cost anything at startup
+#[derive(Default)]
+pub struct KeyDecoder {
+    sequences: OnceCell<HashMap<String, Key>>,
+    physical: Cell<Option<Key>>, // The last key as it is in the same place on a US layout, when the terminal says
+}
+
+impl KeyDecoder {
+    fn sequences() -> HashMap<String, Key> {
+        let mut sequences = HashMap::new();
+        let finals: [(char, KeyConstructor); 6] = [('A', Key::Up), ('B', Key::Down), ('C', Key::Right), ('D', Key::Left), ('H', Key::Home), ('F', Key::End)];
+
+        for (final_byte, key) in finals {
+            sequences.insert(format!("\x1b[{}", final_byte), key(Modifiers::NONE));
+            sequences.insert(format!("\x1bO{}", final_byte), key(Modifiers::NONE));
+
+            for param in 2..=8 {
+                let modifiers = Modifiers::from_xterm(param);
+                sequences.insert(format!("\x1b[1;{}{}", param, final_byte), key(modifiers)); // xterm
+                sequences.insert(format!("\x1b[{}{}", param, final_byte), key(modifiers)); // old xterm, e.g. \x1b[5C
+                sequences.insert(format!("\x1bO{}{}", param, final_byte), key(modifiers)); // some vt100 emulations
+            }
+
+            // rxvt sends lowercase finals for the arrows: \x1b[a for shift+up and \x1bOa for ctrl+up
+            if !matches!(final_byte, 'H' | 'F') {
+                let lower = final_byte.to_ascii_lowercase();
+                sequences.insert(format!("\x1b[{}", lower), key(Modifiers { shift: true, ..Modifiers::NONE }));
+                sequences.insert(format!("\x1bO{}", lower), key(Modifiers { ctrl: true, ..Modifiers::NONE }));
+            }
+        }
+
+        // VT220 style editing keys, e.g. \x1b[5~ for page up and \x1b[5;5~ with ctrl. Home and end have two numbers each
+        let tildes: [(u8, KeyConstructor); 7] = [(1, Key::Home), (7, Key::Home), (4, Key::End), (8, Key::End), (5, Key::PageUp), (6, Key::PageDown), (3, Key::Delete)];
+        for (number, key) in tildes {
+            sequences.insert(format!("\x1b[{}~", number), key(Modifiers::NONE));
+            for param in 2..=8 {
+                sequences.insert(format!("\x1b[{};{}~", number, param), key(Modifiers::from_xterm(param)));
+            }
+            // rxvt again, with ^ for ctrl and $ for shift
+            sequences.insert(format!("\x1b[{}^", number), key(Modifiers { ctrl: true, ..Modifiers::NONE }));
+            sequences.insert(format!("\x1b[{}$", number), key(Modifiers { shift: true, ..Modifiers::NONE }));
+        }
+
+        sequences.insert("\x1b[Z".to_string(), Key::BackTab);
+
+        if let Some(info) = terminfo::load() {
+            for (name, key) in terminfo::special_keys() {
+                if let Some(sequence) = info.get(&name) {
+                    sequences.insert(sequence.to_string(), key);
+                }
+            }
+        }
+
+        sequences
+    }
+
+    pub fn decode(&self, input: RawInput) -> Key {
+        self.physical.set(None);
+        match input {
+            RawInput::Char(char_) => Key::Char(char_),
+            RawInput::Escape(esc) if let Some((key, physical)) = parse_kitty(&esc) => {
+                self.physical.set(Some(physical));
+                key
+            }
+            RawInput::Escape(esc) if esc == "\x1b" => Key::Escape,
+            RawInput::Escape(esc) if let Some(mouse) = parse_sgr_mouse(&esc) => Key::Mouse(mouse),
+            RawInput::Escape(esc) => match self.sequences.get_or_init(KeyDecoder::sequences).get(&esc) {
+                Some(key) => key.clone(),
+                None => {
+                    let mut chars = esc.chars().skip(1);
+                    match (chars.next(), chars.next()) {
+                        (Some(char_), None) => Key::Alt(char_),
+                        _ => Key::Unknown(esc),
+                    }
+                }
+            },
+            RawInput::Malformed(esc) => Key::Unknown(esc),
+        }
+    }
+
+    pub fn read_key(&self) -> Key {
+        self.decode(read_utf8_or_escape())
+    }
+
+    // Where the last key read is on a US layout, for physical key bindings. Only known with the kitty protocol
+    pub fn physical(&self) -> Option<Key> {
+        let physical = self.physical.take();
+        self.physical.set(physical.clone());
+        physical
+    }
+}
@@ -0,0 +1,208 @@
+// Just enough JSON to reformat it. Strings and numbers keep their original spelling, so reformatting never
+// changes an escape or a number's precision, and object keys keep their order
+pub enum Value {
+    Literal(String), // null, true, false, a number, or a string with its quotes
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+// Where parsing failed, counted from 0 in the text given
+pub struct Error {
+    pub message: String,
+    pub line: usize,
+    pub column: usize,
+}
+
+struct Parser {
+    chars: Vec<char>,
+    index: usize,
+}
+
+impl Parser {
+    fn error(&self, message: &str) -> Error {
+        let before = &self.chars[..self.index.min(self.chars.len())];
+        let line = before.iter().filter(|char| **char == '\n').count();
+        let column = before.iter().rev().take_while(|char| **char != '\n').count();
+        Error { message: message.to_string(), line, column }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.index).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(|char| matches!(char, ' ' | '\t' | '\n' | '\r')) {
+            self.index += 1;
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), Error> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(char) if char == expected => {
+                self.index += 1;
+                Ok(())
+            }
+            Some(char) => Err(self.error(&format!("expected '{}' but found '{}'", expected, char))),
+            None => Err(self.error(&format!("expected '{}' but the text ended", expected))),
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, Error> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('{') => self.object(),
+            Some('[') => self.array(),
+            Some('"') => self.string().map(Value::Literal),
+            Some(char) if char == '-' || char.is_ascii_digit() => self.number(),
+            Some(char) if char.is_ascii_alphabetic() => {
+                let start = self.index;
+                while self.peek().is_some_and(|char| char.is_ascii_alphanumeric()) {
+                    self.index += 1;
+                }
+                let word = self.chars[start..self.index].iter().collect::<String>();
+                if !matches!(word.as_str(), "null" | "true" | "false") {
+                    self.index = start;
+                    return Err(self.error(&format!("unknown literal '{}'", word)));
+                }
+                Ok(Value::Literal(word))
+            }
+            Some(char) => Err(self.error(&format!("expected a value but found '{}'", char))),
+            None => Err(self.error("expected a value but the text ended")),
+        }
+    }
+
+    fn object(&mut self) -> Result<Value, Error> {
+        self.expect('{')?;
+        let mut members = vec![];
+        self.skip_whitespace();
+        if self.peek() == Some('}') {
+            self.index += 1;
+            return Ok(Value::Object(members));
+        }
+
+        loop {
+            self.skip_whitespace();
+            if self.peek() != Some('"') {
+                return Err(self.error("expected a key in double quotes"));
+            }
+            let key = self.string()?;
+            self.expect(':')?;
+            members.push((key, self.value()?));
+
+            self.skip_whitespace();
+            match self.peek() {
+                Some(',') => self.index += 1,
+                Some('}') => {
+                    self.index += 1;
+                    return Ok(Value::Object(members));
+                }
+                _ => return Err(self.error("expected ',' or '}' after a value in an object")),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Value, Error> {
+        self.expect('[')?;
+        let mut items = vec![];
+        self.skip_whitespace();
+        if self.peek() == Some(']') {
+            self.index += 1;
+            return Ok(Value::Array(items));
+        }
+
+        loop {
+            items.push(self.value()?);
+            self.skip_whitespace();
+            match self.peek() {
+                Some(',') => self.index += 1,
+                Some(']') => {
+                    self.index += 1;
+                    return Ok(Value::Array(items));
+                }
+                _ => return Err(self.error("expected ',' or ']' after a value in an array")),
+            }
+        }
+    }
+
+    // The string with its quotes and escapes as written
+    fn string(&mut self) -> Result<String, Error> {
+        let start = self.index;
+        self.index += 1;
+        loop {
+            match self.peek() {
+                Some('"') => break,
+                Some('\\') => {
+                    self.index += 1;
+                    match self.peek() {
+                        Some('"' | '\\' | '/' | 'b' | 'f' | 'n' | 'r' | 't') => {}
+                        Some('u') if (1..=4).all(|offset| self.chars.get(self.index + offset).is_some_and(char::is_ascii_hexdigit)) => self.index += 4,
+                        _ => return Err(self.error("invalid escape in a string")),
+                    }
+                }
+                Some(char) if char.is_control() => return Err(self.error("unescaped control character in a string")),
+                Some(_) => {}
+                None => {
+                    self.index = start;
+                    return Err(self.error("string is never closed"));
+                }
+            }
+            self.index += 1;
+        }
+        self.index += 1;
+        Ok(self.chars[start..self.index].iter().collect())
+    }
+
+    fn number(&mut self) -> Result<Value, Error> {
+        let start = self.index;
+        while self.peek().is_some_and(|char| char.is_ascii_digit() || matches!(char, '-' | '+' | '.' | 'e' | 'E')) {
+            self.index += 1;
+        }
+
+        let number = self.chars[start..self.index].iter().collect::<String>();
+        let digits = number.strip_prefix('-').unwrap_or(&number);
+        let leading_zero = digits.len() > 1 && digits.starts_with('0') && digits.as_bytes()[1].is_ascii_digit();
+        if !digits.starts_with(|char: char| char.is_ascii_digit()) || leading_zero || number.parse::<f64>().is_err() {
+            self.index = start;
+            return Err(self.error(&format!("invalid number '{}'", number)));
+        }
+        Ok(Value::Literal(number))
+    }
+}
+
+pub fn parse(text: &str) -> Result<Value, Error> {
+    let mut parser = Parser { chars: text.chars().collect(), index: 0 };
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    match parser.peek() {
+        Some(_) => Err(parser.error("unexpected text after the value")),
+        None => Ok(value),
+    }
+}
+
+// One member or item per line, nested `indent` deeper each level. Empty arrays and objects stay on one line
+pub fn pretty(value: &Value, indent: &str, depth: usize) -> String {
+    let inner = indent.repeat(depth + 1);
+    let outer = indent.repeat(depth);
+    match value {
+        Value::Literal(literal) => literal.clone(),
+        Value::Array(items) if items.is_empty() => "[]".to_string(),
+        Value::Object(members) if members.is_empty() => "{}".to_string(),
+        Value::Array(items) => {
+            let items = items.iter().map(|item| format!("{}{}", inner, pretty(item, indent, depth + 1))).collect::<Vec<String>>();
+            format!("[\n{}\n{}]", items.join(",\n"), outer)
+        }
+        Value::Object(members) => {
+            let members = members.iter().map(|(key, value)| format!("{}{}: {}", inner, key, pretty(value, indent, depth + 1))).collect::<Vec<String>>();
+            format!("{{\n{}\n{}}}", members.join(",\n"), outer)
+        }
+    }
+}
+
+pub fn minify(value: &Value) -> String {
+    match value {
+        Value::Literal(literal) => literal.clone(),
+        Value::Array(items) => format!("[{}]", items.iter().map(minify).collect::<Vec<String>>().join(",")),
+        Value::Object(members) => format!("{{{}}}", members.iter().map(|(key, value)| format!("{}:{}", key, minify(value))).collect::<Vec<String>>().join(",")),
+    }
+}
@@ -0,0 +1,441 @@
+use std::{collections::HashMap, fmt};
+
+use crate::{input::{Key, KeyConstructor, Modifiers}, line_edit};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    MoveUp,
+    MoveDown,
+    RowUp, // By screen row through wrapped lines, which is a line when they don't wrap
+    RowDown,
+    MoveLeft,
+    MoveRight,
+    WordLeft,
+    WordRight,
+    LineStart,
+    LineEnd,
+    PageUp,
+    PageDown,
+    DeleteBack,
+    DeleteForward,
+    DeleteWordBack,
+    Delete,
+    Newline,
+    Indent,
+    Dedent,
+    ToggleSelection,
+    Undo,
+    Redo,
+    Command,
+    Digraph,
+    Replace,
+    NextPane,
+    Copy,
+    Paste,
+    Cut,
+    KillToEnd,
+    KillToStart,
+    Yank,
+    YankPop,
+    FindChar,
+    FindCharBack,
+    TillChar,
+    TillCharBack,
+    RepeatFind,
+    RepeatFindBack,
+    Save,
+    SaveAs,
+    Quit,
+}
+
+pub const ACTIONS: &[Action] = &[
+    Action::MoveUp,
+    Action::MoveDown,
+    Action::RowUp,
+    Action::RowDown,
+    Action::MoveLeft,
+    Action::MoveRight,
+    Action::WordLeft,
+    Action::WordRight,
+    Action::LineStart,
+    Action::LineEnd,
+    Action::PageUp,
+    Action::PageDown,
+    Action::DeleteBack,
+    Action::DeleteForward,
+    Action::DeleteWordBack,
+    Action::Delete,
+    Action::Newline,
+    Action::Indent,
+    Action::Dedent,
+    Action::ToggleSelection,
+    Action::Undo,
+    Action::Redo,
+    Action::Command,
+    Action::Digraph,
+    Action::Replace,
+    Action::NextPane,
+    Action::Copy,
+    Action::Paste,
+    Action::Cut,
+    Action::KillToEnd,
+    Action::KillToStart,
+    Action::Yank,
+    Action::YankPop,
+    Action::FindChar,
+    Action::FindCharBack,
+    Action::TillChar,
+    Action::TillCharBack,
+    Action::RepeatFind,
+    Action::RepeatFindBack,
+    Action::Save,
+    Action::SaveAs,
+    Action::Quit,
+];
+
+impl Action {
+    // The action's name in the [keys] table of the config
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::MoveUp => "move_up",
+            Action::MoveDown => "move_down",
+            Action::RowUp => "row_up",
+            Action::RowDown => "row_down",
+            Action::MoveLeft => "move_left",
+            Action::MoveRight => "move_right",
+            Action::WordLeft => "word_left",
+            Action::WordRight => "word_right",
+            Action::LineStart => "line_start",
+            Action::LineEnd => "line_end",
+            Action::PageUp => "page_up",
+            Action::PageDown => "page_down",
+            Action::DeleteBack => "delete_back",
+            Action::DeleteForward => "delete_forward",
+            Action::DeleteWordBack => "delete_word_back",
+            Action::Delete => "delete",
+            Action::Newline => "newline",
+            Action::Indent => "indent",
+            Action::Dedent => "dedent",
+            Action::ToggleSelection => "toggle_selection",
+            Action::Undo => "undo",
+            Action::Redo => "redo",
+            Action::Command => "command",
+            Action::Digraph => "digraph",
+            Action::Replace => "replace",
+            Action::NextPane => "next_pane",
+            Action::Copy => "copy",
+            Action::Paste => "paste",
+            Action::Cut => "cut",
+            Action::KillToEnd => "kill_to_end",
+            Action::KillToStart => "kill_to_start",
+            Action::Yank => "yank",
+            Action::YankPop => "yank_pop",
+            Action::FindChar => "find_char",
+            Action::FindCharBack => "find_char_back",
+            Action::TillChar => "till_char",
+            Action::TillCharBack => "till_char_back",
+            Action::RepeatFind => "repeat_find",
+            Action::RepeatFindBack => "repeat_find_back",
+            Action::Save => "save",
+            Action::SaveAs => "save_as",
+            Action::Quit => "quit",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Action> {
+        ACTIONS.iter().find(|action| action.name() == name).copied()
+    }
+
+    // Actions that operate on the selection rather than moving away from it
+    pub fn keeps_selection(&self) -> bool {
+        matches!(self, Action::Command | Action::Save | Action::SaveAs | Action::Delete | Action::Copy | Action::Paste | Action::Cut | Action::Yank | Action::Indent | Action::Dedent | Action::ToggleSelection)
+    }
+
+    // Cursor movements, which extend the selection while it is toggled on
+    pub fn moves(&self) -> bool {
+        matches!(self, Action::MoveUp | Action::MoveDown | Action::RowUp | Action::RowDown | Action::MoveLeft | Action::MoveRight | Action::WordLeft | Action::WordRight | Action::LineStart | Action::LineEnd
+            | Action::PageUp | Action::PageDown | Action::FindChar | Action::FindCharBack
+            | Action::TillChar | Action::TillCharBack | Action::RepeatFind | Action::RepeatFindBack)
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            Action::MoveUp => "Move the cursor up a line",
+            Action::MoveDown => "Move the cursor down a line",
+            Action::RowUp => "Move the cursor up a screen row, through wrapped lines",
+            Action::RowDown => "Move the cursor down a screen row, through wrapped lines",
+            Action::MoveLeft => "Move the cursor left a character",
+            Action::MoveRight => "Move the cursor right a character",
+            Action::WordLeft => "Move the cursor to the start of the previous word",
+            Action::WordRight => "Move the cursor to the end of the next word",
+            Action::LineStart => "Move the cursor to the start of the line",
+            Action::LineEnd => "Move the cursor to the end of the line",
+            Action::PageUp => "Scroll up a screenful",
+            Action::PageDown => "Scroll down a screenful",
+            Action::DeleteBack => "Delete the character before the cursor",
+            Action::DeleteForward => "Delete the character under the cursor, joining the next line at the end of one",
+            Action::DeleteWordBack => "Delete back to the start of the previous word",
+            Action::Delete => "Delete the selection, or the current line (recoverable with the trash command)",
+            Action::Newline => "Start a new line",
+            Action::Indent => "Insert one level of indentation, or indent the selected lines",
+            Action::Dedent => "Remove one level of indentation from the current or selected lines",
+            Action::ToggleSelection => "Start selecting from the cursor, so plain movement extends the selection, or stop",
+            Action::Undo => "Undo the last change",
+            Action::Redo => "Redo the last undone change",
+            Action::Command => "Open the command prompt",
+            Action::Digraph => "Insert a special character from the two characters typed next",
+            Action::Replace => "Replace matches of a pattern, confirming each one",
+            Action::NextPane => "Move to the next split pane",
+            Action::Copy => "Copy the selection, or the current line, to the system clipboard",
+            Action::Paste => "Insert the system clipboard at the cursor",
+            Action::Cut => "Cut the selection, or the current line, into the kill ring",
+            Action::KillToEnd => "Cut from the cursor to the end of the line into the kill ring",
+            Action::KillToStart => "Cut from the start of the line to the cursor into the kill ring",
+            Action::Yank => "Insert the newest cut or copied text at the cursor",
+            Action::YankPop => "Replace the text just inserted with the cut before it, cycling through the kill ring",
+            Action::FindChar => "Jump to the next occurrence on the line of the character typed next",
+            Action::FindCharBack => "Jump to the previous occurrence on the line of the character typed next",
+            Action::TillChar => "Jump to just before the next occurrence on the line of the character typed next",
+            Action::TillCharBack => "Jump to just after the previous occurrence on the line of the character typed next",
+            Action::RepeatFind => "Repeat the last character jump",
+            Action::RepeatFindBack => "Repeat the last character jump in the other direction",
+            Action::Save => "Save the file",
+            Action::SaveAs => "Save the buffer under a new path and keep editing it there",
+            Action::Quit => "Quit, asking whether to save any changes",
+        }
+    }
+}
+
+// Where a binding came from, for the whichkey inspector
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Source {
+    Default,
+    Config,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Source::Default => write!(f, "default keymap"),
+            Source::Config => write!(f, "[keys] in the config"),
+        }
+    }
+}
+
+// Parses a key as written in the config: a character or one of enter, tab, backspace, space, esc, up, down, left
+// and right, after any of ctrl+, alt+ and shift+. e.g. "ctrl+o", "alt+x", "shift+up"
+pub fn parse_key(text: &str) -> Option<Key> {
+    let (prefix, name) = match text.rsplit_once('+') {
+        Some((prefix, name)) if !name.is_empty() => (prefix, name),
+        _ => ("", text),
+    };
+
+    let mut modifiers = Modifiers::NONE;
+    for modifier in prefix.split('+').filter(|modifier| !modifier.is_empty()) {
+        match modifier.to_lowercase().as_str() {
+            "ctrl" => modifiers.ctrl = true,
+            "alt" => modifiers.alt = true,
+            "shift" => modifiers.shift = true,
+            _ => return None,
+        }
+    }
+
+    let mut chars = name.chars();
+    let char = match (chars.next(), chars.next(), name.to_lowercase().as_str()) {
+        (Some(char), None, _) => char,
+        (_, _, "enter") => '\r',
+        (_, _, "tab") if modifiers == (Modifiers { shift: true, ..Modifiers::NONE }) => return Some(Key::BackTab),
+        (_, _, "tab") => '\t',
+        (_, _, "backspace") => '\x7F',
+        (_, _, "space") => ' ',
+        (_, _, "esc") if modifiers == Modifiers::NONE => return Some(Key::Escape),
+        (_, _, arrow) => {
+            let arrow: KeyConstructor = match arrow {
+                "up" => Key::Up,
+                "down" => Key::Down,
+                "left" => Key::Left,
+                "right" => Key::Right,
+                "home" => Key::Home,
+                "end" => Key::End,
+                "pageup" => Key::PageUp,
+                "pagedown" => Key::PageDown,
+                "delete" => Key::Delete,
+                _ => return None,
+            };
+            return Some(arrow(modifiers));
+        }
+    };
+
+    Key::from_char(char, modifiers)
+}
+
+pub struct Keymap {
+    bindings: Vec<(Key, Action, Source)>,
+    physical: Vec<(Key, Action)>, // By where the key is rather than what it types, from [physical_keys] in the config
+}
+
+impl Keymap {
+    pub fn new() -> Keymap {
+        let bindings = vec![
+            (Key::Up(Modifiers::NONE), Action::RowUp),
+            (Key::Down(Modifiers::NONE), Action::RowDown),
+            (Key::Up(Modifiers { alt: true, ..Modifiers::NONE }), Action::MoveUp),
+            (Key::Down(Modifiers { alt: true, ..Modifiers::NONE }), Action::MoveDown),
+            (Key::Left(Modifiers::NONE), Action::MoveLeft),
+            (Key::Right(Modifiers::NONE), Action::MoveRight),
+            (Key::Left(Modifiers { ctrl: true, ..Modifiers::NONE }), Action::WordLeft),
+            (Key::Right(Modifiers { ctrl: true, ..Modifiers::NONE }), Action::WordRight),
+            (Key::Home(Modifiers::NONE), Action::LineStart),
+            (Key::End(Modifiers::NONE), Action::LineEnd),
+            (Key::PageUp(Modifiers::NONE), Action::PageUp),
+            (Key::PageDown(Modifiers::NONE), Action::PageDown),
+            (Key::Char('\x7F'), Action::DeleteBack),
+            (Key::Delete(Modifiers::NONE), Action::DeleteForward),
+            (Key::Char('\x08'), Action::DeleteBack), // ctrl+h, which some terminals send for backspace
+            (Key::Alt('\x7F'), Action::DeleteWordBack), // alt+backspace
+            (Key::Char('\r'), Action::Newline),
+            (Key::Char('\n'), Action::Newline),
+            (Key::Char('\t'), Action::Indent),
+            (Key::BackTab, Action::Dedent),
+            (Key::Char('\x00'), Action::ToggleSelection), // ctrl+space
+            (Key::Char('\x1a'), Action::Undo), // ctrl+z
+            (Key::Char('\x19'), Action::Redo), // ctrl+y
+            (Key::Char('\x05'), Action::Command), // ctrl+e
+            (Key::Char('\x04'), Action::Delete), // ctrl+d
+            (Key::Char('\x0b'), Action::Digraph), // ctrl+k
+            (Key::Char('\x12'), Action::Replace), // ctrl+r
+            (Key::Char('\x17'), Action::NextPane), // ctrl+w
+            (Key::Char('\x03'), Action::Copy), // ctrl+c
+            (Key::Alt('v'), Action::Paste),
+            (Key::Char('\x18'), Action::Cut), // ctrl+x
+            (Key::Alt('k'), Action::KillToEnd),
+            (Key::Char('\x15'), Action::KillToStart), // ctrl+u
+            (Key::Char('\x16'), Action::Yank), // ctrl+v
+            (Key::Alt('y'), Action::YankPop),
+            (Key::Alt('f'), Action::FindChar),
+            (Key::Alt('F'), Action::FindCharBack),
+            (Key::Alt('t'), Action::TillChar),
+            (Key::Alt('T'), Action::TillCharBack),
+            (Key::Alt(';'), Action::RepeatFind),
+            (Key::Alt(','), Action::RepeatFindBack),
+            (Key::Char('\x13'), Action::Save), // ctrl+s
+            (Key::Alt('s'), Action::SaveAs),
+            (Key::Char('\x11'), Action::Quit), // ctrl+q
+        ];
+
+        Keymap { bindings: bindings.into_iter().map(|(key, action)| (key, action, Source::Default)).collect(), physical: vec![] }
+    }
+
+    // The default bindings with the [keys] table of the config applied: each action listed there gets exactly the
+    // keys given, which are taken away from whatever they were bound to. Keys in [physical_keys] are written as on a
+    // US layout and work from the same place on any other, on top of the action's other keys. The arrows move by
+    // screen row and alt+arrows by line, unless `arrows_by_row` is off, which swaps them
+    pub fn with_config(keys: &HashMap<String, Vec<String>>, physical: &HashMap<String, Vec<String>>, arrows_by_row: bool) -> Keymap {
+        let mut keymap = Keymap::new();
+        if !arrows_by_row {
+            for (_, action, _) in &mut keymap.bindings {
+                *action = match *action {
+                    Action::RowUp => Action::MoveUp,
+                    Action::RowDown => Action::MoveDown,
+                    Action::MoveUp => Action::RowUp,
+                    Action::MoveDown => Action::RowDown,
+                    action => action,
+                };
+            }
+        }
+        let mut names = keys.keys().collect::<Vec<&String>>();
+        names.sort();
+
+        for name in names {
+            let Some(action) = Action::parse(name) else { continue };
+            let keys = keys[name].iter().filter_map(|key| parse_key(key)).collect::<Vec<Key>>();
+            keymap.bindings.retain(|(key, bound, _)| *bound != action && !keys.contains(key));
+            keymap.bindings.extend(keys.into_iter().map(|key| (key, action, Source::Config)));
+        }
+        for (name, keys) in physical {
+            let Some(action) = Action::parse(name) else { continue };
+            keymap.physical.extend(keys.iter().filter_map(|key| parse_key(key)).map(|key| (key, action)));
+        }
+
+        keymap
+    }
+
+    // Modified arrows fall back to the plain arrow's binding when they have none of their own
+    pub fn lookup(&self, key: &Key) -> Option<Action> {
+        self.find(key).or_else(|| self.unshifted(key).and_then(|unshifted| self.find(&unshifted)))
+            .or_else(|| self.fallback(key).and_then(|plain| self.find(&plain))).map(|(action, _)| action)
+    }
+
+    // The action for a key just read, going by where it is on the keyboard first when the terminal says
+    pub fn lookup_pressed(&self, key: &Key, physical: Option<Key>) -> Option<Action> {
+        let by_place = physical.and_then(|physical| self.physical.iter().find(|(bound, _)| *bound == physical).map(|(_, action)| *action));
+        by_place.or_else(|| self.lookup(key))
+    }
+
+    // Whether any keys are bound by where they are, which needs the terminal to say so
+    pub fn has_physical(&self) -> bool {
+        !self.physical.is_empty()
+    }
+
+    // A shifted arrow with other modifiers, e.g. ctrl+shift+left, does what it does without shift while selecting
+    fn unshifted(&self, key: &Key) -> Option<Key> {
+        let modifiers = key.modifiers().filter(|modifiers| modifiers.shift && (modifiers.ctrl || modifiers.alt))?;
+        key.with_modifiers(Modifiers { shift: false, ..modifiers })
+    }
+
+    fn find(&self, key: &Key) -> Option<(Action, Source)> {
+        self.bindings.iter().find(|(bound, _, _)| bound == key).map(|(_, action, source)| (*action, *source))
+    }
+
+    // The unmodified arrow or editing key a modified one falls back to
+    fn fallback(&self, key: &Key) -> Option<Key> {
+        key.modifiers().filter(|modifiers| *modifiers != Modifiers::NONE)?;
+        key.with_modifiers(Modifiers::NONE)
+    }
+
+    // Everything a key does, one line per mode that handles it, for the whichkey command. The command prompt reads
+    // keys itself, so it is listed too; Editor::describe_key adds the buffer modes that take keys before the keymap
+    pub fn describe(&self, key: &Key) -> Vec<String> {
+        if let Key::Mouse(_) = key {
+            return vec!["mouse: a click places the cursor, dragging selects and the wheel moves the cursor; set mouse = false to leave it to the terminal".to_string()];
+        }
+
+        let action = |action: Action, source: Source| format!("{:?}: {} ({})", action, action.description(), source);
+        let mut lines = vec![];
+
+        let printable = matches!(key, Key::Char(char) if !char.is_control());
+        let editing = match (self.find(key), self.fallback(key)) {
+            (Some((bound, source)), _) => action(bound, source),
+            (None, Some(plain)) if let Some((bound, source)) = self.find(&plain) => format!("{}, as bound to {}", action(bound, source), plain),
+            (None, _) if printable => "types the character".to_string(),
+            (None, _) => "not bound".to_string(),
+        };
+        lines.push(format!("editing: {}", editing));
+
+        if key.modifiers().is_some_and(|modifiers| modifiers.shift) {
+            lines.push("editing: extends the selection, before the above".to_string());
+        }
+
+        let cursors = match self.lookup(key) {
+            Some(Action::DeleteBack) => "deletes before every cursor",
+            _ if printable => "types at every cursor",
+            _ if *key == Key::Escape => "removes the extra cursors",
+            _ => "removes the extra cursors, then as editing",
+        };
+        lines.push(format!("with extra cursors: {}", cursors));
+
+        lines.push(format!("command prompt: {}", line_edit::describe(key)));
+        lines
+    }
+
+    pub fn keys_for(&self, action: Action) -> Vec<&Key> {
+        let mut keys: Vec<&Key> = vec![];
+        for (key, _, _) in self.bindings.iter().filter(|(_, bound, _)| *bound == action) {
+            // \r and \n are both Enter, only list it once
+            if !keys.iter().any(|existing| existing.to_string() == key.to_string()) {
+                keys.push(key);
+            }
+        }
+        keys
+    }
+}
@@ -0,0 +1,40 @@
+use std::collections::VecDeque;
+
+// How many cut or copied texts are kept for cycling through
+const RING_SIZE: usize = 16;
+
+// Text ending in a line break is whole lines, which are put back above the cursor like a linewise paste
+#[derive(Default)]
+pub struct KillRing {
+    kills: VecDeque<String>,
+    pub yank: Option<Yank>, // The text put back by the last yank, while yank_pop can still replace it
+}
+
+#[derive(Clone, Copy)]
+pub struct Yank {
+    pub index: usize, // Into the ring, newest first
+    pub start: (usize, usize),
+    pub end: (usize, usize),
+    pub linewise: bool,
+}
+
+impl KillRing {
+    pub fn push(&mut self, text: String) {
+        if text.is_empty() {
+            return;
+        }
+
+        self.kills.retain(|kill| *kill != text);
+        self.kills.push_front(text);
+        self.kills.truncate(RING_SIZE);
+    }
+
+    pub fn get(&self, index: usize) -> Option<&String> {
+        self.kills.get(index)
+    }
+
+    // The kill before the one at index, wrapping around to the newest
+    pub fn older(&self, index: usize) -> usize {
+        (index + 1) % self.kills.len().max(1)
+    }
+}
@@ -0,0 +1,108 @@
+use crate::{input::{Key, Modifiers}, motions};
+
+// The text typed at a prompt, edited with readline's keys. Prompts read keys themselves, so these work the same
+// whatever the main keymap binds
+#[derive(Default)]
+pub struct LineEdit {
+    pub text: Vec<char>,
+    pub cursor: usize,
+}
+
+// What each key does, for the whichkey command. Keys not listed are ignored unless they type a character
+const KEYS: &[(&str, &str)] = &[
+    ("Enter", "submits"),
+    ("Ctrl+C", "cancels"),
+    ("Esc", "cancels"),
+    ("Backspace", "deletes the character before the cursor"),
+    ("Ctrl+H", "deletes the character before the cursor"),
+    ("Ctrl+D", "deletes the character under the cursor"),
+    ("Delete", "deletes the character under the cursor"),
+    ("Ctrl+A", "moves to the start"),
+    ("Ctrl+E", "moves to the end"),
+    ("Home", "moves to the start"),
+    ("End", "moves to the end"),
+    ("Ctrl+B", "moves back a character"),
+    ("Ctrl+F", "moves forward a character"),
+    ("Left", "moves back a character"),
+    ("Right", "moves forward a character"),
+    ("Alt+b", "moves back a word"),
+    ("Alt+f", "moves forward a word"),
+    ("Ctrl+Left", "moves back a word"),
+    ("Ctrl+Right", "moves forward a word"),
+    ("Ctrl+W", "deletes back to the previous space"),
+    ("Alt+d", "deletes forward to the end of the word"),
+    ("Ctrl+U", "deletes everything before the cursor"),
+    ("Ctrl+K", "deletes everything from the cursor"),
+];
+
+pub fn describe(key: &Key) -> &'static str {
+    match KEYS.iter().find(|(name, _)| *name == key.to_string()) {
+        Some((_, description)) => description,
+        None if matches!(key, Key::Char(char) if !char.is_control()) => "types the character",
+        None => "ignored",
+    }
+}
+
+impl LineEdit {
+    fn word_back(&self) -> usize {
+        motions::word_back(&self.text, self.cursor)
+    }
+
+    fn word_forward(&self) -> usize {
+        motions::word_forward(&self.text, self.cursor)
+    }
+
+    // Applies an editing key. Returns false for keys that aren't editing ones, like Enter
+    pub fn handle(&mut self, key: &Key) -> bool {
+        let ctrl = Modifiers { ctrl: true, ..Modifiers::NONE };
+
+        match key {
+            Key::Char(char_) if !char_.is_control() => {
+                self.text.insert(self.cursor, *char_);
+                self.cursor += 1;
+            }
+            Key::Char('\x7F' | '\x08') => {
+                if self.cursor > 0 {
+                    self.cursor -= 1;
+                    self.text.remove(self.cursor);
+                }
+            }
+            Key::Char('\x04') | Key::Delete(Modifiers::NONE) => {
+                if self.cursor < self.text.len() {
+                    self.text.remove(self.cursor);
+                }
+            }
+            Key::Char('\x01') | Key::Home(Modifiers::NONE) => self.cursor = 0,
+            Key::Char('\x05') | Key::End(Modifiers::NONE) => self.cursor = self.text.len(),
+            Key::Char('\x02') | Key::Left(Modifiers::NONE) => self.cursor = self.cursor.saturating_sub(1),
+            Key::Char('\x06') | Key::Right(Modifiers::NONE) => self.cursor = (self.cursor + 1).min(self.text.len()),
+            Key::Alt('b') => self.cursor = self.word_back(),
+            Key::Alt('f') => self.cursor = self.word_forward(),
+            Key::Left(modifiers) if *modifiers == ctrl => self.cursor = self.word_back(),
+            Key::Right(modifiers) if *modifiers == ctrl => self.cursor = self.word_forward(),
+            // Unlike Alt+b, ctrl+w stops at whitespace, so it takes a whole path at once
+            Key::Char('\x17') => {
+                let mut start = self.cursor;
+                while start > 0 && self.text[start - 1].is_whitespace() {
+                    start -= 1;
+                }
+                while start > 0 && !self.text[start - 1].is_whitespace() {
+                    start -= 1;
+                }
+                self.text.drain(start..self.cursor);
+                self.cursor = start;
+            }
+            Key::Alt('d') => {
+                let end = self.word_forward();
+                self.text.drain(self.cursor..end);
+            }
+            Key::Char('\x15') => {
+                self.text.drain(..self.cursor);
+                self.cursor = 0;
+            }
+            Key::Char('\x0B') => self.text.truncate(self.cursor),
+            _ => return false,
+        }
+        true
+    }
+}
@@ -1,223 +1,481 @@
-use std::{char, env::args, fs::{self, File}, io::{Read, Stdout, Write}, path::PathBuf, process::ExitCode};
-
-use libc::termios as Termios;
+mod backup;
+mod clipboard;
+mod collab;
+mod commands;
+mod conceal;
+mod cursors;
+mod config;
+mod conflicts;
+mod control;
+mod csv;
+mod digraphs;
+mod editor;
+mod encoding;
+mod event_loop;
+mod executable;
+mod filetype;
+mod filters;
+mod folds;
+mod gitcommit;
+mod graphemes;
+mod history;
+mod hooks;
+mod idle;
+mod ignore;
+mod input;
+mod json;
+mod keymap;
+mod killring;
+mod line_edit;
+mod marks;
+mod metrics;
+mod motions;
+mod mouse;
+mod offset;
+mod operations;
+mod panes;
+mod paths;
+mod picker;
+mod print;
+mod rebase;
+mod project_replace;
+mod regex;
+mod remap;
+mod replace;
+mod screen;
+mod segments;
+mod selection;
+mod shell;
+mod startup;
+mod swap;
+mod swatches;
+mod tasks;
+mod templates;
+mod terminal;
+mod terminfo;
+mod text;
+mod theme;
+mod trash;
+mod tree;
+mod trust;
+mod tutor;
+mod urls;
+mod vars;
+mod viewer;
+mod width;
+mod wrap;
+
+use std::{env::{self, args}, fs, io::{self, Write}, panic::{self, AssertUnwindSafe}, path::{Path, PathBuf}, process::ExitCode, time::{SystemTime, UNIX_EPOCH}};
+
+use config::Config;
+use editor::Editor;
+use keymap::Keymap;
+use event_loop::{Event, EventLoop};
+use startup::StartupTimes;
+use terminal::{enable_raw_mode, set_kitty_keys, set_mouse_reporting};
+use text::TextBuffer;
+use trust::Trust;
+
+const FLAGS: &[&str] = &["--tutor", "--startuptime", "--view", "--hex", "--remote"];
+
+// Why the event loop stopped other than by quitting, each of which keeps unsaved changes in swap files
+enum Stop {
+    Signal(i32),
+    Output(io::Error), // The terminal can't be written to any more
+    Panic,
+}
 
-struct RawModeGuard {termios: Termios}
-impl Drop for RawModeGuard {
-    fn drop(&mut self) {
-        unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSAFLUSH, &self.termios); };
-    }
+enum OpenAs {
+    Edit,
+    View { hex: bool },
+    Cancel,
 }
 
-fn enable_raw_mode() -> RawModeGuard {
-    let mut termios: Termios = unsafe { std::mem::zeroed::<Termios>() };
-    unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut termios); };
-    let original_termios: Termios = termios.clone();
+// Asks what to do with a file that is too big to comfortably load or looks binary. This runs before raw mode,
+// so the answer is read as an ordinary line
+fn check_large_or_binary(path: &Path, limit: u64) -> OpenAs {
+    let size = fs::metadata(path).map_or(0, |metadata| metadata.len());
+    let mut sample = vec![0; encoding::BINARY_SAMPLE];
+    let sampled = fs::File::open(path).and_then(|mut file| std::io::Read::read(&mut file, &mut sample)).unwrap_or(0);
+
+    let reason = if size > limit {
+        format!("{} is {:.1} MB", path.display(), size as f64 / (1024.0 * 1024.0))
+    } else if encoding::sample_looks_binary(&sample[..sampled]) {
+        format!("{} looks like a binary file", path.display())
+    } else {
+        return OpenAs::Edit;
+    };
 
-    termios.c_iflag &= !(libc::BRKINT | libc::ICRNL | libc::INPCK | libc::ISTRIP | libc::IXON);
-    termios.c_oflag &= !(libc::OPOST);
-    termios.c_cflag |= libc::CS8;
-    termios.c_lflag &= !(libc::ECHO | libc::ICANON | libc::IEXTEN | libc::ISIG);
+    loop {
+        print!("{}. Open (r)ead-only, in (h)ex, (e)dit anyway, or (c)ancel? ", reason);
+        let _ = std::io::Write::flush(&mut std::io::stdout());
+        let mut answer = String::new();
+        if std::io::stdin().read_line(&mut answer).unwrap_or(0) == 0 {
+            return OpenAs::Cancel;
+        }
 
-    unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSAFLUSH, &termios); };
-    RawModeGuard { termios: original_termios }
+        match answer.trim().to_lowercase().as_str() {
+            "r" => return OpenAs::View { hex: false },
+            "h" => return OpenAs::View { hex: true },
+            "e" => return OpenAs::Edit,
+            "c" => return OpenAs::Cancel,
+            _ => {}
+        }
+    }
 }
 
-fn read_utf8_or_escape() -> (Option<char>, Option<String>) {
-    let mut stdin = std::io::stdin();
-    let mut buf: [u8; 4] = [0, 0, 0, 0];
-    stdin.read_exact(&mut buf[0..1]).expect("Failed to read from STDIN");
-
-    if buf[0] == 0x1B {
-        let mut str = "\x1b".to_string();
+// Unnamed buffers are backed by a file in the cache directory so they survive a crash or an accidental quit
+fn scratch_path() -> Option<PathBuf> {
+    let dir = paths::cache_dir()?;
+    fs::create_dir_all(&dir).ok()?;
 
-        let mut byte = [0];
-        loop {
-            stdin.read_exact(&mut byte).expect("Failed to read from STDIN");
-            str = str.to_string() + String::from_utf8(vec![byte[0]]).unwrap().as_str();
+    let secs = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_secs());
+    Some(dir.join(format!("scratch-{}-{}.txt", secs, std::process::id())))
+}
 
-            if byte[0] >= 64 && byte[0] < 127 && byte[0] != 91 {
-                return (None, Some(str));
+// Project config can run commands (formatter, build, on_open, after_save), so it is only applied once the user trusts the directory
+fn load_project(editor: &mut Editor, root: &Path) {
+    let trusted = match trust::lookup(root) {
+        Trust::Trusted => true,
+        Trust::Denied => false,
+        Trust::Unknown => {
+            let question = format!("Trust project config in {}? (y)es, (n)o, (a)lways, ne(v)er: ", root.display());
+            let answer = editor.prompt(&question).unwrap_or_default().trim().to_lowercase();
+
+            if (answer == "a" || answer == "v") && let Err(err) = trust::remember(root, answer == "a") {
+                editor.message = format!("Unable to save trust decision: {}", err);
             }
-        }
 
-    }
-
-    let num_bytes = if buf[0] < 0x80 {
-        1
-    } else if (buf[0] & 0xE0) == 0xC0 {
-        2
-    } else if (buf[0] & 0xF0) == 0xE0 {
-        3
-    } else if (buf[0] & 0xF8) == 0xF0 {
-        4
-    } else {
-        return (Some('\u{FFFD}'), None);
+            answer == "y" || answer == "a"
+        }
     };
 
-    if num_bytes > 1 {
-        stdin.read_exact(&mut buf[1..num_bytes]).expect("Failed to read from STDIN");
+    if !trusted {
+        editor.message = format!("Ignoring untrusted project config in {}", root.display());
+        return;
     }
 
-    let str = std::str::from_utf8(&buf[0..num_bytes]).unwrap_or("\u{FFFD}");
-    (Some(str.chars().next().unwrap_or('\u{FFFD}')), None)
-}
-
-fn write(file: PathBuf, buffer: &Vec<String>) {
-    let final_str = buffer.join("\n");
-
-    fs::write(file, final_str);
-}
-
-fn get_screen_size() -> Option<(usize, usize)> {
-    let mut ws: libc::winsize = unsafe { std::mem::zeroed() };
-    let res = unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut ws) };
+    let path = root.join(config::PROJECT_CONFIG_NAME);
+    editor.config_files.push((path.clone(), config::modified(&path)));
+    if let Err(err) = editor.config.merge_file(&path) {
+        editor.message = format!("Config error: {}", err);
+        return;
+    }
 
-    if res == 0 {
-        Some((ws.ws_col as usize, ws.ws_row as usize))
-    } else {
-        None
+    if let Some(hook) = editor.config.on_open.clone() {
+        let command = format!("{} {}", hook, shell::quote(&editor.path.display().to_string()));
+        match shell::run_with_env(&command, "", Some(root), &vars::env(&editor.all_vars())) {
+            Ok(output) if !output.status.success() => editor.message = format!("on_open hook failed: {}", shell::summary(&output)),
+            Err(err) => editor.message = format!("Unable to run on_open hook: {}", err),
+            _ => {}
+        }
     }
 }
 
-fn clear_screen(out: &mut Stdout) {
-    write!(out, "\x1bc\x1b[H").expect("failed to write to STDOUT")
-}
+// Offers to load the unsaved changes an earlier session left in a swap file
+fn recover_swap(editor: &mut Editor) {
+    let Some(swap) = swap::recoverable(&editor.path) else { return };
+    let answer = editor.prompt("Unsaved changes from an earlier session were found. Recover them? (y/n): ").unwrap_or_default();
+    if answer.trim().to_lowercase() != "y" {
+        swap::remove(&editor.path);
+        return;
+    }
 
-fn set_cursor_pos(out: &mut Stdout, row: usize, column: usize) {
-    write!(out, "\x1b[{};{}H", row + 1, column + 1).expect("Failed to write to STDOUT");
+    match fs::read(&swap) {
+        Ok(bytes) => {
+            let (lines, _, _) = encoding::decode(&bytes);
+            let cursor = (editor.line, editor.column);
+            editor.replace_lines(0, editor.buffer.len(), lines, cursor);
+            editor.goto(cursor);
+            editor.message = "Recovered unsaved changes, save to keep them".to_string();
+        }
+        Err(err) => editor.message = format!("Unable to read {}: {}", swap.display(), err),
+    }
 }
 
-fn draw_buffer(buffer: &Vec<String>, start_line: usize, line: usize, column: usize, width: usize, height: usize) {
-    let mut out = std::io::stdout();
-    clear_screen(&mut out);
+fn main() -> ExitCode {
+    let mut times = StartupTimes::new();
+
+    let has_flag = |flag: &str| args().skip(1).any(|arg| arg == flag);
+    let tutor = has_flag("--tutor");
+    let startuptime = has_flag("--startuptime");
+    let view = has_flag("--view") || has_flag("--hex");
+    let hex = has_flag("--hex");
+    // --listen on the default socket, or --listen=path
+    let mut listen = args().skip(1).find_map(|arg| match arg.strip_prefix("--listen") {
+        Some("") => control::default_path(),
+        Some(path) => path.strip_prefix('=').map(PathBuf::from),
+        None => None,
+    });
+    // Experimental: --share=address shares the buffer, --join=address#secret edits one shared elsewhere
+    let share = args().skip(1).find_map(|arg| arg.strip_prefix("--share=").map(str::to_string));
+    let join = args().skip(1).find_map(|arg| arg.strip_prefix("--join=").map(str::to_string));
+    let paths = args().skip(1).filter(|arg| !FLAGS.contains(&arg.as_str()) && !["--listen", "--share=", "--join=", "--fifo="].iter().any(|flag| arg.starts_with(flag))).collect::<Vec<String>>();
+
+    // Files go to the editor already listening on the socket when there is one, otherwise this editor starts
+    // listening so the next --remote comes here
+    if has_flag("--remote") && let Some(socket) = listen.clone().or_else(control::default_path) {
+        let cwd = env::current_dir().unwrap_or_default();
+        let files = paths.iter().map(|path| cwd.join(path)).collect::<Vec<PathBuf>>();
+        match control::hand_off(&socket, &files) {
+            Some(Ok(())) => return ExitCode::SUCCESS,
+            Some(Err(err)) => {
+                eprintln!("Unable to hand off to the editor on {}: {}", socket.display(), err);
+                return ExitCode::FAILURE;
+            }
+            None => listen = Some(socket),
+        }
+    }
+    let path = paths.first().cloned().unwrap_or_default();
+
+    // A missing config file is fine, it just means the defaults
+    let mut config = Config::default();
+    let global_config = config::global_config_path();
+    let config_error = global_config.as_ref().filter(|path| path.is_file()).and_then(|path| config.merge_file(path).err());
+    times.mark("load config");
+
+    let open_as = if view {
+        OpenAs::View { hex }
+    } else if !tutor && Path::new(&path).is_file() {
+        check_large_or_binary(Path::new(&path), config.large_file_size)
+    } else {
+        OpenAs::Edit
+    };
 
-    let mut lin = 0;
-    for current_line in 0..height.min(buffer.len()) {
-        set_cursor_pos(&mut out, lin, 0);
-        lin += 1;
+    if let OpenAs::View { hex } = open_as {
+        return match viewer::run(Path::new(&path), hex) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(err) => {
+                eprintln!("Unable to view {}: {}", path, err);
+                ExitCode::FAILURE
+            }
+        };
+    }
 
-        let curr_str = buffer[current_line + start_line].clone();
+    if let OpenAs::Cancel = open_as {
+        return ExitCode::SUCCESS;
+    }
 
-        if curr_str.len() < width {
-            write!(out, "{}", curr_str).expect("Failed to write to STDOUT");
-        } else if line == current_line {
-            write!(out, "{}", curr_str.chars().skip(curr_str.len() - width).take(width).collect::<String>()).expect("Failed to write to STDOUT");
-        } else {
-            write!(out, "{}", curr_str.chars().take(width).collect::<String>()).expect("Failed to write to STDOUT");
+    let scratch = tutor || path.is_empty();
+    let pathbuf = if scratch {
+        match scratch_path() {
+            Some(pathbuf) => pathbuf,
+            None => {
+                eprintln!("Unable to create a scratch file in the cache directory!");
+                return ExitCode::FAILURE;
+            }
         }
+    } else {
+        PathBuf::from(path.clone())
+    };
 
-        write!(out, "\x1b[K").expect("Failed to write to STDOUT");
+    // A path that doesn't exist yet is a new file, created on the first save
+    let new_file = !scratch && !pathbuf.exists();
+    if !scratch && !new_file && !pathbuf.is_file() {
+        eprintln!("{} is not a file!", path);
+        return ExitCode::FAILURE;
     }
 
-    set_cursor_pos(&mut out, line - start_line, column.min(width));
-    out.flush().expect("Failed to write to STDOUT");
-}
+    let start_dir = if scratch {
+        env::current_dir().ok()
+    } else {
+        let parent = pathbuf.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+        fs::canonicalize(parent).ok()
+    };
+    let project_root = start_dir.as_deref().and_then(config::find_project_root);
+
+    times.mark("find project root");
+
+    // The file's text, line ending and encoding go straight into the buffer, as when opening another file
+    let mut editor = Editor::new(vec![String::new()], pathbuf, scratch, config, project_root);
+    if tutor {
+        editor.buffer = tutor::generate(&Keymap::new()).into();
+    } else if new_file {
+        editor.message = format!("New file {}", path);
+    } else if !scratch {
+        match fs::read(&editor.path) {
+            Ok(bytes) => {
+                let (buffer, line_ending, encoding) = encoding::decode(&bytes);
+                (editor.buffer, editor.line_ending, editor.encoding) = (buffer.into(), line_ending, encoding);
+            }
+            Err(err) => {
+                eprintln!("Unable to read {}: {}", path, err);
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+    times.mark("read file");
 
-fn main() -> ExitCode {
-    let mut buffer: Vec<String> = vec![];
-    let mut line = 0;
-    let mut start_line = 0;
-    let mut column = 0;
+    if let Some(err) = config_error {
+        editor.message = format!("Config error: {}", err);
+    }
+    // Watched for changes even when missing, so creating it takes effect too
+    editor.config_files.extend(global_config.map(|path| {
+        let modified = config::modified(&path);
+        (path, modified)
+    }));
+    editor.arg_list = paths.iter().map(PathBuf::from).collect();
+    if !scratch {
+        editor.folds = folds::load(&editor.path);
+        if let Some(position) = marks::last_position(&editor.path) {
+            editor.goto(position);
+        }
+    }
 
-    let path = args().skip(1).collect::<String>();
-    let pathbuf = PathBuf::from(path.clone());
+    times.mark("create editor");
 
-    if !pathbuf.is_file() {
-        eprintln!("You must specify a file!");
-        return ExitCode::FAILURE;
+    let guard = enable_raw_mode();
+    terminal::restore_on_panic();
+    times.mark("enable raw mode");
+    if let Some(root) = editor.project_root.clone() {
+        load_project(&mut editor, &root);
+        times.mark("load project config");
     }
-
-    for file_line in fs::read_to_string(pathbuf.clone()).expect("Unable to read file").split("\n") {
-        buffer.push(file_line.to_string());
+    editor.panes[0].options.numbers = editor.config.line_numbers;
+    editor.panes[0].options.wrap = editor.config.wrap;
+    editor.panes[0].options.invisibles = editor.config.show_invisibles;
+    editor.keymap = Keymap::with_config(&editor.config.keys, &editor.config.physical_keys, editor.config.arrows_by_row);
+    // Physical bindings need the terminal to say where keys are
+    editor.kitty_keys = editor.keymap.has_physical() && terminal::kitty_keys_supported();
+    editor.load_theme();
+    if !scratch {
+        recover_swap(&mut editor);
+        editor.apply_template();
+        conflicts::announce(&mut editor);
+    }
+    editor.scroll();
+    // Checked by the draw the event loop starts with
+    let _ = editor.draw();
+    times.mark("first draw");
+
+    let mut events = EventLoop::new();
+    let session = match (&share, &join) {
+        (Some(address), _) => collab::share(&mut editor, address),
+        (None, Some(address)) => collab::join(&mut editor, address),
+        (None, None) => Ok(()),
+    };
+    if let Err(err) = session {
+        editor.message = err;
     }
 
-    let _guard = enable_raw_mode();
-
-    let (w_, h_) = get_screen_size().expect("Unable to get terminal size");
-    draw_buffer(&buffer, 0, line, column, w_, h_);
+    // Only a socket this editor made is removed when it exits
+    let listening = listen.filter(|socket| match control::listen(socket) {
+        Ok(()) => true,
+        Err(err) => {
+            editor.message = format!("Unable to listen on {}: {}", socket.display(), err);
+            false
+        }
+    });
+    // --fifo=path reads commands from a named pipe, made if it isn't there and removed on exit if it was made here
+    let fifo = args().skip(1).find_map(|arg| arg.strip_prefix("--fifo=").map(PathBuf::from));
+    let made_fifo = fifo.filter(|path| {
+        let existed = path.exists();
+        match control::listen_fifo(path) {
+            Ok(()) => !existed,
+            Err(err) => {
+                editor.message = format!("Unable to read commands from {}: {}", path.display(), err);
+                false
+            }
+        }
+    });
+    // A panic is caught here, after the hook has put the terminal back, so it stops the editor like a signal does
+    let stopped = panic::catch_unwind(AssertUnwindSafe(|| {
+        let mut drawn = editor.draw();
+        while drawn.is_ok() {
+            drawn = match events.next(&mut editor) {
+                Event::Key(key, arrived) => {
+                    let key_read = editor.last_input;
+                    editor.process_key(key);
+                    if editor.quit {
+                        return None;
+                    }
+                    collab::after_key(&mut editor);
 
-    loop {
-        let (char, escape) = read_utf8_or_escape();
-        let esc = escape.unwrap_or("".to_string());
-
-        match char {
-            Some(char_) => match char_ {
-                '\x7F' => { // Backspace
-                    let line_content = buffer.get_mut(line).unwrap();
-                    if column > 0 {
-                        line_content.remove(line_content.char_indices().nth(column - 1).map_or(0, |(byte, _)| byte));
-                        column -= 1;
-                    } else if buffer.len() > 1 {
-                        column = buffer[line - 1].len();
-                        buffer[line - 1] = buffer[line - 1].clone() + buffer.remove(line).to_string().as_str();
-                        line -= 1;
+                    editor.scroll();
+                    let drawn = editor.draw();
+                    // Keys that opened a prompt include the time spent typing into it, so they aren't counted
+                    if editor.last_input == key_read {
+                        editor.latency.record(arrived.elapsed());
                     }
-                },
-                '\n' => { // Enter sometimes
-                    line += 1;
-                    column = 0;
-                    buffer.insert(line, "".to_string());
-                }
-                '\r' => { // Enter other times
-                    line += 1;
-                    column = 0;
-                    buffer.insert(line, "".to_string());
+                    drawn
                 }
-                '\x11' => { // ctrl+q
-                    write(pathbuf.clone(), &buffer);
-                    break;
+                Event::Idle if idle::run(&mut editor) => editor.draw(),
+                Event::Idle => Ok(()),
+                Event::Signal(signal) => return Some(Stop::Signal(signal)),
+                Event::Resize => {
+                    editor.keep_cursor_rows();
+                    editor.draw()
                 }
-                '\x13' => write(pathbuf.clone(), &buffer), // ctrl+s
-                _ => {
-                    if !char_.is_control() {
-                        let line_ = buffer.get_mut(line).unwrap();
-                        let byte_index = line_.char_indices().nth(column).map(|(idx, _)| idx).unwrap_or(line_.len());
-                        line_.insert(byte_index, char_);
-                        column += 1;
-                    }
+                Event::Background(result) => {
+                    result(&mut editor);
+                    editor.scroll();
+                    editor.draw()
                 }
-            },
-            None => match esc.as_str() {
-                "\x1b[A" => {
-                    line = line.saturating_sub(1).max(0);
-                },
-                "\x1b[B" => {
-                    line = (line + 1).min(buffer.len() - 1);
-                },
-                "\x1b[C" => {
-                    column = (column + 1).min(buffer[line].len());
-                },
-                "\x1b[D" => {
-                    column = column.saturating_sub(1).max(0);
-                },
-                _ => {
-                    // Uncomment this to figure out escape sequences for things like function keys
-                    // panic!("Unhandled escape sequence: {:?}", esc);
+            };
+        }
+        drawn.err().map(Stop::Output)
+    })).unwrap_or(Some(Stop::Panic));
+
+    // Killed, hung up, unable to draw or panicked: keep unsaved changes in the swap file rather than overwriting the file
+    let mut swap_error = None;
+    if stopped.is_some() {
+        editor.each_document(|editor| {
+            if editor.unsaved && !editor.scratch {
+                if let Err(err) = swap::write(editor) {
+                    swap_error = Some(format!("unable to write the swap file for {}: {}", editor.path.display(), err));
                 }
+            } else if editor.unsaved {
+                editor.save();
             }
-        }
-
-        let (width, height) = get_screen_size().unwrap_or((1, 1));
+        });
+    }
 
-        if line < start_line + 8 {
-            start_line = line.saturating_sub(8);
+    editor.each_document(|editor| {
+        editor.remember_state();
+        if !editor.scratch && !editor.unsaved {
+            swap::remove(&editor.path);
         }
-
-        if line >= start_line + height - 8 {
-            start_line = line - (height - 8) + 1;
+    });
+    if let Some(socket) = listening {
+        let _ = fs::remove_file(socket);
+    }
+    if let Some(fifo) = made_fifo {
+        let _ = fs::remove_file(fifo);
+    }
+    let mut out = std::io::stdout();
+    let _ = set_mouse_reporting(&mut out, false);
+    if editor.kitty_keys {
+        let _ = set_kitty_keys(&mut out, false);
+    }
+    let _ = out.flush();
+    drop(guard);
+    if let Some(stop) = &stopped {
+        let kept = match swap_error {
+            Some(err) => format!(", {}", err),
+            None if editor.unsaved && !editor.scratch => ", unsaved changes are kept in the swap file".to_string(),
+            None => String::new(),
+        };
+        match stop {
+            Stop::Signal(signal) => eprintln!("Exiting on signal {}{}", signal, kept),
+            Stop::Output(err) => eprintln!("Exiting as the terminal can't be written to ({}){}", err, kept),
+            Stop::Panic => eprintln!("Exiting after the error above{}", kept),
         }
+    }
 
-        start_line = start_line.max(0);
-        if buffer.len() > height {
-            start_line = start_line.min(buffer.len() - height);
+    if editor.scratch {
+        if editor.buffer.iter().all(|line| line.is_empty()) {
+            let _ = fs::remove_file(&editor.path);
         } else {
-            start_line = 0;
+            eprintln!("Scratch buffer saved to {}", editor.path.display());
         }
+    }
 
-        draw_buffer(&buffer, start_line, line, column, width, height);
+    if startuptime {
+        eprintln!("{}", times.report());
     }
 
-    ExitCode::SUCCESS
+    match stopped {
+        Some(Stop::Output(_) | Stop::Panic) => ExitCode::FAILURE,
+        _ => ExitCode::SUCCESS,
+    }
 }
-
@@ -0,0 +1,74 @@
+use std::{fs, path::{Path, PathBuf}};
+
+use crate::paths;
+
+// The name under which each file's last cursor position is stored, next to the uppercase global marks
+const LAST_POSITION: &str = "'";
+
+struct Entry {
+    name: String,
+    line: usize,
+    column: usize,
+    path: PathBuf,
+}
+
+fn marks_file() -> Option<PathBuf> {
+    paths::state_dir().map(|dir| dir.join("marks"))
+}
+
+// The marks file holds one "name line column /path" entry per line
+fn load() -> Vec<Entry> {
+    let Some(text) = marks_file().and_then(|file| fs::read_to_string(file).ok()) else { return vec![] };
+
+    text.lines().filter_map(|line| {
+        let mut parts = line.splitn(4, ' ');
+        Some(Entry {
+            name: parts.next()?.to_string(),
+            line: parts.next()?.parse().ok()?,
+            column: parts.next()?.parse().ok()?,
+            path: PathBuf::from(parts.next()?),
+        })
+    }).collect()
+}
+
+fn store(entries: &[Entry]) -> std::io::Result<()> {
+    let file = marks_file().ok_or(std::io::Error::other("no state directory"))?;
+    if let Some(parent) = file.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let text = entries.iter().map(|entry| format!("{} {} {} {}\n", entry.name, entry.line, entry.column, entry.path.display())).collect::<String>();
+    fs::write(file, text)
+}
+
+// Marks are keyed by absolute path so they work from any directory
+fn absolute(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+fn set(name: &str, path: &Path, (line, column): (usize, usize)) -> std::io::Result<()> {
+    let path = absolute(path);
+    let mut entries = load();
+
+    // Global marks name one place anywhere, the last position is one per file
+    entries.retain(|entry| entry.name != name || (name == LAST_POSITION && entry.path != path));
+    entries.push(Entry { name: name.to_string(), line, column, path });
+    store(&entries)
+}
+
+pub fn last_position(path: &Path) -> Option<(usize, usize)> {
+    let path = absolute(path);
+    load().into_iter().rev().find(|entry| entry.name == LAST_POSITION && entry.path == path).map(|entry| (entry.line, entry.column))
+}
+
+pub fn remember_position(path: &Path, position: (usize, usize)) -> std::io::Result<()> {
+    set(LAST_POSITION, path, position)
+}
+
+pub fn set_global(mark: char, path: &Path, position: (usize, usize)) -> std::io::Result<()> {
+    set(&mark.to_string(), path, position)
+}
+
+pub fn global(mark: char) -> Option<(PathBuf, (usize, usize))> {
+    load().into_iter().rev().find(|entry| entry.name == mark.to_string()).map(|entry| (entry.path, (entry.line, entry.column)))
+}
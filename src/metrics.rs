@@ -0,0 +1,31 @@
+use std::{collections::VecDeque, fmt, time::Duration};
+
+// How many recent keystrokes the averages cover
+const SAMPLES: usize = 100;
+
+// Time from a key arriving to its frame being on screen
+#[derive(Default)]
+pub struct Latency {
+    samples: VecDeque<Duration>,
+    max: Duration,
+}
+
+impl Latency {
+    pub fn record(&mut self, latency: Duration) {
+        self.samples.push_back(latency);
+        if self.samples.len() > SAMPLES {
+            self.samples.pop_front();
+        }
+        self.max = self.max.max(latency);
+    }
+}
+
+impl fmt::Display for Latency {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let millis = |duration: Duration| duration.as_secs_f64() * 1000.0;
+        let Some(last) = self.samples.back() else { return write!(f, "key to frame: no keys yet") };
+        let average = self.samples.iter().sum::<Duration>() / self.samples.len() as u32;
+
+        write!(f, "key to frame: last {:.2}ms, avg {:.2}ms, max {:.2}ms", millis(*last), millis(average), millis(self.max))
+    }
+}
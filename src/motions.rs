@@ -0,0 +1,55 @@
+fn is_word(char: char) -> bool {
+    char.is_alphanumeric() || char == '_'
+}
+
+// Where the word before `index` starts, with words made of letters, digits and _ like readline's
+pub fn word_back(chars: &[char], mut index: usize) -> usize {
+    while index > 0 && !is_word(chars[index - 1]) {
+        index -= 1;
+    }
+    while index > 0 && is_word(chars[index - 1]) {
+        index -= 1;
+    }
+    index
+}
+
+// Where the word at or after `index` ends
+pub fn word_forward(chars: &[char], mut index: usize) -> usize {
+    while index < chars.len() && !is_word(chars[index]) {
+        index += 1;
+    }
+    while index < chars.len() && is_word(chars[index]) {
+        index += 1;
+    }
+    index
+}
+
+// A jump to a char on the cursor's line, like vim's f and t: onto the next occurrence, or just before it for a till
+#[derive(Clone, Copy)]
+pub struct CharFind {
+    pub target: char,
+    pub forward: bool,
+    pub till: bool,
+}
+
+impl CharFind {
+    // Repeated in the other direction, for repeating a find backwards
+    pub fn reversed(self) -> CharFind {
+        CharFind { forward: !self.forward, ..self }
+    }
+
+    // The column the jump lands on from `column`, or None if the char isn't there. A repeated till skips an
+    // occurrence right next to the cursor, which it would otherwise stop in front of every time
+    pub fn column(&self, line: &str, column: usize, repeat: bool) -> Option<usize> {
+        let chars = line.chars().collect::<Vec<char>>();
+        let skip = if self.till && repeat { 1 } else { 0 };
+
+        if self.forward {
+            let found = (column + 1 + skip..chars.len()).find(|index| chars[*index] == self.target)?;
+            Some(if self.till { found - 1 } else { found })
+        } else {
+            let found = (0..column.min(chars.len()).saturating_sub(skip)).rev().find(|index| chars[*index] == self.target)?;
+            Some(if self.till { found + 1 } else { found })
+        }
+    }
+}
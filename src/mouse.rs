@@ -0,0 +1,61 @@
+use crate::{editor::Editor, input::{Key, Mouse, MouseButton, MouseKind}, keymap::Action, terminal::get_screen_size, tree};
+
+// Lines moved by one notch of the scroll wheel
+const SCROLL_LINES: usize = 3;
+
+// A left click places the cursor in whichever pane it lands in, or selects a file tree entry, and dragging from there selects. The wheel moves
+// the cursor, since the terminal doesn't scroll by itself while the mouse is reported
+pub fn handle(editor: &mut Editor, mouse: Mouse) {
+    let position = (mouse.row, mouse.column);
+
+    match mouse.kind {
+        MouseKind::Press(MouseButton::Left) => {
+            editor.mouse_anchor = None;
+            if let Some(tree) = &mut editor.tree {
+                tree.focused = false;
+            }
+            if let Some(rect) = editor.tree_rect(get_screen_size().unwrap_or((1, 1))) && position.1 < rect.width {
+                return click_tree(editor, position.0.wrapping_sub(rect.top), rect.rows);
+            }
+            let Some(pane) = editor.pane_at(position) else { return };
+            if pane != editor.focused {
+                editor.focus(pane);
+            }
+
+            editor.selection = None;
+            editor.selecting = false;
+            editor.cursors.clear();
+            editor.goto(editor.position_in(pane, position));
+            editor.mouse_anchor = Some((editor.line, editor.column));
+        }
+        // Dragging out of the pane keeps selecting up to its edge
+        MouseKind::Drag(MouseButton::Left) => {
+            let Some(anchor) = editor.mouse_anchor else { return };
+            editor.goto(editor.position_in(editor.focused, position));
+            editor.selection = Some(anchor).filter(|anchor| *anchor != (editor.line, editor.column));
+        }
+        MouseKind::Release => editor.mouse_anchor = None,
+        MouseKind::ScrollUp | MouseKind::ScrollDown => {
+            let action = if mouse.kind == MouseKind::ScrollUp { Action::RowUp } else { Action::RowDown };
+            editor.selection = None;
+            for _ in 0..SCROLL_LINES {
+                editor.run_action(action);
+            }
+        }
+        _ => {}
+    }
+}
+
+// Focuses the file tree on the entry at a row, opening it if it was already selected
+fn click_tree(editor: &mut Editor, row: usize, rows: usize) {
+    let Some(tree) = &mut editor.tree else { return };
+    tree.focused = true;
+    if row >= rows || tree.first_shown(rows) + row >= tree.entries.len() {
+        return;
+    }
+    let index = tree.first_shown(rows) + row;
+    if index == tree.cursor {
+        return tree::handle_key(editor, Key::Char('\r'));
+    }
+    tree.cursor = index;
+}
@@ -0,0 +1,68 @@
+use crate::{encoding::{self, Encoding, LineEnding}, text::TextBuffer};
+
+// How many bytes a char takes in the file
+fn char_bytes(char: char, encoding: Encoding) -> usize {
+    if encoding::escaped_byte(char).is_some() || encoding == Encoding::Latin1 {
+        1
+    } else {
+        char.len_utf8()
+    }
+}
+
+fn line_break_len(line_ending: LineEnding) -> usize {
+    match line_ending {
+        LineEnding::Lf => 1,
+        LineEnding::Crlf => 2,
+    }
+}
+
+// The (byte, char) offset of a position from the start of the file as it would be saved
+pub fn offsets(buffer: &impl TextBuffer, (line, column): (usize, usize), line_ending: LineEnding, encoding: Encoding) -> (usize, usize) {
+    let break_len = line_break_len(line_ending);
+    let mut bytes = 0;
+    let mut chars = 0;
+
+    for text in buffer.lines(..line) {
+        bytes += text.chars().map(|char| char_bytes(char, encoding)).sum::<usize>() + break_len;
+        chars += text.chars().count() + break_len;
+    }
+
+    for char in buffer[line].chars().take(column) {
+        bytes += char_bytes(char, encoding);
+        chars += 1;
+    }
+
+    (bytes, chars)
+}
+
+// The position of a byte (or char) offset. An offset inside a multi-byte char or a line break lands on that char
+// or the end of that line, and one past the end of the file lands at the end
+pub fn position(buffer: &impl TextBuffer, offset: usize, in_bytes: bool, line_ending: LineEnding, encoding: Encoding) -> (usize, usize) {
+    let break_len = line_break_len(line_ending);
+    let mut remaining = offset;
+
+    for (line, text) in buffer.iter().enumerate() {
+        for (column, char) in text.chars().enumerate() {
+            let len = if in_bytes { char_bytes(char, encoding) } else { 1 };
+            if remaining < len {
+                return (line, column);
+            }
+            remaining -= len;
+        }
+
+        if remaining < break_len || line == buffer.len() - 1 {
+            return (line, text.chars().count());
+        }
+        remaining -= break_len;
+    }
+
+    (0, 0)
+}
+
+// A decimal number, or hexadecimal with a 0x prefix
+pub fn parse_number(text: &str) -> Option<usize> {
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => usize::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
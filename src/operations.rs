@@ -0,0 +1,75 @@
+use std::ops::Range;
+
+use crate::{editor::byte_index, text::TextBuffer};
+
+// A buffer edit described by where it happens rather than by the lines it rewrites. Typing, commands, and anything
+// driving the editor from outside build these and hand them to Editor::apply, which records each as one change
+#[derive(Clone, Debug)]
+pub enum Operation {
+    InsertText { at: (usize, usize), text: String }, // The text may span several lines
+    DeleteRange { start: (usize, usize), end: (usize, usize) },
+    SplitLine { at: (usize, usize), indent: String }, // The new line starts with the indent
+    JoinLines { line: usize }, // With the line after it
+}
+
+// What an operation does to the buffer, leaving the cursor at `cursor`
+pub struct Edit {
+    pub kind: EditKind,
+    pub cursor: (usize, usize),
+}
+
+pub enum EditKind {
+    Lines { start: usize, count: usize, new: Vec<String> }, // `count` lines from `start` are replaced by `new`
+    Text { line: usize, bytes: Range<usize>, text: String }, // Part of one line is, in place
+}
+
+impl Operation {
+    // None when the operation doesn't fit the buffer, e.g. joining the last line with the one after it. Columns
+    // past the end of a line mean its end. Works on byte offsets, since a single line can be megabytes long
+    pub fn edit(&self, buffer: &impl TextBuffer) -> Option<Edit> {
+        match self {
+            Operation::InsertText { at: (line, column), text } => {
+                let current = buffer.get(*line)?;
+                let (byte, column) = byte_index(current, *column);
+                if !text.contains('\n') {
+                    let cursor = (*line, column + text.chars().count());
+                    return Some(Edit { kind: EditKind::Text { line: *line, bytes: byte..byte, text: text.clone() }, cursor });
+                }
+
+                let mut lines = text.split('\n').map(str::to_string).collect::<Vec<String>>();
+
+                let last = lines.len() - 1;
+                let cursor_column = lines[last].chars().count() + if last == 0 { column } else { 0 };
+                let mut first = String::with_capacity(current.len() + text.len());
+                first.push_str(&current[..byte]);
+                first.push_str(&lines[0]);
+                lines[0] = first;
+                lines[last].push_str(&current[byte..]);
+                Some(Edit { kind: EditKind::Lines { start: *line, count: 1, new: lines }, cursor: (line + last, cursor_column) })
+            }
+            Operation::DeleteRange { start, end } => {
+                let (start, end) = if end < start { (*end, *start) } else { (*start, *end) };
+                let (first, last) = (buffer.get(start.0)?, buffer.get(end.0)?);
+                let (start_byte, start_column) = byte_index(first, start.1);
+                let (end_byte, _) = byte_index(last, end.1);
+                let cursor = (start.0, start_column);
+                if start.0 == end.0 {
+                    return Some(Edit { kind: EditKind::Text { line: start.0, bytes: start_byte..end_byte.max(start_byte), text: String::new() }, cursor });
+                }
+                let joined = first[..start_byte].to_string() + &last[end_byte..];
+                Some(Edit { kind: EditKind::Lines { start: start.0, count: end.0 - start.0 + 1, new: vec![joined] }, cursor })
+            }
+            Operation::SplitLine { at: (line, column), indent } => {
+                let current = buffer.get(*line)?;
+                let (byte, _) = byte_index(current, *column);
+                let new = vec![current[..byte].to_string(), indent.clone() + &current[byte..]];
+                Some(Edit { kind: EditKind::Lines { start: *line, count: 1, new }, cursor: (line + 1, indent.chars().count()) })
+            }
+            Operation::JoinLines { line } => {
+                let next = buffer.get(line + 1)?;
+                let current = &buffer[*line];
+                Some(Edit { kind: EditKind::Lines { start: *line, count: 2, new: vec![current.clone() + next] }, cursor: (*line, current.chars().count()) })
+            }
+        }
+    }
+}
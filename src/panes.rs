@@ -0,0 +1,89 @@
+use std::{collections::HashMap, fs, path::PathBuf, time::Instant};
+
+use crate::{encoding::{self, Encoding, LineEnding}, filetype, folds::{self, Folds}, history::History, text::Lines, vars::Vars};
+
+// Where one pane is looking, and in which buffer. Panes are stacked top to bottom in side by side columns, each
+// pane with its own status bar
+#[derive(Clone, Copy, Default)]
+pub struct View {
+    pub line: usize,
+    pub column: usize,
+    pub start_line: usize,
+    pub start_row: usize, // Of the start line, when it wraps onto more rows than fit above the cursor
+    pub options: Options,
+    pub document: usize, // Index into the editor's documents
+    pub stack: usize, // Which column of panes this one is in, from the left
+}
+
+// Display settings that belong to the pane rather than the buffer, so two panes onto one file can show it differently
+#[derive(Clone, Copy, Default)]
+pub struct Options {
+    pub numbers: bool,
+    pub whitespace: bool, // Spaces as · and tabs as →
+    pub invisibles: bool, // Zero-width chars and soft hyphens as placeholders rather than not at all, see width::is_invisible
+    pub wrap: bool, // Long lines continue on the rows below rather than being cut off
+}
+
+// The part of the screen a pane draws its text in. Its status bar is on the row below, unless in zen mode
+#[derive(Clone, Copy)]
+pub struct Rect {
+    pub top: usize,
+    pub left: usize,
+    pub width: usize,
+    pub rows: usize,
+}
+
+// Shares `total` rows or columns between `count` panes as evenly as possible, the first panes getting any left over
+pub fn shares(total: usize, count: usize) -> Vec<usize> {
+    (0..count).map(|index| total / count + if index < total % count { 1 } else { 0 }).collect()
+}
+
+// Text shown in its own pane below the others, like the output of the run command. It can't be focused or edited
+pub struct Output {
+    pub title: String,
+    pub lines: Vec<String>,
+}
+
+// A buffer that isn't the focused pane's. The focused pane's buffer lives in the editor's own fields, and is
+// swapped with one of these when focus moves to a pane onto another buffer
+pub struct Document {
+    pub buffer: Lines,
+    pub path: PathBuf,
+    pub scratch: bool,
+    pub unsaved: bool,
+    pub last_autosave: Instant,
+    pub history: History,
+    pub marks: HashMap<char, (usize, usize)>,
+    pub line_ending: LineEnding,
+    pub encoding: Encoding,
+    pub filetype: &'static str,
+    pub filters: Vec<&'static str>,
+    pub folds: Folds,
+    pub swap_stale: bool,
+    pub executable_checked: bool,
+    pub vars: Vars,
+}
+
+impl Document {
+    pub fn open(path: PathBuf) -> Result<Document, String> {
+        let bytes = fs::read(&path).map_err(|err| format!("Unable to open {}: {}", path.display(), err))?;
+        let (buffer, line_ending, encoding) = encoding::decode(&bytes);
+        Ok(Document {
+            buffer: buffer.into(),
+            filetype: filetype::detect(&path, false),
+            folds: folds::load(&path),
+            path,
+            scratch: false,
+            unsaved: false,
+            last_autosave: Instant::now(),
+            history: History::default(),
+            marks: HashMap::new(),
+            line_ending,
+            encoding,
+            filters: vec![],
+            swap_stale: false,
+            executable_checked: false,
+            vars: Vars::default(),
+        })
+    }
+}
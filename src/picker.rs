@@ -0,0 +1,60 @@
+use std::io::{self, Write};
+
+use crate::{editor::Editor, input::Key, terminal::{clear_screen, get_screen_size, set_cursor_pos}};
+
+fn draw(title: &str, items: &[String], cursor: usize, top: usize) -> io::Result<()> {
+    let (width, height) = get_screen_size().unwrap_or((1, 1));
+    let text_height = height.saturating_sub(2).max(1);
+
+    let mut out = std::io::stdout();
+    clear_screen(&mut out)?;
+
+    for (row, item) in items.iter().enumerate().skip(top).take(text_height) {
+        set_cursor_pos(&mut out, row - top, 0)?;
+        let text = item.chars().filter(|char| !char.is_control()).take(width).collect::<String>();
+        if row == cursor {
+            write!(out, "\x1b[7m{:<width$}\x1b[0m", text, width = width)?;
+        } else {
+            write!(out, "{}\x1b[K", text)?;
+        }
+    }
+
+    let status = format!(" {} ({}/{})", title, cursor + 1, items.len());
+    set_cursor_pos(&mut out, height.saturating_sub(2), 0)?;
+    write!(out, "\x1b[7m{:<width$}\x1b[0m", status.chars().take(width).collect::<String>(), width = width)?;
+
+    set_cursor_pos(&mut out, height.saturating_sub(1), 0)?;
+    write!(out, "{}\x1b[K", "Enter: choose, Esc: cancel".chars().take(width).collect::<String>())?;
+    out.flush()
+}
+
+// A full screen list to choose one item from. Returns its index, or None if the user cancels or it can't be drawn
+pub fn pick(editor: &mut Editor, title: &str, items: &[String]) -> Option<usize> {
+    if items.is_empty() {
+        return None;
+    }
+
+    let mut cursor = 0;
+    let mut top = 0;
+    loop {
+        let (_, height) = get_screen_size().unwrap_or((1, 1));
+        let text_height = height.saturating_sub(2).max(1);
+        cursor = cursor.min(items.len() - 1);
+        if cursor < top {
+            top = cursor;
+        } else if cursor >= top + text_height {
+            top = cursor + 1 - text_height;
+        }
+        if draw(title, items, cursor, top).is_err() {
+            return None;
+        }
+
+        match editor.read_key() {
+            Key::Up(_) => cursor = cursor.saturating_sub(1),
+            Key::Down(_) => cursor += 1,
+            Key::Char('\r' | '\n') => return Some(cursor),
+            Key::Char('\x03' | 'q') | Key::Escape => return None,
+            _ => {}
+        }
+    }
+}
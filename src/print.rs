@@ -0,0 +1,48 @@
+use std::env;
+
+use crate::{editor::Editor, shell, text::TextBuffer};
+
+const DEFAULT_PRINT_COMMAND: &str = "lpr";
+
+fn print_command() -> String {
+    env::var("TINYEDITOR_PRINT").ok().filter(|command| !command.trim().is_empty()).unwrap_or(DEFAULT_PRINT_COMMAND.to_string())
+}
+
+fn format_for_print(editor: &Editor, line_numbers: bool, header: bool) -> String {
+    let mut out = String::new();
+
+    if header {
+        out += &format!("{}  ({} lines)\n\n", editor.path.display(), editor.buffer.len());
+    }
+
+    let number_width = editor.buffer.len().to_string().len();
+    for (index, line) in editor.buffer.iter().enumerate() {
+        if line_numbers {
+            out += &format!("{:>width$}  ", index + 1, width = number_width);
+        }
+        out += line;
+        out += "\n";
+    }
+
+    out
+}
+
+// Pipes the buffer to the print command ($TINYEDITOR_PRINT, or lpr). -n adds line numbers, -h adds a header
+pub fn print(editor: &mut Editor, args: &[&str]) {
+    let line_numbers = args.contains(&"-n");
+    let header = args.contains(&"-h");
+
+    if let Some(unknown) = args.iter().find(|arg| !["-n", "-h"].contains(arg)) {
+        editor.message = format!("print: unknown option {}", unknown);
+        return;
+    }
+
+    let command = print_command();
+    let text = format_for_print(editor, line_numbers, header);
+
+    editor.message = match shell::run(&command, &text, None) {
+        Ok(output) if output.status.success() => format!("Sent {} lines to {}", editor.buffer.len(), command),
+        Ok(output) => format!("print: {} failed: {}", command, shell::summary(&output)),
+        Err(err) => format!("print: unable to run {}: {}", command, err),
+    };
+}
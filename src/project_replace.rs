@@ -0,0 +1,236 @@
+use std::{fs, io::{self, Write}, path::{Path, PathBuf}};
+
+use crate::{backup, editor::{self, Editor}, encoding, ignore::Ignores, input::Key, replace::{self, Matcher, ReplaceOptions}, terminal::{clear_screen, get_screen_size, set_cursor_pos}, text::{Lines, TextBuffer}};
+
+// Directories that never hold files worth rewriting
+const SKIPPED_DIRS: &[&str] = &["target", "node_modules"];
+
+struct FileChange {
+    path: PathBuf,
+    lines: Vec<String>,
+    changed: Vec<(usize, String, String)>, // Line index, old text, new text
+    count: usize,
+    apply: bool,
+}
+
+// Every file under `dir`, skipping hidden and build directories and whatever ignore files leave out. `relative` is
+// the directory's path from the project root
+fn project_files(dir: &Path, relative: &str, ignores: &mut Ignores, files: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else { return };
+    // A directory's own type, not its target's, so a symlink back up the tree isn't followed round forever
+    let mut entries = entries.flatten().map(|entry| (entry.path(), entry.file_type().is_ok_and(|kind| kind.is_dir()))).collect::<Vec<(PathBuf, bool)>>();
+    entries.sort();
+    let before = ignores.enter(dir, relative);
+
+    for (path, dir) in entries {
+        let name = path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+        if name.starts_with('.') {
+            continue;
+        }
+
+        let path_relative = if relative.is_empty() { name.clone() } else { format!("{}/{}", relative, name) };
+        if dir {
+            if !SKIPPED_DIRS.contains(&name.as_str()) && !ignores.is_ignored(&path_relative, true) {
+                project_files(&path, &path_relative, ignores, files);
+            }
+        } else if path.is_file() && !ignores.is_ignored(&path_relative, false) {
+            files.push(path);
+        }
+    }
+    ignores.leave(before);
+}
+
+// A file's lines as they are on disk, or None for files left alone: those over the large file size, binary ones,
+// and ones that aren't UTF-8
+fn read_lines(path: &Path, limit: u64) -> Option<Vec<String>> {
+    if fs::metadata(path).ok()?.len() > limit {
+        return None;
+    }
+    let bytes = fs::read(path).ok()?;
+    if encoding::sample_looks_binary(&bytes[..bytes.len().min(encoding::BINARY_SAMPLE)]) {
+        return None;
+    }
+    Some(String::from_utf8(bytes).ok()?.split('\n').map(str::to_string).collect())
+}
+
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+fn collect_changes(editor: &Editor, root: &Path, matcher: &Matcher, replacement: &str, options: ReplaceOptions) -> Vec<FileChange> {
+    let mut files = vec![];
+    project_files(root, "", &mut Ignores::default(), &mut files);
+
+    // Files open in panes are taken from their buffers, so unsaved edits are included
+    let focused = (!editor.scratch).then_some((&editor.path, &editor.buffer));
+    let documents = editor.documents.iter().flatten().filter(|document| !document.scratch).map(|document| (&document.path, &document.buffer));
+    let open = focused.into_iter().chain(documents).filter_map(|(path, buffer)| Some((fs::canonicalize(path).ok()?, buffer))).collect::<Vec<(PathBuf, &Lines)>>();
+
+    let mut changes = vec![];
+    for path in files {
+        let canonical = fs::canonicalize(&path).unwrap_or_else(|_| path.clone());
+        let lines = match open.iter().find(|(open, _)| *open == canonical) {
+            Some((_, buffer)) => buffer.to_vec(),
+            None => match read_lines(&path, editor.config.large_file_size) {
+                Some(lines) => lines,
+                None => continue,
+            },
+        };
+
+        let mut new_lines = lines.clone();
+        let mut changed = vec![];
+        let mut count = 0;
+        for (index, line) in lines.iter().enumerate() {
+            if let Some((new_line, replaced)) = replace::replace_line(line, matcher, replacement, options) {
+                changed.push((index, line.clone(), new_line.clone()));
+                new_lines[index] = new_line;
+                count += replaced;
+            }
+        }
+
+        if count > 0 {
+            changes.push(FileChange { path, lines: new_lines, changed, count, apply: true });
+        }
+    }
+
+    changes
+}
+
+// The preview as (file index, row text) pairs: a header per file followed by its changed lines
+fn preview_rows(changes: &[FileChange], root: &Path) -> Vec<(usize, String)> {
+    let mut rows = vec![];
+    for (file, change) in changes.iter().enumerate() {
+        let name = change.path.strip_prefix(root).unwrap_or(&change.path).display().to_string();
+        let mark = if change.apply { "[x]" } else { "[ ]" };
+        rows.push((file, format!("{} {} ({} replacement{})", mark, name, change.count, if change.count == 1 { "" } else { "s" })));
+
+        for (index, old, new) in &change.changed {
+            rows.push((file, format!("  {:>5} - {}", index + 1, old)));
+            rows.push((file, format!("  {:>5} + {}", index + 1, new)));
+        }
+    }
+    rows
+}
+
+fn draw_preview(changes: &[FileChange], rows: &[(usize, String)], cursor: usize, top: usize) -> io::Result<()> {
+    let (width, height) = get_screen_size().unwrap_or((1, 1));
+    let text_height = height.saturating_sub(2).max(1);
+
+    let mut out = std::io::stdout();
+    clear_screen(&mut out)?;
+
+    for (row, (file, text)) in rows.iter().enumerate().skip(top).take(text_height) {
+        set_cursor_pos(&mut out, row - top, 0)?;
+        let text = text.chars().take(width).collect::<String>();
+        let style = if row == cursor {
+            "\x1b[7m"
+        } else if !changes[*file].apply {
+            "\x1b[2m"
+        } else if text.starts_with("  ") && text.chars().nth(8) == Some('-') {
+            "\x1b[31m"
+        } else if text.starts_with("  ") {
+            "\x1b[32m"
+        } else {
+            "\x1b[1m"
+        };
+        write!(out, "{}{}\x1b[0m\x1b[K", style, text)?;
+    }
+
+    let selected = changes.iter().filter(|change| change.apply).collect::<Vec<&FileChange>>();
+    let status = format!(" Replace preview: {} of {} files, {} replacements", selected.len(), changes.len(), selected.iter().map(|change| change.count).sum::<usize>());
+    set_cursor_pos(&mut out, height.saturating_sub(2), 0)?;
+    write!(out, "\x1b[7m{:<width$}\x1b[0m", status.chars().take(width).collect::<String>(), width = width)?;
+
+    set_cursor_pos(&mut out, height.saturating_sub(1), 0)?;
+    let help = "Space: include/skip file, Enter: apply, Esc: cancel";
+    write!(out, "{}\x1b[K", help.chars().take(width).collect::<String>())?;
+    out.flush()
+}
+
+// Lets the user look over every pending change and opt files out. Returns false if they cancel or it can't be drawn
+fn review(editor: &mut Editor, changes: &mut [FileChange], root: &Path) -> bool {
+    let mut cursor = 0;
+    let mut top = 0;
+
+    loop {
+        let rows = preview_rows(changes, root);
+        let (_, height) = get_screen_size().unwrap_or((1, 1));
+        let text_height = height.saturating_sub(2).max(1);
+        cursor = cursor.min(rows.len() - 1);
+        if cursor < top {
+            top = cursor;
+        } else if cursor >= top + text_height {
+            top = cursor + 1 - text_height;
+        }
+        if draw_preview(changes, &rows, cursor, top).is_err() {
+            return false;
+        }
+
+        match editor.read_key() {
+            Key::Up(_) => cursor = cursor.saturating_sub(1),
+            Key::Down(_) => cursor += 1,
+            Key::Char(' ') => changes[rows[cursor].0].apply ^= true,
+            Key::Char('\r' | '\n') => return true,
+            Key::Char('\x03' | 'q') | Key::Escape => return false,
+            _ => {}
+        }
+    }
+}
+
+// Replaces across every file in the project, applying only after the user confirms the preview
+pub fn replace_in_project(editor: &mut Editor, matcher: &Matcher, replacement: &str, options: ReplaceOptions) {
+    let Some(root) = editor.project_root.clone().or_else(|| std::env::current_dir().ok()) else {
+        editor.message = "No project directory".to_string();
+        return;
+    };
+
+    let mut changes = collect_changes(editor, &root, matcher, replacement, options);
+    if changes.is_empty() {
+        editor.message = "No matches in the project".to_string();
+        return;
+    }
+
+    if !review(editor, &mut changes, &root) {
+        editor.message = "Replace cancelled".to_string();
+        return;
+    }
+
+    let mut files = 0;
+    let mut count = 0;
+    let mut applied = changes.into_iter().filter(|change| change.apply).collect::<Vec<FileChange>>();
+    // Files open in a pane get the replacement in their buffers, as a change undo can take back, and saving is left
+    // to the user. Their files on disk aren't written, so a later save can't quietly put the old text back
+    editor.each_document(|editor| {
+        if let Some(index) = applied.iter().position(|change| !editor.scratch && same_file(&change.path, &editor.path)) {
+            applied.remove(index);
+            count += replace::replace_all(editor, matcher, replacement, options);
+            files += 1;
+        }
+    });
+
+    let mut failed = vec![];
+    let mut not_backed_up = vec![];
+    for change in applied {
+        // As with saving, a backup that can't be made is reported but doesn't stop the write
+        if backup::make(&change.path, &editor.config.backup).is_err() {
+            not_backed_up.push(change.path.display().to_string());
+        }
+        if editor::write(change.path.clone(), change.lines.join("\n").as_bytes()).is_ok() {
+            count += change.count;
+            files += 1;
+        } else {
+            failed.push(change.path.display().to_string());
+        }
+    }
+
+    editor.message = format!("Replaced {} occurrence{} in {} file{}", count, if count == 1 { "" } else { "s" }, files, if files == 1 { "" } else { "s" });
+    if !failed.is_empty() {
+        editor.message += &format!(", unable to write {}", failed.join(", "));
+    }
+    if !not_backed_up.is_empty() {
+        editor.message += &format!(", unable to back up {}", not_backed_up.join(", "));
+    }
+}
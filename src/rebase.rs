@@ -0,0 +1,85 @@
+use crate::{editor::Editor, text::TextBuffer};
+
+// The todo list `git rebase -i` opens: one commit a line as `pick <hash> <subject>`, with # comments explaining the
+// commands. With the rebase_keys variable on, which it is unless turned off, letters change the command on the line
+// rather than typing, and J and K move the line, since the order of the lines is the order commits are applied in
+pub const NAME: &str = "git-rebase-todo";
+
+// Commands that take a commit, with their one letter forms
+const COMMANDS: &[(&str, char)] = &[("pick", 'p'), ("reword", 'r'), ("edit", 'e'), ("squash", 's'), ("fixup", 'f'), ("drop", 'd')];
+
+// The others git knows, which are highlighted too but not changed by a keystroke
+const OTHERS: &[&str] = &["exec", "x", "break", "b", "label", "l", "reset", "t", "merge", "m", "update-ref", "u"];
+
+const HELP: &str = "p pick, r reword, e edit, s squash, f fixup, d drop, J/K move down/up; `rebase` to type instead";
+
+// The command taking a commit that starts a line, by its full name, and how long it is as written
+fn command_at(line: &str) -> Option<(&'static str, usize)> {
+    let word = line.split(' ').next()?;
+    COMMANDS.iter().find(|(name, letter)| word == *name || word.chars().eq([*letter])).map(|(name, _)| (*name, word.len()))
+}
+
+// The length of the command starting a line, if it starts with one
+pub fn keyword_len(line: &str) -> Option<usize> {
+    let word = line.split(' ').next()?;
+    command_at(line).map(|(_, length)| length).or(OTHERS.contains(&word).then_some(word.len()))
+}
+
+pub fn is_dropped(line: &str) -> bool {
+    command_at(line).is_some_and(|(name, _)| name == "drop")
+}
+
+// Handles a typed char in a todo list, returning whether it was used up rather than left to be typed
+pub fn handle_key(editor: &mut Editor, char: char) -> bool {
+    if char.is_control() {
+        return false;
+    }
+
+    match char {
+        'J' | 'K' => move_line(editor, char == 'J'),
+        char if let Some((name, _)) = COMMANDS.iter().find(|(_, letter)| *letter == char) => set_command(editor, name),
+        _ => editor.message = HELP.to_string(),
+    }
+    true
+}
+
+// What a typed char does in a todo list, for whichkey, matching handle_key
+pub fn describe(char: char) -> Option<String> {
+    match char {
+        char if char.is_control() => None,
+        'J' | 'K' => Some(format!("moves the line {}", if char == 'J' { "down" } else { "up" })),
+        char if let Some((name, _)) = COMMANDS.iter().find(|(_, letter)| *letter == char) => Some(format!("sets the line to {}", name)),
+        _ => Some("shows the todo list's keys".to_string()),
+    }
+}
+
+// Sets the command on the cursor line, or back to pick when it already has it
+fn set_command(editor: &mut Editor, name: &str) {
+    let line = editor.buffer[editor.line].clone();
+    let Some((current, length)) = command_at(&line) else {
+        editor.message = "Not a commit line".to_string();
+        return;
+    };
+
+    let name = if current == name { "pick" } else { name };
+    let line_index = editor.line;
+    editor.history.seal();
+    editor.replace_text(line_index, 0..length, name.to_string(), (line_index, 0));
+    editor.history.seal();
+    editor.message = format!("{} {}", name, line[length..].split_whitespace().next().unwrap_or(""));
+}
+
+// Swaps the cursor line with the one below or above, keeping the cursor on it
+fn move_line(editor: &mut Editor, down: bool) {
+    let line = editor.line;
+    let other = if down { line + 1 } else { line.wrapping_sub(1) };
+    if other >= editor.buffer.len() {
+        return;
+    }
+
+    let first = line.min(other);
+    let lines = vec![editor.buffer[first + 1].clone(), editor.buffer[first].clone()];
+    editor.history.seal();
+    editor.replace_lines(first, 2, lines, (other, 0));
+    editor.history.seal();
+}
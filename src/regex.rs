@@ -0,0 +1,511 @@
+// A small regular expression engine. Supports literals, ., [classes], \d \w \s (and their negations), ^ $ \b,
+// capturing and (?:non-capturing) groups, alternation, and the * + ? {m,n} quantifiers with lazy variants. Patterns
+// are compiled to a program run as a Pike VM: every way the pattern could be matching is stepped along the text
+// together, so matching takes the text's length times the program's at worst, with no recursion however long the
+// line or however the pattern nests. Matching works on chars so offsets line up with cursor columns
+
+#[derive(Clone, Debug)]
+enum ClassItem {
+    Range(char, char),
+    Digit(bool), // The bool negates, e.g. \D
+    Word(bool),
+    Space(bool),
+}
+
+impl ClassItem {
+    fn matches(&self, char: char, ignore_case: bool) -> bool {
+        match self {
+            ClassItem::Range(from, to) => {
+                (*from..=*to).contains(&char) || (ignore_case && char.to_lowercase().chain(char.to_uppercase()).any(|char| (*from..=*to).contains(&char)))
+            }
+            ClassItem::Digit(negated) => char.is_ascii_digit() != *negated,
+            ClassItem::Word(negated) => is_word(char) != *negated,
+            ClassItem::Space(negated) => char.is_whitespace() != *negated,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Node {
+    Char(char),
+    Any,
+    Class(Vec<ClassItem>, bool),
+    Start,
+    End,
+    WordBoundary(bool),
+    Group(Box<Node>, Option<usize>),
+    Concat(Vec<Node>),
+    Alternation(Vec<Node>),
+    Repeat { node: Box<Node>, min: usize, max: Option<usize>, greedy: bool },
+}
+
+// The class a \d, \w, or \s style escape stands for
+fn shorthand(escape: char) -> Option<ClassItem> {
+    match escape {
+        'd' => Some(ClassItem::Digit(false)),
+        'D' => Some(ClassItem::Digit(true)),
+        'w' => Some(ClassItem::Word(false)),
+        'W' => Some(ClassItem::Word(true)),
+        's' => Some(ClassItem::Space(false)),
+        'S' => Some(ClassItem::Space(true)),
+        _ => None,
+    }
+}
+
+pub fn is_word(char: char) -> bool {
+    char.is_alphanumeric() || char == '_'
+}
+
+// Capture group spans in chars, index 0 being the whole match
+pub type Captures = Vec<Option<(usize, usize)>>;
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    groups: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let char = self.peek()?;
+        self.pos += 1;
+        Some(char)
+    }
+
+    fn alternation(&mut self) -> Result<Node, String> {
+        let mut branches = vec![self.concat()?];
+        while self.peek() == Some('|') {
+            self.pos += 1;
+            branches.push(self.concat()?);
+        }
+
+        Ok(if branches.len() == 1 { branches.remove(0) } else { Node::Alternation(branches) })
+    }
+
+    fn concat(&mut self) -> Result<Node, String> {
+        let mut nodes = vec![];
+        while let Some(char) = self.peek() {
+            if char == '|' || char == ')' {
+                break;
+            }
+            let atom = self.atom()?;
+            nodes.push(self.quantifier(atom)?);
+        }
+
+        Ok(Node::Concat(nodes))
+    }
+
+    fn quantifier(&mut self, atom: Node) -> Result<Node, String> {
+        let (min, max) = match self.peek() {
+            Some('*') => (0, None),
+            Some('+') => (1, None),
+            Some('?') => (0, Some(1)),
+            Some('{') => match self.braces() {
+                Some(bounds) => bounds,
+                None => return Ok(atom), // Not a valid repetition, so the brace is a literal
+            },
+            _ => return Ok(atom),
+        };
+
+        self.pos += 1; // Past the quantifier, or the closing brace
+        if matches!(atom, Node::Start | Node::End | Node::WordBoundary(_)) {
+            return Err("nothing to repeat".to_string());
+        }
+
+        let greedy = if self.peek() == Some('?') {
+            self.pos += 1;
+            false
+        } else {
+            true
+        };
+
+        if max.is_some_and(|max| max < min) {
+            return Err("repetition range is backwards".to_string());
+        }
+
+        Ok(Node::Repeat { node: Box::new(atom), min, max, greedy })
+    }
+
+    // Parses {m}, {m,}, or {m,n}, leaving pos on the closing brace
+    fn braces(&mut self) -> Option<(usize, Option<usize>)> {
+        let close = self.chars[self.pos..].iter().position(|char| *char == '}')? + self.pos;
+        let inner = self.chars[self.pos + 1..close].iter().collect::<String>();
+
+        let bounds = match inner.split_once(',') {
+            Some((min, "")) => (min.trim().parse().ok()?, None),
+            Some((min, max)) => (min.trim().parse().ok()?, Some(max.trim().parse().ok()?)),
+            None => {
+                let count = inner.trim().parse().ok()?;
+                (count, Some(count))
+            }
+        };
+
+        self.pos = close;
+        Some(bounds)
+    }
+
+    fn escape(&mut self) -> Result<Node, String> {
+        let Some(char) = self.next() else { return Err("trailing backslash".to_string()) };
+
+        if let Some(item) = shorthand(char) {
+            return Ok(Node::Class(vec![item], false));
+        }
+
+        Ok(match char {
+            'b' => Node::WordBoundary(true),
+            'B' => Node::WordBoundary(false),
+            't' => Node::Char('\t'),
+            'n' => Node::Char('\n'),
+            char => Node::Char(char),
+        })
+    }
+
+    fn class(&mut self) -> Result<Node, String> {
+        let negated = self.peek() == Some('^');
+        if negated {
+            self.pos += 1;
+        }
+
+        let mut items = vec![];
+        let mut first = true;
+        loop {
+            let Some(char) = self.next() else { return Err("unterminated character class".to_string()) };
+
+            let start = match char {
+                ']' if !first => break,
+                '\\' => match self.next() {
+                    Some(escape) if shorthand(escape).is_some() => {
+                        items.extend(shorthand(escape));
+                        first = false;
+                        continue;
+                    }
+                    Some('t') => '\t',
+                    Some('n') => '\n',
+                    Some(char) => char,
+                    None => return Err("trailing backslash".to_string()),
+                },
+                char => char,
+            };
+            first = false;
+
+            if self.peek() == Some('-') && self.chars.get(self.pos + 1).is_some_and(|char| *char != ']') {
+                self.pos += 1;
+                let end = match self.next() {
+                    Some('\\') => self.next().ok_or("trailing backslash".to_string())?,
+                    Some(char) => char,
+                    None => return Err("unterminated character class".to_string()),
+                };
+
+                if end < start {
+                    return Err(format!("invalid class range {}-{}", start, end));
+                }
+                items.push(ClassItem::Range(start, end));
+            } else {
+                items.push(ClassItem::Range(start, start));
+            }
+        }
+
+        Ok(Node::Class(items, negated))
+    }
+
+    fn atom(&mut self) -> Result<Node, String> {
+        let char = self.next().ok_or("unexpected end of pattern".to_string())?;
+
+        match char {
+            '.' => Ok(Node::Any),
+            '^' => Ok(Node::Start),
+            '$' => Ok(Node::End),
+            '\\' => self.escape(),
+            '[' => self.class(),
+            '(' => {
+                let index = if self.chars[self.pos..].starts_with(&['?', ':']) {
+                    self.pos += 2;
+                    None
+                } else {
+                    self.groups += 1;
+                    Some(self.groups)
+                };
+
+                let inner = self.alternation()?;
+                if self.next() != Some(')') {
+                    return Err("unbalanced parenthesis".to_string());
+                }
+
+                Ok(Node::Group(Box::new(inner), index))
+            }
+            ')' => Err("unbalanced parenthesis".to_string()),
+            '*' | '+' | '?' => Err("nothing to repeat".to_string()),
+            char => Ok(Node::Char(char)),
+        }
+    }
+}
+
+// One step of a compiled pattern. Threads run from the first, moving on to the next unless told otherwise
+#[derive(Debug)]
+enum Inst {
+    Char(char),
+    Any,
+    Class(Vec<ClassItem>, bool),
+    Start,
+    End,
+    WordBoundary(bool),
+    Save(usize), // Records the position in a capture slot, two to a group: its start, then its end
+    Split(usize, usize), // Carries on at both, the first taking priority
+    Jump(usize),
+    Match,
+}
+
+// Repeats are compiled into copies of what they repeat, so a program bigger than this is refused rather than built
+const MAX_PROGRAM: usize = 50_000;
+
+// The split a repeat loops or skips with: greedy ones try the repeated part first, lazy ones what comes after
+fn repeat_split(first: usize, second: usize, greedy: bool) -> Inst {
+    if greedy { Inst::Split(first, second) } else { Inst::Split(second, first) }
+}
+
+struct Compiler {
+    program: Vec<Inst>,
+}
+
+impl Compiler {
+    fn emit(&mut self, inst: Inst) -> usize {
+        self.program.push(inst);
+        self.program.len() - 1
+    }
+
+    fn compile(&mut self, node: &Node) -> Result<(), String> {
+        if self.program.len() > MAX_PROGRAM {
+            return Err("pattern is too large".to_string());
+        }
+
+        match node {
+            Node::Char(char) => _ = self.emit(Inst::Char(*char)),
+            Node::Any => _ = self.emit(Inst::Any),
+            Node::Class(items, negated) => _ = self.emit(Inst::Class(items.clone(), *negated)),
+            Node::Start => _ = self.emit(Inst::Start),
+            Node::End => _ = self.emit(Inst::End),
+            Node::WordBoundary(expected) => _ = self.emit(Inst::WordBoundary(*expected)),
+            Node::Group(inner, Some(index)) => {
+                self.emit(Inst::Save(2 * index));
+                self.compile(inner)?;
+                self.emit(Inst::Save(2 * index + 1));
+            }
+            Node::Group(inner, None) => self.compile(inner)?,
+            Node::Concat(nodes) => {
+                for node in nodes {
+                    self.compile(node)?;
+                }
+            }
+            // Each branch but the last splits off the ones after it, and jumps past them once it has matched
+            Node::Alternation(branches) => {
+                let mut jumps = vec![];
+                for (index, branch) in branches.iter().enumerate() {
+                    if index + 1 == branches.len() {
+                        self.compile(branch)?;
+                        break;
+                    }
+                    let split = self.emit(Inst::Jump(0));
+                    self.compile(branch)?;
+                    jumps.push(self.emit(Inst::Jump(0)));
+                    self.program[split] = Inst::Split(split + 1, self.program.len());
+                }
+                let end = self.program.len();
+                for jump in jumps {
+                    self.program[jump] = Inst::Jump(end);
+                }
+            }
+            Node::Repeat { node, min, max, greedy } => {
+                if *min > MAX_PROGRAM || max.is_some_and(|max| max > MAX_PROGRAM) {
+                    return Err("repetition count is too large".to_string());
+                }
+                for _ in 0..*min {
+                    self.compile(node)?;
+                }
+                match max {
+                    None => {
+                        let split = self.emit(Inst::Jump(0));
+                        self.compile(node)?;
+                        self.emit(Inst::Jump(split));
+                        self.program[split] = repeat_split(split + 1, self.program.len(), *greedy);
+                    }
+                    // Each optional copy can be skipped, going straight past the last
+                    Some(max) => {
+                        let mut splits = vec![];
+                        for _ in *min..*max {
+                            splits.push(self.emit(Inst::Jump(0)));
+                            self.compile(node)?;
+                        }
+                        let end = self.program.len();
+                        for split in splits {
+                            self.program[split] = repeat_split(split + 1, end, *greedy);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// Capture slots, two to a group, where a thread has been so far
+type Slots = Vec<Option<usize>>;
+
+// The threads at one position in the text, in priority order, each at a different instruction
+struct Threads {
+    list: Vec<(usize, Slots)>,
+    seen: Vec<bool>,
+}
+
+impl Threads {
+    fn new(size: usize) -> Threads {
+        Threads { list: vec![], seen: vec![false; size] }
+    }
+
+    fn clear(&mut self) {
+        self.list.clear();
+        self.seen.fill(false);
+    }
+}
+
+pub struct Regex {
+    program: Vec<Inst>,
+    groups: usize,
+    ignore_case: bool,
+}
+
+impl Regex {
+    pub fn new(pattern: &str, ignore_case: bool) -> Result<Regex, String> {
+        let mut parser = Parser { chars: pattern.chars().collect(), pos: 0, groups: 0 };
+        let node = parser.alternation()?;
+
+        if parser.pos != parser.chars.len() {
+            return Err("unbalanced parenthesis".to_string());
+        }
+
+        // The whole match is group 0
+        let mut compiler = Compiler { program: vec![] };
+        compiler.compile(&Node::Group(Box::new(node), Some(0)))?;
+        compiler.emit(Inst::Match);
+        Ok(Regex { program: compiler.program, groups: parser.groups, ignore_case })
+    }
+
+    // Finds the leftmost match starting at or after `start`. A new thread starts at each position until something
+    // matches, after the others so an earlier start wins, and a thread reaching the end cuts off those behind it
+    pub fn captures_from(&self, text: &[char], start: usize) -> Option<Captures> {
+        let mut current = Threads::new(self.program.len());
+        let mut next = Threads::new(self.program.len());
+        let mut slots = vec![None; 2 * (self.groups + 1)];
+        let mut matched = None;
+
+        for pos in start..=text.len() {
+            if matched.is_none() {
+                slots.fill(None);
+                self.add(&mut current, 0, pos, text, &mut slots);
+            } else if current.list.is_empty() {
+                break;
+            }
+
+            for (pc, thread) in &current.list {
+                match &self.program[*pc] {
+                    Inst::Match => {
+                        matched = Some(thread.clone());
+                        break;
+                    }
+                    inst if text.get(pos).is_some_and(|char| self.consumes(inst, *char)) => {
+                        self.add(&mut next, pc + 1, pos + 1, text, &mut thread.clone());
+                    }
+                    _ => {}
+                }
+            }
+            std::mem::swap(&mut current, &mut next);
+            next.clear();
+        }
+
+        let slots = matched?;
+        Some((0..=self.groups).map(|group| Some((slots[2 * group]?, slots[2 * group + 1]?))).collect())
+    }
+
+    // All non-overlapping matches in `text`. An empty match moves on by one char so this always terminates
+    pub fn captures_all(&self, text: &[char]) -> Vec<Captures> {
+        let mut all = vec![];
+        let mut start = 0;
+
+        while start <= text.len() {
+            let Some(captures) = self.captures_from(text, start) else { break };
+            let (match_start, match_end) = captures[0].unwrap_or((start, start));
+
+            start = if match_end == match_start { match_end + 1 } else { match_end };
+            all.push(captures);
+        }
+
+        all
+    }
+
+    fn char_matches(&self, pattern: char, char: char) -> bool {
+        pattern == char || (self.ignore_case && pattern.to_lowercase().eq(char.to_lowercase()))
+    }
+
+    // Whether an instruction that takes a char takes this one
+    fn consumes(&self, inst: &Inst, char: char) -> bool {
+        match inst {
+            Inst::Char(pattern) => self.char_matches(*pattern, char),
+            Inst::Any => char != '\n',
+            Inst::Class(items, negated) => items.iter().any(|item| item.matches(char, self.ignore_case)) != *negated,
+            _ => false,
+        }
+    }
+
+    // Adds a thread at `pc`, following jumps, splits, saves and assertions through to the instructions that take a
+    // char or match. A stack stands in for recursion, with entries putting back the slots a save changed once the
+    // paths after it have been added. An instruction already reached at this position is skipped, as the thread
+    // there first has priority, which also stops repeats of something empty from looping
+    fn add(&self, threads: &mut Threads, pc: usize, pos: usize, text: &[char], slots: &mut Slots) {
+        enum Step {
+            Visit(usize),
+            Restore(usize, Option<usize>),
+        }
+
+        let mut stack = vec![Step::Visit(pc)];
+        while let Some(step) = stack.pop() {
+            let pc = match step {
+                Step::Visit(pc) => pc,
+                Step::Restore(slot, old) => {
+                    slots[slot] = old;
+                    continue;
+                }
+            };
+            if threads.seen[pc] {
+                continue;
+            }
+            threads.seen[pc] = true;
+
+            match &self.program[pc] {
+                Inst::Jump(to) => stack.push(Step::Visit(*to)),
+                Inst::Split(first, second) => {
+                    stack.push(Step::Visit(*second));
+                    stack.push(Step::Visit(*first));
+                }
+                Inst::Save(slot) => {
+                    stack.push(Step::Restore(*slot, slots[*slot]));
+                    slots[*slot] = Some(pos);
+                    stack.push(Step::Visit(pc + 1));
+                }
+                Inst::Start if pos == 0 => stack.push(Step::Visit(pc + 1)),
+                Inst::End if pos == text.len() => stack.push(Step::Visit(pc + 1)),
+                Inst::WordBoundary(expected) => {
+                    let before = pos > 0 && is_word(text[pos - 1]);
+                    let after = pos < text.len() && is_word(text[pos]);
+                    if (before != after) == *expected {
+                        stack.push(Step::Visit(pc + 1));
+                    }
+                }
+                Inst::Start | Inst::End => {}
+                _ => threads.list.push((pc, slots.clone())),
+            }
+        }
+    }
+}
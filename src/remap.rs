@@ -0,0 +1,34 @@
+use crate::text::TextBuffer;
+
+// Works out where a position in the old text of a buffer ends up after the whole buffer is replaced, as when a
+// formatter rewrites it. Lines the same at the start or end of both texts keep their place; a line in between is
+// looked for by its content, ignoring indentation, near where it would be if the changed block was stretched
+pub fn remap_line(old: &impl TextBuffer, new: &[String], line: usize) -> Option<usize> {
+    let prefix = old.iter().zip(new).take_while(|(old, new)| old == new).count();
+    let suffix = old.lines(prefix..).rev().zip(new[prefix..].iter().rev()).take_while(|(old, new)| old == new).count();
+    let (old_end, new_end) = (old.len() - suffix, new.len() - suffix);
+
+    if line < prefix {
+        return Some(line);
+    }
+    if line >= old_end {
+        return Some(line + new_end - old_end);
+    }
+
+    let text = old[line].trim();
+    let estimate = prefix + (line - prefix) * (new_end - prefix) / (old_end - prefix);
+    (prefix..new_end).filter(|index| new[*index].trim() == text).min_by_key(|index| index.abs_diff(estimate))
+}
+
+// The cursor's new position: its line remapped, and its column moved by however much the line's indentation changed.
+// A line that can't be found keeps its number, clamped to the new text
+pub fn remap_cursor(old: &impl TextBuffer, new: &[String], (line, column): (usize, usize)) -> (usize, usize) {
+    let Some(new_line) = remap_line(old, new, line) else {
+        let line = line.min(new.len() - 1);
+        return (line, column.min(new[line].chars().count()));
+    };
+
+    let indent = |text: &str| text.chars().take_while(|char| char.is_whitespace()).count();
+    let column = (column + indent(&new[new_line])).saturating_sub(indent(&old[line]));
+    (new_line, column.min(new[new_line].chars().count()))
+}
@@ -0,0 +1,362 @@
+use crate::{editor::Editor, input::Key, regex::{Captures, Regex}, text::TextBuffer};
+
+#[derive(Clone, Copy, Default)]
+pub struct ReplaceOptions {
+    pub ignore_case: bool,
+    pub preserve_case: bool, // Implies ignore_case
+    pub regex: bool, // Also enables $1 and \U style escapes in the replacement
+}
+
+pub enum Matcher {
+    Literal(Vec<char>, bool),
+    Regex(Regex),
+}
+
+impl Matcher {
+    pub fn new(pattern: &str, options: ReplaceOptions) -> Result<Matcher, String> {
+        let ignore_case = options.ignore_case || options.preserve_case;
+
+        if options.regex {
+            Regex::new(pattern, ignore_case).map(Matcher::Regex)
+        } else {
+            Ok(Matcher::Literal(pattern.chars().collect(), ignore_case))
+        }
+    }
+
+    pub fn find_all(&self, line: &[char]) -> Vec<Captures> {
+        match self {
+            Matcher::Literal(pattern, ignore_case) => find_matches(line, pattern, *ignore_case).into_iter().map(|span| vec![Some(span)]).collect(),
+            Matcher::Regex(regex) => regex.captures_all(line),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum CaseConversion {
+    None,
+    Upper,
+    Lower,
+}
+
+// Expands a regex replacement: $1 or ${1} insert a capture group ($0 is the whole match, $$ is a literal $),
+// \U and \L convert what follows to upper or lower case until \E, and \u and \l convert just the next char
+pub fn expand(replacement: &str, captures: &Captures, text: &[char]) -> String {
+    let mut out = String::new();
+    let mut conversion = CaseConversion::None;
+    let mut next_char: Option<CaseConversion> = None;
+    let mut chars = replacement.chars().peekable();
+
+    let push = |out: &mut String, char: char, conversion: CaseConversion, next_char: &mut Option<CaseConversion>| {
+        match next_char.take().unwrap_or(conversion) {
+            CaseConversion::Upper => out.extend(char.to_uppercase()),
+            CaseConversion::Lower => out.extend(char.to_lowercase()),
+            CaseConversion::None => out.push(char),
+        }
+    };
+
+    while let Some(char) = chars.next() {
+        match char {
+            '$' => {
+                let group = if chars.peek() == Some(&'{') {
+                    chars.next();
+                    let digits = chars.by_ref().take_while(|char| *char != '}').collect::<String>();
+                    digits.parse::<usize>().ok()
+                } else if chars.peek().is_some_and(char::is_ascii_digit) {
+                    chars.next().and_then(|digit| digit.to_digit(10)).map(|digit| digit as usize)
+                } else {
+                    if chars.peek() == Some(&'$') {
+                        chars.next();
+                    }
+                    push(&mut out, '$', conversion, &mut next_char);
+                    continue;
+                };
+
+                if let Some((start, end)) = group.and_then(|group| captures.get(group).copied().flatten()) {
+                    for char in &text[start..end] {
+                        push(&mut out, *char, conversion, &mut next_char);
+                    }
+                }
+            }
+            '\\' => match chars.next() {
+                Some('U') => conversion = CaseConversion::Upper,
+                Some('L') => conversion = CaseConversion::Lower,
+                Some('E') => conversion = CaseConversion::None,
+                Some('u') => next_char = Some(CaseConversion::Upper),
+                Some('l') => next_char = Some(CaseConversion::Lower),
+                Some('n') => out.push('\n'),
+                Some('t') => push(&mut out, '\t', conversion, &mut next_char),
+                Some(char) => push(&mut out, char, conversion, &mut next_char),
+                None => out.push('\\'),
+            },
+            char => push(&mut out, char, conversion, &mut next_char),
+        }
+    }
+
+    out
+}
+
+fn chars_equal(a: char, b: char, ignore_case: bool) -> bool {
+    a == b || (ignore_case && a.to_lowercase().eq(b.to_lowercase()))
+}
+
+// Char ranges (start, end exclusive) of non-overlapping matches of `pattern` in `line`
+pub fn find_matches(line: &[char], pattern: &[char], ignore_case: bool) -> Vec<(usize, usize)> {
+    let mut matches = vec![];
+    if pattern.is_empty() {
+        return matches;
+    }
+
+    let mut start = 0;
+    while start + pattern.len() <= line.len() {
+        if line[start..start + pattern.len()].iter().zip(pattern).all(|(a, b)| chars_equal(*a, *b, ignore_case)) {
+            matches.push((start, start + pattern.len()));
+            start += pattern.len();
+        } else {
+            start += 1;
+        }
+    }
+
+    matches
+}
+
+// Gives the replacement the same shape of case as the text it replaces: foo -> bar, Foo -> Bar, FOO -> BAR.
+// Anything else (mixed case) gets the replacement as typed
+pub fn adapt_case(matched: &str, replacement: &str) -> String {
+    let letters = matched.chars().filter(|char| char.is_alphabetic()).collect::<Vec<char>>();
+    if letters.is_empty() {
+        return replacement.to_string();
+    }
+
+    if letters.iter().all(|char| char.is_uppercase()) && letters.len() > 1 {
+        return replacement.to_uppercase();
+    }
+
+    if letters.iter().all(|char| char.is_lowercase()) {
+        return replacement.to_lowercase();
+    }
+
+    if letters[0].is_uppercase() && letters[1..].iter().all(|char| char.is_lowercase()) {
+        let mut chars = replacement.chars();
+        return match chars.next() {
+            Some(first) => first.to_uppercase().chain(chars.flat_map(char::to_lowercase)).collect(),
+            None => String::new(),
+        };
+    }
+
+    replacement.to_string()
+}
+
+// What one match is replaced with
+fn replacement_for(chars: &[char], captures: &Captures, replacement: &str, options: ReplaceOptions) -> String {
+    let Some((start, end)) = captures[0] else { return String::new() };
+    let matched = chars[start..end].iter().collect::<String>();
+    let expanded = if options.regex { expand(replacement, captures, chars) } else { replacement.to_string() };
+    if options.preserve_case { adapt_case(&matched, &expanded) } else { expanded }
+}
+
+// Replaces every match on one line, returning the new line and how many were replaced, or None if nothing matched
+pub fn replace_line(line: &str, matcher: &Matcher, replacement: &str, options: ReplaceOptions) -> Option<(String, usize)> {
+    let chars = line.chars().collect::<Vec<char>>();
+    let matches = matcher.find_all(&chars);
+    if matches.is_empty() {
+        return None;
+    }
+
+    let mut new_line = String::new();
+    let mut last = 0;
+    for captures in &matches {
+        let Some((start, end)) = captures[0] else { continue };
+        new_line.extend(&chars[last..start]);
+
+        new_line += &replacement_for(&chars, captures, replacement, options);
+        last = end;
+    }
+    new_line.extend(&chars[last..]);
+
+    Some((new_line, matches.len()))
+}
+
+// A replacement with \n in it breaks the line it lands on, as each buffer line holds exactly one line
+fn split_lines(line: &str) -> Vec<String> {
+    line.split('\n').map(str::to_string).collect()
+}
+
+// Replaces every match in the buffer as a single undo step, returning how many were replaced
+pub fn replace_all(editor: &mut Editor, matcher: &Matcher, replacement: &str, options: ReplaceOptions) -> usize {
+    let mut count = 0;
+    let mut new_lines: Vec<Option<String>> = vec![None; editor.buffer.len()];
+
+    for (index, line) in editor.buffer.iter().enumerate() {
+        let Some((new_line, replaced)) = replace_line(line, matcher, replacement, options) else { continue };
+
+        new_lines[index] = Some(new_line);
+        count += replaced;
+    }
+
+    let (Some(first), Some(last)) = (new_lines.iter().position(Option::is_some), new_lines.iter().rposition(Option::is_some)) else { return count };
+    let lines = (first..=last).flat_map(|index| split_lines(new_lines[index].as_deref().unwrap_or(&editor.buffer[index]))).collect::<Vec<String>>();
+    // The cursor keeps its line, moving down with it past any lines added above
+    let added = lines.len() - (last - first + 1);
+    let (line, text) = match editor.line {
+        line if line > last => (line + added, &editor.buffer[line]),
+        line if line >= first => (line, &lines[line - first]),
+        line => (line, &editor.buffer[line]),
+    };
+    let column = editor.column.min(text.chars().count());
+    editor.history.seal();
+    editor.replace_lines(first, last - first + 1, lines, (line, column));
+    editor.history.seal();
+
+    count
+}
+
+// Asks for a pattern and a replacement on the message row, then confirms each match
+pub fn prompt_and_confirm(editor: &mut Editor) {
+    let Some(pattern) = editor.prompt("Replace: ").filter(|pattern| !pattern.is_empty()) else { return };
+    let Some(replacement) = editor.prompt(&format!("Replace {} with: ", pattern)) else { return };
+
+    let options = ReplaceOptions::default();
+    let matcher = match Matcher::new(&pattern, options) {
+        Ok(matcher) => matcher,
+        Err(err) => {
+            editor.message = format!("Invalid pattern: {}", err);
+            return;
+        }
+    };
+
+    let count = confirm_each(editor, &matcher, &replacement, options, ask);
+    editor.message = format!("Replaced {} occurrence{}", count, if count == 1 { "" } else { "s" });
+}
+
+// Asks on the message row what to do with the selected match, until a key that answers
+fn ask(editor: &mut Editor) -> char {
+    editor.message = "Replace? (y)es, (n)o, (a)ll, (q)uit".to_string();
+    editor.scroll();
+    loop {
+        match editor.draw().map(|()| editor.read_key()) {
+            Ok(Key::Char(answer)) => return answer,
+            Ok(Key::Escape) | Err(_) => return 'q',
+            _ => {}
+        }
+    }
+}
+
+// Walks the matches from the cursor to the end of the buffer and around from the top, selecting each and asking
+// what to do: y replaces it, n skips it, a replaces it and all the rest, q or Esc stops. All the replacements
+// together are a single undo step. Returns how many were replaced
+pub fn confirm_each(editor: &mut Editor, matcher: &Matcher, replacement: &str, options: ReplaceOptions, mut ask: impl FnMut(&mut Editor) -> char) -> usize {
+    let original = editor.buffer.clone();
+    let start = (editor.line, editor.column);
+    // Where the walk stops after wrapping around, which moves as replacements before it change the text
+    let mut stop = start;
+    let (mut line, mut column) = start;
+    let mut wrapped = false;
+    let mut all = false;
+    let mut count = 0;
+
+    loop {
+        let chars = editor.buffer[line].chars().collect::<Vec<char>>();
+        let found = matcher.find_all(&chars).into_iter().find(|captures| {
+            captures[0].is_some_and(|(from, _)| from >= column && (!wrapped || line != stop.0 || from < stop.1))
+        });
+
+        let Some(captures) = found else {
+            // Back round to where it started, which may be on the last line, so this goes before wrapping
+            if wrapped && line >= stop.0 {
+                break;
+            }
+            (line, column) = (line + 1, 0);
+            if line == editor.buffer.len() {
+                (line, wrapped) = (0, true);
+            }
+            continue;
+        };
+
+        let Some((from, to)) = captures[0] else { break };
+        editor.selection = Some((line, from));
+        editor.goto((line, to));
+
+        let answer = if all { 'a' } else { ask(editor) };
+
+        match answer {
+            'y' | 'a' => {
+                let replaced = replacement_for(&chars, &captures, replacement, options);
+                let lines = split_lines(&(chars[..from].iter().collect::<String>() + &replaced + &chars[to..].iter().collect::<String>()));
+                let added = lines.len() - 1;
+                editor.buffer.splice_lines(line, 1, lines);
+                if wrapped && line == stop.0 {
+                    stop = (stop.0 + added, (stop.1 + editor.buffer[line + added].chars().count()).saturating_sub(chars.len()));
+                } else if wrapped && line < stop.0 {
+                    stop.0 += added;
+                }
+                line += added;
+                let after = editor.buffer[line].chars().count() - (chars.len() - to);
+                column = if to > from { after } else { after + 1 }; // Step past empty matches
+                editor.goto((line, after));
+                all = answer == 'a';
+                count += 1;
+            }
+            'n' => column = if to > from { to } else { from + 1 },
+            'q' | '\x03' => break,
+            _ => {}
+        }
+    }
+
+    editor.selection = None;
+    editor.message.clear();
+
+    // The buffer was edited in place to show each replacement as it happened. Put it back and redo it all as one
+    // change, from the first line that differs to the last, counted from each end as \n may have added lines
+    if editor.buffer != original {
+        let first = original.iter().zip(editor.buffer.iter()).take_while(|(old, new)| old == new).count();
+        let same_end = original.lines(first..).rev().zip(editor.buffer.lines(first..).rev()).take_while(|(old, new)| old == new).count();
+        let new = editor.buffer.lines(first..editor.buffer.len() - same_end).cloned().collect();
+        let cursor = (editor.line, editor.column);
+        editor.buffer = original.clone();
+        (editor.line, editor.column) = start;
+        editor.history.seal();
+        editor.replace_lines(first, original.len() - same_end - first, new, cursor);
+        editor.history.seal();
+    }
+
+    count
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::config::Config;
+
+    fn editor(lines: &[&str]) -> Editor {
+        Editor::new(lines.iter().map(|line| line.to_string()).collect(), PathBuf::from("test.txt"), false, Config::default(), None)
+    }
+
+    #[test]
+    fn confirm_each_stops_in_a_one_line_buffer() {
+        let mut editor = editor(&["foo"]);
+        let matcher = Matcher::new("foo", ReplaceOptions::default()).unwrap();
+        let mut asked = 0;
+        let count = confirm_each(&mut editor, &matcher, "bar", ReplaceOptions::default(), |_| {
+            asked += 1;
+            'n'
+        });
+        assert_eq!((count, asked), (0, 1));
+        assert_eq!(editor.buffer.to_vec(), ["foo"]);
+    }
+
+    #[test]
+    fn confirm_each_wraps_from_the_last_line() {
+        let mut editor = editor(&["foo", "foo foo"]);
+        editor.line = 1;
+        let matcher = Matcher::new("foo", ReplaceOptions::default()).unwrap();
+        let mut asked = 0;
+        let count = confirm_each(&mut editor, &matcher, "bar", ReplaceOptions::default(), |_| {
+            asked += 1;
+            'y'
+        });
+        assert_eq!((count, asked), (3, 3));
+        assert_eq!(editor.buffer.to_vec(), ["bar", "bar bar"]);
+    }
+}
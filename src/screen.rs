@@ -0,0 +1,77 @@
+use std::io::{self, Write};
+
+use crate::terminal::{self, clear_screen};
+
+// The last frame on the terminal, by row, so drawing the next one only rewrites the rows that changed rather than
+// resetting the terminal and repainting everything, which flickers over slow connections. Frames are drawn into
+// memory by the same code as before: every piece of a row starts by moving the cursor to it, so splitting the
+// frame where the cursor moves gives what each row shows
+#[derive(Default)]
+pub struct Screen {
+    rows: Vec<Vec<u8>>,
+    size: (usize, usize),
+    clears: usize, // terminal::clears() when the last frame was drawn
+}
+
+impl Screen {
+    // Writes a frame drawn into memory, returning whether the terminal was reset first. That happens for the first
+    // frame, after a resize, and after something else cleared the screen, since what the last frame left there is gone
+    pub fn update(&mut self, out: &mut impl Write, frame: &[u8], size: (usize, usize)) -> io::Result<bool> {
+        let rows = split_rows(frame, size.1);
+        let full = self.rows.is_empty() || self.size != size || self.clears != terminal::clears();
+        if full {
+            clear_screen(out)?;
+        }
+
+        for (row, bytes) in rows.iter().enumerate() {
+            if full {
+                out.write_all(bytes)?;
+            } else if self.rows.get(row) != Some(bytes) {
+                // The rest of the frame was drawn onto blank rows
+                write!(out, "\x1b[{};1H\x1b[0m\x1b[2K", row + 1)?;
+                out.write_all(bytes)?;
+            }
+        }
+
+        self.rows = rows;
+        self.size = size;
+        self.clears = terminal::clears();
+        Ok(full)
+    }
+}
+
+// The bytes drawn on each of `height` rows, going by the \x1b[row;columnH sequences that move the cursor
+fn split_rows(frame: &[u8], height: usize) -> Vec<Vec<u8>> {
+    let mut rows = vec![vec![]; height];
+    let mut row = 0;
+    let mut start = 0;
+    let mut index = 0;
+    while index < frame.len() {
+        if let Some((target, length)) = cursor_move(&frame[index..]) {
+            if let Some(bytes) = rows.get_mut(row) {
+                bytes.extend_from_slice(&frame[start..index]);
+            }
+            row = target;
+            start = index;
+            index += length;
+        } else {
+            index += 1;
+        }
+    }
+    if let Some(bytes) = rows.get_mut(row) {
+        bytes.extend_from_slice(&frame[start..]);
+    }
+    rows
+}
+
+// The row a cursor position sequence at the start of `bytes` moves to, and its length
+fn cursor_move(bytes: &[u8]) -> Option<(usize, usize)> {
+    let parameters = bytes.strip_prefix(b"\x1b[")?;
+    let length = parameters.iter().position(|byte| !byte.is_ascii_digit() && *byte != b';')?;
+    if parameters[length] != b'H' {
+        return None;
+    }
+
+    let row = parameters[..length].split(|byte| *byte == b';').next().and_then(|row| std::str::from_utf8(row).ok()?.parse::<usize>().ok());
+    Some((row.unwrap_or(1).saturating_sub(1), length + 3))
+}
@@ -0,0 +1,44 @@
+use std::{fs, path::Path, time::{Duration, Instant, SystemTime, UNIX_EPOCH}};
+
+use crate::config::Config;
+
+// Where laptops report their batteries
+const POWER_SUPPLIES: &str = "/sys/class/power_supply";
+
+// The local time as "14:05"
+fn clock() -> Option<String> {
+    let now = unsafe { libc::time(std::ptr::null_mut()) };
+    let mut tm = unsafe { std::mem::zeroed::<libc::tm>() };
+    if unsafe { libc::localtime_r(&now, &mut tm) }.is_null() {
+        return None;
+    }
+    Some(format!("{:02}:{:02}", tm.tm_hour, tm.tm_min))
+}
+
+// The charge of the first battery as "bat 87%", with a + while it charges. None on machines without one
+fn battery() -> Option<String> {
+    let read = |path: &Path| fs::read_to_string(path).ok().map(|text| text.trim().to_string());
+    let mut supplies = fs::read_dir(POWER_SUPPLIES).ok()?.filter_map(|entry| entry.ok().map(|entry| entry.path())).collect::<Vec<_>>();
+    supplies.sort();
+
+    let supply = supplies.into_iter().find(|supply| read(&supply.join("type")).is_some_and(|kind| kind == "Battery"))?;
+    let capacity = read(&supply.join("capacity"))?;
+    let charging = read(&supply.join("status")).is_some_and(|status| status == "Charging");
+    Some(format!("bat {}%{}", capacity, if charging { "+" } else { "" }))
+}
+
+// The enabled segments, separated by two spaces
+pub fn render(config: &Config) -> String {
+    let segments = [
+        config.status_battery.then(battery).flatten(),
+        config.status_clock.then(clock).flatten(),
+    ];
+    segments.into_iter().flatten().collect::<Vec<String>>().join("  ")
+}
+
+// When the segments next need redrawing: at the start of the next minute, when the clock changes. Battery levels
+// don't need watching more closely than that
+pub fn next_update() -> Instant {
+    let secs = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_secs());
+    Instant::now() + Duration::from_secs(60 - secs % 60)
+}
@@ -0,0 +1,65 @@
+use crate::text::TextBuffer;
+
+// Statistics for the status bar while a selection is active
+pub struct SelectionStats {
+    pub lines: usize,
+    pub words: usize,
+    pub chars: usize,
+    pub sum: Option<f64>,
+}
+
+impl SelectionStats {
+    pub fn new(text: &str) -> SelectionStats {
+        let words = text.split_whitespace().collect::<Vec<&str>>();
+        let numbers = words.iter().map(|word| word.trim_end_matches(',').parse::<f64>()).collect::<Result<Vec<f64>, _>>().ok();
+
+        SelectionStats {
+            lines: text.split('\n').count(),
+            words: words.len(),
+            chars: text.chars().filter(|char| *char != '\n').count(),
+            sum: numbers.filter(|numbers| !numbers.is_empty()).map(|numbers| numbers.iter().sum()),
+        }
+    }
+}
+
+impl std::fmt::Display for SelectionStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{} lines, {} words, {} chars", self.lines, self.words, self.chars)?;
+        match self.sum {
+            Some(sum) if sum.fract() == 0.0 && sum.abs() < 1e15 => write!(f, ", sum {}", sum as i64),
+            Some(sum) => write!(f, ", sum {}", sum),
+            None => Ok(()),
+        }
+    }
+}
+
+// Orders an anchor and the cursor into (start, end) positions
+pub fn ordered(anchor: (usize, usize), cursor: (usize, usize)) -> ((usize, usize), (usize, usize)) {
+    if anchor <= cursor { (anchor, cursor) } else { (cursor, anchor) }
+}
+
+// The selected char columns on `line`, end exclusive. A selection that continues past the end of the line
+// also covers one cell after it, so selected line breaks are visible
+pub fn columns_on_line(start: (usize, usize), end: (usize, usize), line: usize, line_len: usize) -> Option<(usize, usize)> {
+    if line < start.0 || line > end.0 {
+        return None;
+    }
+
+    let from = if line == start.0 { start.1 } else { 0 };
+    let to = if line == end.0 { end.1 } else { line_len + 1 };
+    Some((from, to))
+}
+
+pub fn text(buffer: &impl TextBuffer, start: (usize, usize), end: (usize, usize)) -> String {
+    let mut text = String::new();
+
+    for (line, content) in buffer.iter().enumerate().take(end.0 + 1).skip(start.0) {
+        let (from, to) = columns_on_line(start, end, line, content.chars().count()).unwrap_or((0, 0));
+        text.extend(content.chars().skip(from).take(to.saturating_sub(from)));
+        if line != end.0 {
+            text.push('\n');
+        }
+    }
+
+    text
+}
@@ -0,0 +1,42 @@
+use std::{env, io::Write, path::Path, process::{Command, Output, Stdio}};
+
+// Runs a command through sh with `input` on its stdin, capturing stdout and stderr so nothing scribbles
+// over the screen while in raw mode
+pub fn run(command: &str, input: &str, dir: Option<&Path>) -> std::io::Result<Output> {
+    run_with_env(command, input, dir, &[])
+}
+
+// Runs a command as run does, with extra environment variables, e.g. a buffer's variables for hooks
+pub fn run_with_env(command: &str, input: &str, dir: Option<&Path>, env: &[(String, String)]) -> std::io::Result<Output> {
+    let mut process = Command::new("sh");
+    process.arg("-c").arg(command).stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::piped());
+    process.envs(env.iter().map(|(name, value)| (name, value)));
+    if let Some(dir) = dir {
+        process.current_dir(dir);
+    }
+
+    let mut child = process.spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        let _ = stdin.write_all(input.as_bytes());
+    }
+
+    child.wait_with_output()
+}
+
+// The first non-empty line of stderr, falling back to stdout, for showing in the message line
+pub fn summary(output: &Output) -> String {
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    stderr.lines().chain(stdout.lines()).map(str::trim).find(|line| !line.is_empty()).unwrap_or("").to_string()
+}
+
+// Quotes an argument for sh
+pub fn quote(arg: &str) -> String {
+    format!("'{}'", arg.replace('\'', "'\\''"))
+}
+
+// Whether a program can be found in $PATH
+pub fn on_path(program: &str) -> bool {
+    env::var_os("PATH").is_some_and(|path| env::split_paths(&path).any(|dir| dir.join(program).is_file()))
+}
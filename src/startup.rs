@@ -0,0 +1,41 @@
+use std::time::{Duration, Instant};
+
+// Cold start should stay well under this; the --startuptime report flags anything slower
+const BUDGET: Duration = Duration::from_millis(5);
+
+// Records how long each step of startup took, for --startuptime
+pub struct StartupTimes {
+    start: Instant,
+    last: Instant,
+    phases: Vec<(&'static str, Duration)>,
+}
+
+impl StartupTimes {
+    pub fn new() -> StartupTimes {
+        let now = Instant::now();
+        StartupTimes { start: now, last: now, phases: vec![] }
+    }
+
+    // Ends the current phase, naming what it did
+    pub fn mark(&mut self, name: &'static str) {
+        let now = Instant::now();
+        self.phases.push((name, now - self.last));
+        self.last = now;
+    }
+
+    pub fn report(&self) -> String {
+        let millis = |duration: Duration| duration.as_secs_f64() * 1000.0;
+        let total = self.last - self.start;
+
+        let mut report = String::from("Startup times:\n");
+        for (name, duration) in &self.phases {
+            report += &format!("  {:>8.3}ms  {}\n", millis(*duration), name);
+        }
+        report += &format!("  {:>8.3}ms  total", millis(total));
+        if total > BUDGET {
+            report += &format!(" (over the {}ms budget)", BUDGET.as_millis());
+        }
+
+        report
+    }
+}
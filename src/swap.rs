@@ -0,0 +1,35 @@
+use std::{fs, path::{Path, PathBuf}};
+
+use crate::{editor::Editor, encoding, paths};
+
+// Swap files hold unsaved changes to named files so they can be recovered after a crash. They live in the
+// cache directory, named after the file's absolute path with / replaced by %
+pub fn swap_path(path: &Path) -> Option<PathBuf> {
+    let absolute = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let name = absolute.display().to_string().replace('/', "%");
+    paths::cache_dir().map(|dir| dir.join("swap").join(format!("{}.swp", name)))
+}
+
+pub fn write(editor: &Editor) -> std::io::Result<()> {
+    let swap = swap_path(&editor.path).ok_or(std::io::Error::other("no cache directory"))?;
+    if let Some(parent) = swap.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    fs::write(swap, encoding::encode(&editor.buffer, editor.line_ending, editor.encoding).0)
+}
+
+pub fn remove(path: &Path) {
+    if let Some(swap) = swap_path(path) {
+        let _ = fs::remove_file(swap);
+    }
+}
+
+// A swap file newer than the file itself means an earlier session ended without saving
+pub fn recoverable(path: &Path) -> Option<PathBuf> {
+    let swap = swap_path(path)?;
+    let swap_time = fs::metadata(&swap).and_then(|metadata| metadata.modified()).ok()?;
+    let file_time = fs::metadata(path).and_then(|metadata| metadata.modified()).ok()?;
+
+    (swap_time > file_time).then_some(swap)
+}
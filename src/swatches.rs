@@ -0,0 +1,37 @@
+use crate::theme::Rgb;
+
+// Filetypes where colour literals are common enough to preview
+pub fn applies(filetype: &str) -> bool {
+    matches!(filetype, "css" | "toml" | "json" | "yaml")
+}
+
+// #rrggbb and rgb(r, g, b) literals on a line, in order
+pub fn colors(line: &str) -> Vec<Rgb> {
+    let mut colors = vec![];
+    let mut rest = line;
+    while let Some(start) = rest.find(['#', 'r']) {
+        let (color, length) = if rest[start..].starts_with('#') { hex(&rest[start..]) } else { function(&rest[start..]) };
+        colors.extend(color);
+        rest = &rest[start + length.max(1)..];
+    }
+    colors
+}
+
+// Not part of a longer hex number, so ids and hashes aren't mistaken for colours
+fn hex(text: &str) -> (Option<Rgb>, usize) {
+    let digits = text[1..].chars().take_while(char::is_ascii_hexdigit).count();
+    let color = if digits == 6 { Rgb::parse_hex(&text[..7]) } else { None };
+    (color, digits + 1)
+}
+
+fn function(text: &str) -> (Option<Rgb>, usize) {
+    let Some(arguments) = text.strip_prefix("rgb(") else { return (None, 1) };
+    let Some(end) = arguments.find(')') else { return (None, 4) };
+    let channels = arguments[..end].split([',', ' ']).filter(|channel| !channel.is_empty())
+        .map(|channel| channel.trim().parse::<u8>().ok()).collect::<Option<Vec<u8>>>();
+    let color = match channels.as_deref() {
+        Some(&[r, g, b]) => Some(Rgb(r, g, b)),
+        _ => None,
+    };
+    (color, end + 5)
+}
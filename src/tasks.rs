@@ -0,0 +1,55 @@
+use std::{io::Write, time::{Duration, Instant}};
+
+use crate::{editor::Editor, event_loop};
+
+// Tasks that take at least this long say when they finish, as the user has likely moved on to something else
+const LONG: Duration = Duration::from_secs(2);
+
+struct Task {
+    id: usize,
+    name: String,
+    started: Instant,
+}
+
+// Work running in the background, like builds and after_save hooks, so it can be listed while it runs
+#[derive(Default)]
+pub struct Tasks {
+    running: Vec<Task>,
+    next_id: usize,
+}
+
+impl Tasks {
+    // The running tasks with how long each has taken so far, oldest first
+    pub fn describe(&self) -> Vec<String> {
+        self.running.iter().map(|task| format!("{} ({}s)", task.name, task.started.elapsed().as_secs())).collect()
+    }
+}
+
+// Runs `work` on another thread as a named task and hands its result to `done` on the main thread. A long task that
+// finishes without `done` saying anything gets a message of its own, and rings the bell when task_bell is set
+pub fn spawn<T: Send + 'static>(editor: &mut Editor, name: String, work: impl FnOnce() -> T + Send + 'static, done: impl FnOnce(&mut Editor, T) + Send + 'static) {
+    let id = editor.tasks.next_id;
+    editor.tasks.next_id += 1;
+    editor.tasks.running.push(Task { id, name, started: Instant::now() });
+
+    event_loop::spawn(work, move |editor, result| {
+        let Some(index) = editor.tasks.running.iter().position(|task| task.id == id) else { return };
+        let task = editor.tasks.running.remove(index);
+        let message = editor.message.clone();
+        done(editor, result);
+
+        let took = task.started.elapsed();
+        if took < LONG {
+            return;
+        }
+        if editor.message == message {
+            editor.message = format!("{} finished after {}s", task.name, took.as_secs());
+        }
+        if editor.config.task_bell {
+            // Failing to write is left to the draw that follows to report
+            let mut out = std::io::stdout();
+            let _ = write!(out, "\x07");
+            let _ = out.flush();
+        }
+    });
+}
@@ -0,0 +1,33 @@
+use std::{fs, path::{Path, PathBuf}};
+
+use crate::paths;
+
+// Where the cursor goes once a template is filled in. Removed from the text
+const CURSOR_MARKER: &str = "${cursor}";
+
+// templates/<file name> in the config directory, e.g. templates/Makefile, then templates/<extension>, e.g. templates/html
+fn find(path: &Path) -> Option<PathBuf> {
+    let dir = paths::config_dir()?.join("templates");
+    let name = path.file_name().map(|name| dir.join(name));
+    let extension = path.extension().map(|extension| dir.join(extension));
+    name.into_iter().chain(extension).find(|template| template.is_file())
+}
+
+// The template's lines for a new file at path, with where the cursor starts
+pub fn load(path: &Path) -> Option<(Vec<String>, (usize, usize))> {
+    let text = fs::read_to_string(find(path)?).ok()?;
+    let text = text.strip_suffix('\n').unwrap_or(&text);
+
+    let mut cursor = None;
+    let lines = text.split('\n').enumerate().map(|(number, line)| {
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        match line.find(CURSOR_MARKER) {
+            Some(byte) if cursor.is_none() => {
+                cursor = Some((number, line[..byte].chars().count()));
+                line.replacen(CURSOR_MARKER, "", 1)
+            }
+            _ => line.to_string(),
+        }
+    }).collect::<Vec<String>>();
+    Some((lines, cursor.unwrap_or((0, 0))))
+}
@@ -0,0 +1,133 @@
+use std::{io::{self, Write}, panic, sync::{OnceLock, atomic::{AtomicUsize, Ordering}}, time::{Duration, Instant}};
+
+use libc::termios as Termios;
+
+// The settings from before raw mode, for the panic hook to put back
+static ORIGINAL: OnceLock<Termios> = OnceLock::new();
+
+pub struct RawModeGuard {termios: Termios}
+impl Drop for RawModeGuard {
+    fn drop(&mut self) {
+        unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSAFLUSH, &self.termios); };
+    }
+}
+
+pub fn enable_raw_mode() -> RawModeGuard {
+    let mut termios: Termios = unsafe { std::mem::zeroed::<Termios>() };
+    unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut termios); };
+    let original_termios: Termios = termios;
+    let _ = ORIGINAL.set(original_termios);
+
+    termios.c_iflag &= !(libc::BRKINT | libc::ICRNL | libc::INPCK | libc::ISTRIP | libc::IXON);
+    termios.c_oflag &= !(libc::OPOST);
+    termios.c_cflag |= libc::CS8;
+    termios.c_lflag &= !(libc::ECHO | libc::ICANON | libc::IEXTEN | libc::ISIG);
+
+    unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSAFLUSH, &termios); };
+    RawModeGuard { termios: original_termios }
+}
+
+// A panic would otherwise print its message in raw mode, over the editor's last frame, with mouse reporting still
+// on. This puts the terminal back first and says what went wrong in a line
+pub fn restore_on_panic() {
+    panic::set_hook(Box::new(|info| {
+        // Failures are ignored: a failing stdout may be what panicked, and termios must still go back
+        let mut out = std::io::stdout();
+        let _ = set_mouse_reporting(&mut out, false);
+        let _ = set_kitty_keys(&mut out, false);
+        let _ = write!(out, "\x1b[0m\x1b[2J\x1b[H");
+        let _ = out.flush();
+        if let Some(termios) = ORIGINAL.get() {
+            unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSAFLUSH, termios) };
+        }
+
+        let message = info.payload().downcast_ref::<&str>().map(|message| message.to_string())
+            .or_else(|| info.payload().downcast_ref::<String>().cloned()).unwrap_or_default();
+        let location = info.location().map(|location| format!(" at {}:{}", location.file(), location.line())).unwrap_or_default();
+        eprintln!("tinyeditor stopped: {}{}", message, location);
+    }));
+}
+
+pub fn wait_for_input(timeout: Duration) -> bool {
+    poll_readable(&[libc::STDIN_FILENO], Some(timeout))[0]
+}
+
+// Waits until any of the fds can be read or the timeout (None waits forever) passes, returning which are readable
+pub fn poll_readable(fds: &[i32], timeout: Option<Duration>) -> Vec<bool> {
+    let mut pollfds = fds.iter().map(|fd| libc::pollfd { fd: *fd, events: libc::POLLIN, revents: 0 }).collect::<Vec<libc::pollfd>>();
+    let millis = timeout.map_or(-1, |timeout| timeout.as_millis().min(i32::MAX as u128) as i32);
+
+    if unsafe { libc::poll(pollfds.as_mut_ptr(), pollfds.len() as libc::nfds_t, millis) } <= 0 {
+        return vec![false; fds.len()];
+    }
+    pollfds.iter().map(|pollfd| pollfd.revents & libc::POLLIN != 0).collect()
+}
+
+// Sends queries to the terminal and returns its replies. A device attributes query goes last: every terminal answers
+// it, so there is no waiting out the timeout on terminals that ignore the others. Must be called in raw mode
+pub fn query(queries: &str, timeout: Duration) -> io::Result<String> {
+    let mut out = std::io::stdout();
+    write!(out, "{}\x1b[c", queries)?;
+    out.flush()?;
+
+    let deadline = Instant::now() + timeout;
+    let mut replies = Vec::new();
+    while !String::from_utf8_lossy(&replies).contains("\x1b[?") || !replies.ends_with(b"c") {
+        let left = deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() || !poll_readable(&[libc::STDIN_FILENO], Some(left))[0] {
+            break;
+        }
+        let mut bytes = [0u8; 4096];
+        let count = unsafe { libc::read(libc::STDIN_FILENO, bytes.as_mut_ptr() as *mut libc::c_void, bytes.len()) };
+        if count <= 0 {
+            break;
+        }
+        replies.extend_from_slice(&bytes[..count as usize]);
+    }
+    Ok(String::from_utf8_lossy(&replies).into_owned())
+}
+
+pub fn get_screen_size() -> Option<(usize, usize)> {
+    let mut ws: libc::winsize = unsafe { std::mem::zeroed() };
+    let res = unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut ws) };
+
+    if res == 0 {
+        Some((ws.ws_col as usize, ws.ws_row as usize))
+    } else {
+        None
+    }
+}
+
+// How many times the screen was cleared, so the editor knows its last frame is gone after another screen was up
+static CLEARS: AtomicUsize = AtomicUsize::new(0);
+
+pub fn clear_screen(out: &mut impl Write) -> io::Result<()> {
+    CLEARS.fetch_add(1, Ordering::Relaxed);
+    write!(out, "\x1bc\x1b[H")
+}
+
+pub fn clears() -> usize {
+    CLEARS.load(Ordering::Relaxed)
+}
+
+// Button presses, releases and drags reported as SGR sequences (modes 1002 and 1006)
+pub fn set_mouse_reporting(out: &mut impl Write, on: bool) -> io::Result<()> {
+    let mode = if on { 'h' } else { 'l' };
+    write!(out, "\x1b[?1002{}\x1b[?1006{}", mode, mode)
+}
+
+// Whether the terminal speaks the kitty keyboard protocol, which answers a flags query with \x1b[?<flags>u
+pub fn kitty_keys_supported() -> bool {
+    let replies = query("\x1b[?u", Duration::from_millis(200)).unwrap_or_default();
+    replies.split("\x1b[?").skip(1).any(|reply| reply.split_once('u').is_some_and(|(flags, _)| flags.chars().all(|char| char.is_ascii_digit())))
+}
+
+// Keys sent as kitty protocol sequences carrying the key in the same place on a US layout as well as the char typed
+// (flags 1 and 4), so shortcuts can go by where keys are. Plain keys without modifiers still come as text
+pub fn set_kitty_keys(out: &mut impl Write, on: bool) -> io::Result<()> {
+    write!(out, "\x1b[={};1u", if on { 5 } else { 0 })
+}
+
+pub fn set_cursor_pos(out: &mut impl Write, row: usize, column: usize) -> io::Result<()> {
+    write!(out, "\x1b[{};{}H", row + 1, column + 1)
+}
@@ -0,0 +1,129 @@
+use std::{collections::HashMap, env, fs, path::PathBuf};
+
+use crate::input::{Key, KeyConstructor, Modifiers};
+
+// Indices of the standard string capabilities we care about, in the order term.h defines them
+const STANDARD_STRINGS: [(usize, &str); 16] = [
+    (59, "kdch1"), (61, "kcud1"), (76, "khome"), (79, "kcub1"), (81, "knp"), (82, "kpp"), (83, "kcuf1"), (87, "kcuu1"), (164, "kend"),
+    (191, "kDC"), (194, "kEND"), (199, "kHOM"), (201, "kLFT"), (204, "kNXT"), (206, "kPRV"), (210, "kRIT"),
+];
+
+const MAGIC_16BIT: i16 = 0o432;
+const MAGIC_32BIT: i16 = 0o1036;
+
+// The terminfo capability names for arrow and editing keys, including the ncurses extended names for modified
+// ones (kLFT5 is ctrl+left, the digit being the xterm modifier parameter)
+pub fn special_keys() -> Vec<(String, Key)> {
+    let special: [(&str, &str, KeyConstructor); 9] = [
+        ("kcuu1", "kUP", Key::Up), ("kcud1", "kDN", Key::Down), ("kcub1", "kLFT", Key::Left), ("kcuf1", "kRIT", Key::Right),
+        ("khome", "kHOM", Key::Home), ("kend", "kEND", Key::End), ("kpp", "kPRV", Key::PageUp), ("knp", "kNXT", Key::PageDown),
+        ("kdch1", "kDC", Key::Delete),
+    ];
+    let mut keys = vec![];
+
+    for (plain, extended, key) in special {
+        keys.push((plain.to_string(), key(Modifiers::NONE)));
+        keys.push((extended.to_string(), key(Modifiers::from_xterm(2))));
+
+        for param in 3..=8 {
+            keys.push((format!("{}{}", extended, param), key(Modifiers::from_xterm(param))));
+        }
+    }
+
+    keys
+}
+
+fn find_entry(term: &str) -> Option<PathBuf> {
+    let first = term.chars().next()?;
+    let mut dirs = vec![];
+
+    if let Some(dir) = env::var_os("TERMINFO") {
+        dirs.push(PathBuf::from(dir));
+    }
+    if let Some(home) = env::var_os("HOME") {
+        dirs.push(PathBuf::from(home).join(".terminfo"));
+    }
+    if let Some(list) = env::var_os("TERMINFO_DIRS") {
+        dirs.extend(env::split_paths(&list).filter(|dir| !dir.as_os_str().is_empty()));
+    }
+    dirs.extend(["/etc/terminfo", "/lib/terminfo", "/usr/share/terminfo", "/usr/lib/terminfo"].map(PathBuf::from));
+
+    dirs.into_iter().flat_map(|dir| [dir.join(first.to_string()).join(term), dir.join(format!("{:x}", first as u32)).join(term)])
+        .find(|path| path.is_file())
+}
+
+// Loads the string capabilities for $TERM from the compiled terminfo database
+pub fn load() -> Option<HashMap<String, String>> {
+    let term = env::var("TERM").ok().filter(|term| !term.is_empty() && !term.contains('/'))?;
+    parse(&fs::read(find_entry(&term)?).ok()?)
+}
+
+fn read_i16(data: &[u8], pos: usize) -> Option<i16> {
+    Some(i16::from_le_bytes([*data.get(pos)?, *data.get(pos + 1)?]))
+}
+
+fn read_cstr(table: &[u8], offset: i16) -> Option<String> {
+    let start = usize::try_from(offset).ok()?;
+    let end = start + table.get(start..)?.iter().position(|byte| *byte == 0)?;
+    Some(String::from_utf8_lossy(&table[start..end]).to_string())
+}
+
+pub fn parse(data: &[u8]) -> Option<HashMap<String, String>> {
+    let mut capabilities = HashMap::new();
+
+    let magic = read_i16(data, 0)?;
+    let number_size = match magic {
+        MAGIC_16BIT => 2,
+        MAGIC_32BIT => 4,
+        _ => return None,
+    };
+
+    let header = (1..6).map(|idx| read_i16(data, idx * 2).map(|value| value.max(0) as usize)).collect::<Option<Vec<usize>>>()?;
+    let (names_size, bool_count, number_count, string_count, table_size) = (header[0], header[1], header[2], header[3], header[4]);
+
+    let mut pos = 12 + names_size + bool_count;
+    pos += pos % 2;
+    pos += number_count * number_size;
+
+    let offsets_start = pos;
+    let table_start = offsets_start + string_count * 2;
+    let table = data.get(table_start..table_start + table_size)?;
+
+    for (index, name) in STANDARD_STRINGS {
+        if index < string_count && let Some(value) = read_i16(data, offsets_start + index * 2).and_then(|offset| read_cstr(table, offset)) {
+            capabilities.insert(name.to_string(), value);
+        }
+    }
+
+    // Extended (user-defined) capabilities follow, with their names stored after their values
+    let mut pos = table_start + table_size;
+    pos += pos % 2;
+    let Some(extended) = (0..5).map(|idx| read_i16(data, pos + idx * 2).map(|value| value.max(0) as usize)).collect::<Option<Vec<usize>>>() else {
+        return Some(capabilities);
+    };
+    let (ext_bools, ext_numbers, ext_strings, _, ext_table_size) = (extended[0], extended[1], extended[2], extended[3], extended[4]);
+
+    pos += 10 + ext_bools;
+    pos += pos % 2;
+    pos += ext_numbers * number_size;
+
+    let value_offsets = (0..ext_strings).map(|idx| read_i16(data, pos + idx * 2)).collect::<Option<Vec<i16>>>()?;
+    pos += ext_strings * 2;
+    let name_offsets = (0..ext_bools + ext_numbers + ext_strings).map(|idx| read_i16(data, pos + idx * 2)).collect::<Option<Vec<i16>>>()?;
+    pos += name_offsets.len() * 2;
+    let ext_table = data.get(pos..pos + ext_table_size)?;
+
+    let values = value_offsets.iter().map(|offset| read_cstr(ext_table, *offset)).collect::<Vec<Option<String>>>();
+    let names_start = value_offsets.iter().zip(&values)
+        .filter_map(|(offset, value)| value.as_ref().map(|value| *offset as usize + value.len() + 1))
+        .max().unwrap_or(0);
+    let names = ext_table.get(names_start..)?;
+
+    for (value, name_offset) in values.into_iter().zip(&name_offsets[ext_bools + ext_numbers..]) {
+        if let (Some(value), Some(name)) = (value, read_cstr(names, *name_offset)) {
+            capabilities.insert(name, value);
+        }
+    }
+
+    Some(capabilities)
+}
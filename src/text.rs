@@ -0,0 +1,197 @@
+use std::ops::{Bound, Index, IndexMut, Range, RangeBounds};
+
+// Where a buffer's text is kept. Everything reads lines by index and every edit goes through these, so the storage
+// can change without touching the rest. Editing part of a line works in place, which keeps typing into a
+// multi-megabyte line from copying the whole line for the buffer and again for undo
+pub trait TextBuffer: Index<usize, Output = String> {
+    fn len(&self) -> usize;
+
+    // Replaces `count` lines at `start`, returning the lines taken out
+    fn splice_lines(&mut self, start: usize, count: usize, new: Vec<String>) -> Vec<String>;
+
+    // Replaces a byte range of one line, returning the text taken out. Only the rest of the line after it moves
+    fn edit_line(&mut self, line: usize, bytes: Range<usize>, text: &str) -> String;
+
+    fn get(&self, line: usize) -> Option<&String> {
+        (line < self.len()).then(|| &self[line])
+    }
+
+    fn last(&self) -> Option<&String> {
+        self.len().checked_sub(1).map(|line| &self[line])
+    }
+
+    fn iter(&self) -> Iter<'_, Self> {
+        self.lines(..)
+    }
+
+    // The lines in a range, as a slice of a Vec<String> would give them
+    fn lines(&self, range: impl RangeBounds<usize>) -> Iter<'_, Self> {
+        let front = match range.start_bound() {
+            Bound::Included(start) => *start,
+            Bound::Excluded(start) => start + 1,
+            Bound::Unbounded => 0,
+        };
+        let back = match range.end_bound() {
+            Bound::Included(end) => end + 1,
+            Bound::Excluded(end) => *end,
+            Bound::Unbounded => self.len(),
+        };
+        assert!(front <= back && back <= self.len(), "Lines {}..{} out of range for {} lines", front, back, self.len());
+        Iter { buffer: self, front, back }
+    }
+
+    fn join(&self, separator: &str) -> String {
+        let mut text = String::new();
+        for (index, line) in self.iter().enumerate() {
+            if index > 0 {
+                text.push_str(separator);
+            }
+            text.push_str(line);
+        }
+        text
+    }
+
+    fn to_vec(&self) -> Vec<String> {
+        self.iter().cloned().collect()
+    }
+}
+
+impl TextBuffer for Vec<String> {
+    fn len(&self) -> usize {
+        Vec::len(self)
+    }
+
+    fn splice_lines(&mut self, start: usize, count: usize, new: Vec<String>) -> Vec<String> {
+        self.splice(start..start + count, new).collect()
+    }
+
+    fn edit_line(&mut self, line: usize, bytes: Range<usize>, text: &str) -> String {
+        let old = self[line][bytes.clone()].to_string();
+        self[line].replace_range(bytes, text);
+        old
+    }
+}
+
+pub struct Iter<'a, B: TextBuffer + ?Sized> {
+    buffer: &'a B,
+    front: usize,
+    back: usize,
+}
+
+impl<'a, B: TextBuffer + ?Sized> Iterator for Iter<'a, B> {
+    type Item = &'a String;
+
+    fn next(&mut self) -> Option<&'a String> {
+        (self.front < self.back).then(|| {
+            self.front += 1;
+            &self.buffer[self.front - 1]
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.back - self.front, Some(self.back - self.front))
+    }
+}
+
+impl<B: TextBuffer + ?Sized> DoubleEndedIterator for Iter<'_, B> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        (self.front < self.back).then(|| {
+            self.back -= 1;
+            &self.buffer[self.back]
+        })
+    }
+}
+
+impl<B: TextBuffer + ?Sized> ExactSizeIterator for Iter<'_, B> {}
+
+// The lines of an open buffer, as a gap buffer: the lines before the gap in order and those after it reversed, so
+// adding and removing lines at the gap only pushes and pops. The gap moves to each edit, and edits stay close to
+// the cursor, so adding a line to a big file costs the distance from the last edit rather than every line after it
+#[derive(Clone, Debug, Default)]
+pub struct Lines {
+    before: Vec<String>,
+    after: Vec<String>, // The last line first
+}
+
+impl Lines {
+    fn move_gap(&mut self, to: usize) {
+        while self.before.len() > to {
+            self.after.extend(self.before.pop());
+        }
+        while self.before.len() < to {
+            self.before.extend(self.after.pop());
+        }
+    }
+}
+
+// The same lines, wherever the gap is
+impl PartialEq for Lines {
+    fn eq(&self, other: &Lines) -> bool {
+        self.len() == other.len() && self.iter().eq(other.iter())
+    }
+}
+
+impl From<Vec<String>> for Lines {
+    fn from(lines: Vec<String>) -> Lines {
+        Lines { before: lines, after: vec![] }
+    }
+}
+
+impl Index<usize> for Lines {
+    type Output = String;
+
+    fn index(&self, line: usize) -> &String {
+        match line.checked_sub(self.before.len()) {
+            None => &self.before[line],
+            Some(past) => &self.after[self.after.len().checked_sub(past + 1).expect("Line out of range")],
+        }
+    }
+}
+
+impl IndexMut<usize> for Lines {
+    fn index_mut(&mut self, line: usize) -> &mut String {
+        match line.checked_sub(self.before.len()) {
+            None => &mut self.before[line],
+            Some(past) => {
+                let index = self.after.len().checked_sub(past + 1).expect("Line out of range");
+                &mut self.after[index]
+            }
+        }
+    }
+}
+
+impl TextBuffer for Lines {
+    fn len(&self) -> usize {
+        self.before.len() + self.after.len()
+    }
+
+    fn splice_lines(&mut self, start: usize, count: usize, new: Vec<String>) -> Vec<String> {
+        self.move_gap(start);
+        let old = (0..count).map_while(|_| self.after.pop()).collect::<Vec<String>>();
+        self.before.extend(new);
+        old
+    }
+
+    fn edit_line(&mut self, line: usize, bytes: Range<usize>, text: &str) -> String {
+        let old = self[line][bytes.clone()].to_string();
+        self[line].replace_range(bytes, text);
+        old
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines_splice_around_the_gap() {
+        let mut lines = Lines::from(["a", "b", "c", "d"].map(String::from).to_vec());
+        assert_eq!(lines.splice_lines(3, 1, vec!["D".into(), "e".into()]), ["d"]);
+        assert_eq!(lines.splice_lines(1, 2, vec![]), ["b", "c"]);
+        assert_eq!(lines.splice_lines(0, 0, vec!["z".into()]), Vec::<String>::new());
+        lines[2] += "!";
+        assert_eq!(lines.to_vec(), ["z", "a", "D!", "e"]);
+        assert_eq!(lines.lines(1..3).rev().collect::<Vec<_>>(), ["D!", "a"]);
+        assert_eq!(lines, Lines::from(lines.to_vec()));
+    }
+}
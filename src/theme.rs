@@ -0,0 +1,112 @@
+use std::{fs, path::{Path, PathBuf}, time::Duration};
+
+use crate::{paths, terminal};
+
+// How long to wait for the terminal to answer the colour queries
+const QUERY_TIMEOUT: Duration = Duration::from_millis(200);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Rgb(pub u8, pub u8, pub u8);
+
+impl Rgb {
+    // `amount` of the way from this colour to `other`
+    fn mix(self, other: Rgb, amount: f32) -> Rgb {
+        let channel = |from: u8, to: u8| (from as f32 + (to as f32 - from as f32) * amount).round() as u8;
+        Rgb(channel(self.0, other.0), channel(self.1, other.1), channel(self.2, other.2))
+    }
+
+    // "rrggbb", with or without a leading #
+    pub fn parse_hex(text: &str) -> Option<Rgb> {
+        let hex = text.trim().trim_start_matches('#');
+        let channel = |index: usize| hex.get(index..index + 2).and_then(|channel| u8::from_str_radix(channel, 16).ok());
+        (hex.len() == 6).then_some(())?;
+        Some(Rgb(channel(0)?, channel(2)?, channel(4)?))
+    }
+}
+
+// The colours the UI is drawn in. Without a theme the terminal's reverse video and faint text are used instead
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Theme {
+    pub foreground: Rgb,
+    pub status: Rgb, // Status bar background
+    pub faint: Rgb, // Rulers, line numbers, fold markers and panes without focus
+    pub selection: Rgb, // Selection background
+}
+
+#[derive(Clone, Copy)]
+pub enum Style {
+    Status,
+    Faint,
+    Selection,
+}
+
+// The escape sequence that starts text in `style`
+pub fn escape(theme: Option<&Theme>, style: Style) -> String {
+    let fg = |Rgb(r, g, b): Rgb| format!("38;2;{};{};{}", r, g, b);
+    let bg = |Rgb(r, g, b): Rgb| format!("48;2;{};{};{}", r, g, b);
+
+    match (theme, style) {
+        (None, Style::Status | Style::Selection) => "\x1b[7m".to_string(),
+        (None, Style::Faint) => "\x1b[2m".to_string(),
+        (Some(theme), Style::Status) => format!("\x1b[{};{}m", fg(theme.foreground), bg(theme.status)),
+        (Some(theme), Style::Faint) => format!("\x1b[{}m", fg(theme.faint)),
+        (Some(theme), Style::Selection) => format!("\x1b[{};{}m", fg(theme.foreground), bg(theme.selection)),
+    }
+}
+
+// A base16 scheme in its YAML form, where base00 to base0F are given as hex colours, one `key: "value"` per line
+pub fn load_base16(path: &Path) -> Result<Theme, String> {
+    let text = fs::read_to_string(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+    let color = |name: &str| text.lines()
+        .filter_map(|line| line.split_once(':'))
+        .find(|(key, _)| key.trim() == name)
+        .and_then(|(_, value)| Rgb::parse_hex(value.split('#').next().unwrap_or(value).trim().trim_matches(['"', '\''])))
+        .ok_or(format!("{}: no {} colour", path.display(), name));
+
+    Ok(Theme { foreground: color("base05")?, status: color("base01")?, faint: color("base03")?, selection: color("base02")? })
+}
+
+// "rgb:ffff/8080/0000" as terminals report colours, with 1 to 4 hex digits per channel
+fn parse_rgb(text: &str) -> Option<Rgb> {
+    let channels = text.strip_prefix("rgb:")?.split('/').map(|channel| {
+        let value = u32::from_str_radix(channel, 16).ok()?;
+        let max = (1u32 << (4 * channel.len().clamp(1, 4))) - 1;
+        Some((value * 255 / max) as u8)
+    }).collect::<Option<Vec<u8>>>()?;
+
+    match channels[..] {
+        [r, g, b] => Some(Rgb(r, g, b)),
+        _ => None,
+    }
+}
+
+// Asks the terminal for its foreground and background (OSC 10 and 11) and builds a theme between them. Must be
+// called in raw mode, before anything else reads input
+pub fn from_terminal() -> Option<Theme> {
+    let replies = terminal::query("\x1b]10;?\x1b\\\x1b]11;?\x1b\\", QUERY_TIMEOUT).ok()?;
+    let reply = |code: &str| replies.split("\x1b]").find_map(|reply| {
+        let color = reply.strip_prefix(code)?.split(['\x1b', '\x07']).next()?;
+        parse_rgb(color)
+    });
+
+    let (foreground, background) = (reply("10;")?, reply("11;")?);
+    Some(Theme {
+        foreground,
+        status: background.mix(foreground, 0.2),
+        faint: background.mix(foreground, 0.5),
+        selection: background.mix(foreground, 0.3),
+    })
+}
+
+// The theme named by the `theme` setting: "terminal" for one derived from the terminal's own colours, otherwise a
+// base16 file, relative to the config directory unless absolute
+pub fn load(setting: &str) -> Result<Option<Theme>, String> {
+    match setting {
+        "" => Ok(None),
+        "terminal" => Ok(from_terminal()),
+        path => {
+            let path = paths::config_dir().map_or(PathBuf::from(path), |dir| dir.join(path));
+            load_base16(&path).map(Some)
+        }
+    }
+}
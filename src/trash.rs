@@ -0,0 +1,55 @@
+use std::{collections::VecDeque, time::Instant};
+
+// How many deletions are kept. This is separate from the undo history so a deletion can be recovered
+// after it has been undone past, redone over, or trimmed
+const TRASH_SIZE: usize = 50;
+
+pub struct TrashEntry {
+    pub text: String,
+    pub linewise: bool, // Whole lines, restored as lines above the cursor rather than at it
+    pub line: usize,
+    pub deleted_at: Instant,
+}
+
+impl TrashEntry {
+    // e.g. "3m ago, 2 lines from line 14: fn main() {"
+    pub fn describe(&self) -> String {
+        let secs = self.deleted_at.elapsed().as_secs();
+        let ago = match secs {
+            0..60 => format!("{}s", secs),
+            60..3600 => format!("{}m", secs / 60),
+            _ => format!("{}h", secs / 3600),
+        };
+
+        let lines = self.text.split('\n').count();
+        let size = if self.linewise || lines > 1 {
+            format!("{} line{}", lines, if lines == 1 { "" } else { "s" })
+        } else {
+            format!("{} chars", self.text.chars().count())
+        };
+
+        let preview = self.text.split('\n').find(|line| !line.trim().is_empty()).unwrap_or("").trim();
+        format!("{} ago, {} from line {}: {}", ago, size, self.line + 1, preview)
+    }
+}
+
+#[derive(Default)]
+pub struct Trash {
+    entries: VecDeque<TrashEntry>,
+}
+
+impl Trash {
+    pub fn push(&mut self, text: String, linewise: bool, line: usize) {
+        if text.is_empty() {
+            return;
+        }
+
+        self.entries.push_front(TrashEntry { text, linewise, line, deleted_at: Instant::now() });
+        self.entries.truncate(TRASH_SIZE);
+    }
+
+    // Newest first
+    pub fn entries(&self) -> &VecDeque<TrashEntry> {
+        &self.entries
+    }
+}
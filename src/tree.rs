@@ -0,0 +1,316 @@
+use std::{collections::HashSet, fs, io, path::{Path, PathBuf}, sync::{Arc, atomic::{AtomicBool, Ordering}}, thread, time::Duration};
+
+use crate::{editor::Editor, event_loop, input::Key, keymap::Action, terminal::{get_screen_size, poll_readable}};
+
+// Columns the sidebar takes, at most a third of the screen, not counting the │ after it
+pub const WIDTH: usize = 30;
+
+// Directories that are never worth listing
+const SKIPPED: &[&str] = &[".git"];
+
+pub struct Entry {
+    pub path: PathBuf,
+    pub depth: usize,
+    pub dir: bool,
+}
+
+// A sidebar listing the project's files, directories first, with directories expanded in place. It lists them
+// again whenever something is created, deleted or renamed in a directory it shows
+pub struct FileTree {
+    pub root: PathBuf,
+    pub entries: Vec<Entry>,
+    expanded: HashSet<PathBuf>,
+    pub cursor: usize,
+    pub top: usize,
+    pub focused: bool,
+    watcher: Option<Watcher>,
+}
+
+impl FileTree {
+    pub fn new(root: PathBuf) -> FileTree {
+        let mut tree = FileTree { root, entries: vec![], expanded: HashSet::new(), cursor: 0, top: 0, focused: true, watcher: Watcher::new() };
+        tree.refresh();
+        tree
+    }
+
+    // Lists the files again, keeping the cursor on the same one when it is still there
+    pub fn refresh(&mut self) {
+        let selected = self.selected().map(|entry| entry.path.clone());
+        self.expanded.retain(|dir| dir.is_dir());
+        self.entries = vec![];
+        self.list(&self.root.clone(), 0);
+        if let Some(watcher) = &self.watcher {
+            for dir in std::iter::once(&self.root).chain(&self.expanded) {
+                watcher.watch(dir);
+            }
+        }
+
+        if let Some(index) = selected.and_then(|selected| self.entries.iter().position(|entry| entry.path == selected)) {
+            self.cursor = index;
+        }
+        self.cursor = self.cursor.min(self.entries.len().saturating_sub(1));
+    }
+
+    fn list(&mut self, dir: &Path, depth: usize) {
+        let Ok(read) = fs::read_dir(dir) else { return };
+        let mut children = read.flatten()
+            .filter(|child| !SKIPPED.contains(&child.file_name().to_string_lossy().as_ref()))
+            .map(|child| (child.path(), child.path().is_dir()))
+            .collect::<Vec<(PathBuf, bool)>>();
+        children.sort_by_key(|(path, dir)| (!dir, path.file_name().map(|name| name.to_string_lossy().to_lowercase())));
+
+        for (path, dir) in children {
+            self.entries.push(Entry { path: path.clone(), depth, dir });
+            if dir && self.expanded.contains(&path) {
+                self.list(&path, depth + 1);
+            }
+        }
+    }
+
+    pub fn selected(&self) -> Option<&Entry> {
+        self.entries.get(self.cursor)
+    }
+
+    pub fn is_expanded(&self, dir: &Path) -> bool {
+        self.expanded.contains(dir)
+    }
+
+    // The directory new files go in: the selected one, or the one the selected file is in
+    fn target_dir(&self) -> PathBuf {
+        match self.selected() {
+            Some(entry) if entry.dir => entry.path.clone(),
+            Some(entry) => entry.path.parent().map_or(self.root.clone(), Path::to_path_buf),
+            None => self.root.clone(),
+        }
+    }
+
+    // Keeps the cursor within the `rows` shown
+    pub fn scroll(&mut self, rows: usize) {
+        if self.cursor < self.top {
+            self.top = self.cursor;
+        } else if self.cursor >= self.top + rows {
+            self.top = self.cursor + 1 - rows;
+        }
+    }
+
+    // The first entry shown in `rows`, keeping the cursor in view however the entries changed since it last scrolled
+    pub fn first_shown(&self, rows: usize) -> usize {
+        self.top.min(self.cursor).max((self.cursor + 1).saturating_sub(rows))
+    }
+
+    // A row of the sidebar: the name indented by depth, with an arrow for directories
+    pub fn row(&self, entry: &Entry) -> String {
+        let name = entry.path.file_name().map_or(String::new(), |name| name.to_string_lossy().to_string());
+        let marker = match (entry.dir, self.is_expanded(&entry.path)) {
+            (true, true) => "▾ ",
+            (true, false) => "▸ ",
+            (false, _) => "  ",
+        };
+        format!("{}{}{}{}", "  ".repeat(entry.depth), marker, name, if entry.dir { "/" } else { "" })
+    }
+}
+
+// Keys while the sidebar has focus. Escape or next_pane go back to the panes, and the command prompt, save and
+// quit work as they do there
+pub fn handle_key(editor: &mut Editor, key: Key) {
+    match editor.keymap.lookup_pressed(&key, editor.keys.physical()) {
+        Some(action @ (Action::Command | Action::Save | Action::SaveAs | Action::Quit)) => return editor.run_action(action),
+        Some(Action::NextPane) => return editor.tree.iter_mut().for_each(|tree| tree.focused = false),
+        _ => {}
+    }
+
+    let rows = editor.tree_rect(get_screen_size().unwrap_or((1, 1))).map_or(1, |rect| rect.rows);
+    let Some(tree) = &mut editor.tree else { return };
+    let count = tree.entries.len();
+    match key {
+        Key::Up(_) => tree.cursor = tree.cursor.saturating_sub(1),
+        Key::Down(_) => tree.cursor = (tree.cursor + 1).min(count.saturating_sub(1)),
+        Key::PageUp(_) => tree.cursor = tree.cursor.saturating_sub(rows),
+        Key::PageDown(_) => tree.cursor = (tree.cursor + rows).min(count.saturating_sub(1)),
+        Key::Home(_) => tree.cursor = 0,
+        Key::End(_) => tree.cursor = count.saturating_sub(1),
+        Key::Char('\r' | '\n') | Key::Right(_) => open(editor),
+        Key::Left(_) => collapse(tree),
+        Key::Char('n') => create(editor),
+        Key::Char('r') => rename(editor),
+        Key::Char('d') => delete(editor),
+        Key::Char('q') => editor.tree = None,
+        Key::Escape => tree.focused = false,
+        _ => editor.message = "Files: Enter opens, n new (end with / for a directory), r rename, d delete, q close, Esc back".to_string(),
+    }
+    if let Some(tree) = &mut editor.tree {
+        tree.scroll(rows);
+    }
+}
+
+// What a key does in the focused tree, for whichkey, matching handle_key
+pub fn describe(editor: &Editor, key: &Key) -> &'static str {
+    match editor.keymap.lookup_pressed(key, editor.keys.physical()) {
+        Some(Action::Command | Action::Save | Action::SaveAs | Action::Quit) => return "as editing",
+        Some(Action::NextPane) => return "moves focus back to the buffer",
+        _ => {}
+    }
+    match key {
+        Key::Up(_) | Key::Down(_) | Key::PageUp(_) | Key::PageDown(_) | Key::Home(_) | Key::End(_) => "moves through the files",
+        Key::Char('\r' | '\n') | Key::Right(_) => "opens the file or expands the directory",
+        Key::Left(_) => "collapses the directory",
+        Key::Char('n') => "creates a file, or a directory when the name ends with /",
+        Key::Char('r') => "renames the file",
+        Key::Char('d') => "deletes the file",
+        Key::Char('q') => "closes the tree",
+        Key::Escape => "moves focus back to the buffer",
+        _ => "shows the tree's keys",
+    }
+}
+
+// Files open in the focused pane, or beside it, see Editor::show_file. Directories expand or collapse
+fn open(editor: &mut Editor) {
+    let Some(tree) = &mut editor.tree else { return };
+    let Some(entry) = tree.selected() else { return };
+    let path = entry.path.clone();
+    if entry.dir {
+        if !tree.expanded.remove(&path) {
+            tree.expanded.insert(path);
+        }
+        tree.refresh();
+        return;
+    }
+
+    match editor.show_file(&path) {
+        Ok(()) => {
+            if let Some(tree) = &mut editor.tree {
+                tree.focused = false;
+            }
+        }
+        Err(err) => editor.message = err,
+    }
+}
+
+// Collapses the selected directory, or moves up to the one the selection is in
+fn collapse(tree: &mut FileTree) {
+    let Some(entry) = tree.selected() else { return };
+    let path = entry.path.clone();
+    if entry.dir && tree.expanded.remove(&path) {
+        tree.refresh();
+    } else if let Some(parent) = path.parent() && let Some(index) = tree.entries.iter().position(|entry| entry.path == parent) {
+        tree.cursor = index;
+    }
+}
+
+fn create(editor: &mut Editor) {
+    let Some(dir) = editor.tree.as_ref().map(FileTree::target_dir) else { return };
+    let Some(name) = editor.prompt(&format!("New file in {}/ (end with / for a directory): ", dir.display())).filter(|name| !name.trim().is_empty()) else { return };
+    let path = dir.join(name.trim());
+
+    let result = if name.ends_with('/') {
+        fs::create_dir_all(&path)
+    } else if path.exists() {
+        Err(io::Error::new(io::ErrorKind::AlreadyExists, "it already exists"))
+    } else {
+        path.parent().map_or(Ok(()), fs::create_dir_all).and_then(|_| fs::write(&path, ""))
+    };
+    finish(editor, result, &path, &format!("Created {}", path.display()));
+}
+
+fn rename(editor: &mut Editor) {
+    let Some(path) = editor.tree.as_ref().and_then(|tree| tree.selected()).map(|entry| entry.path.clone()) else { return };
+    let name = path.file_name().map_or(String::new(), |name| name.to_string_lossy().to_string());
+    let Some(new_name) = editor.prompt(&format!("Rename {} to: ", name)).filter(|new_name| !new_name.trim().is_empty()) else { return };
+    let new_path = path.with_file_name(new_name.trim());
+
+    let result = if new_path.exists() {
+        Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("{} already exists", new_path.display())))
+    } else {
+        fs::rename(&path, &new_path)
+    };
+    // The buffer follows the file it was editing
+    if result.is_ok() && editor.path == path {
+        editor.path = new_path.clone();
+    }
+    finish(editor, result, &new_path, &format!("Renamed {} to {}", name, new_name.trim()));
+}
+
+// Directories are only deleted when empty
+fn delete(editor: &mut Editor) {
+    let Some((path, dir)) = editor.tree.as_ref().and_then(|tree| tree.selected()).map(|entry| (entry.path.clone(), entry.dir)) else { return };
+    let answer = editor.prompt(&format!("Delete {}? (y/n) ", path.display()));
+    if !answer.is_some_and(|answer| answer.trim().eq_ignore_ascii_case("y")) {
+        return;
+    }
+
+    let result = if dir { fs::remove_dir(&path) } else { fs::remove_file(&path) };
+    finish(editor, result, &path, &format!("Deleted {}", path.display()));
+}
+
+fn finish(editor: &mut Editor, result: io::Result<()>, path: &Path, done: &str) {
+    match result {
+        Ok(()) => editor.message = done.to_string(),
+        Err(err) => editor.message = format!("Unable to change {}: {}", path.display(), err),
+    }
+    if let Some(tree) = &mut editor.tree {
+        tree.refresh();
+        if let Some(index) = tree.entries.iter().position(|entry| entry.path == path) {
+            tree.cursor = index;
+        }
+    }
+}
+
+// Watches directories with inotify, refreshing the tree when their entries change. Elsewhere the tree is only
+// listed again after changes made through it
+struct Watcher {
+    fd: i32,
+    stop: Arc<AtomicBool>,
+}
+
+impl Watcher {
+    #[cfg(target_os = "linux")]
+    fn new() -> Option<Watcher> {
+        let fd = unsafe { libc::inotify_init1(libc::IN_CLOEXEC) };
+        if fd < 0 {
+            return None;
+        }
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = stop.clone();
+        thread::spawn(move || {
+            let mut events = [0u8; 4096];
+            // Waking every so often to see whether the tree was closed, since closing the fd doesn't end a read
+            while !stopped.load(Ordering::Relaxed) {
+                if !poll_readable(&[fd], Some(Duration::from_millis(500)))[0] {
+                    continue;
+                }
+                if unsafe { libc::read(fd, events.as_mut_ptr() as *mut libc::c_void, events.len()) } <= 0 {
+                    break;
+                }
+                event_loop::send(Box::new(|editor: &mut Editor| {
+                    if let Some(tree) = &mut editor.tree {
+                        tree.refresh();
+                    }
+                }));
+            }
+            unsafe { libc::close(fd) };
+        });
+        Some(Watcher { fd, stop })
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn new() -> Option<Watcher> {
+        None
+    }
+
+    #[cfg(target_os = "linux")]
+    fn watch(&self, dir: &Path) {
+        let Ok(path) = std::ffi::CString::new(dir.as_os_str().as_encoded_bytes()) else { return };
+        let mask = libc::IN_CREATE | libc::IN_DELETE | libc::IN_MOVED_FROM | libc::IN_MOVED_TO;
+        unsafe { libc::inotify_add_watch(self.fd, path.as_ptr(), mask) };
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn watch(&self, _dir: &Path) {}
+}
+
+impl Drop for Watcher {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
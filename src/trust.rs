@@ -0,0 +1,36 @@
+use std::{fs, io::Write, path::{Path, PathBuf}};
+
+use crate::paths;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Trust {
+    Trusted,
+    Denied,
+    Unknown,
+}
+
+fn trust_file() -> Option<PathBuf> {
+    paths::state_dir().map(|dir| dir.join("trust"))
+}
+
+// The trust file holds one decision per line, "+ /path" for trusted and "- /path" for denied.
+// Later lines win so a decision can be changed by appending
+pub fn lookup(dir: &Path) -> Trust {
+    let Some(text) = trust_file().and_then(|file| fs::read_to_string(file).ok()) else { return Trust::Unknown };
+
+    text.lines().rev().find_map(|line| match line.split_once(' ') {
+        Some(("+", path)) if Path::new(path) == dir => Some(Trust::Trusted),
+        Some(("-", path)) if Path::new(path) == dir => Some(Trust::Denied),
+        _ => None,
+    }).unwrap_or(Trust::Unknown)
+}
+
+pub fn remember(dir: &Path, trusted: bool) -> std::io::Result<()> {
+    let file = trust_file().ok_or(std::io::Error::other("no state directory"))?;
+    if let Some(parent) = file.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let mut trust = fs::OpenOptions::new().create(true).append(true).open(file)?;
+    writeln!(trust, "{} {}", if trusted { "+" } else { "-" }, dir.display())
+}
@@ -0,0 +1,58 @@
+use crate::{commands::COMMANDS, keymap::{Action, Keymap}};
+
+fn keys_for(keymap: &Keymap, action: Action) -> String {
+    let keys = keymap.keys_for(action).iter().map(|key| key.to_string()).collect::<Vec<String>>();
+    if keys.is_empty() { "(unbound)".to_string() } else { keys.join(" or ") }
+}
+
+// The tutorial is generated from the live keymap and command table so it never describes keys that don't exist
+pub fn generate(keymap: &Keymap) -> Vec<String> {
+    let key = |action| keys_for(keymap, action);
+    let mut lines = vec![
+        "Welcome to the tinyeditor tutorial!".to_string(),
+        "".to_string(),
+        "This is a scratch copy, so feel free to edit it while you follow along.".to_string(),
+        "".to_string(),
+        "== Moving around ==".to_string(),
+        "".to_string(),
+        format!("  {:<24} {}", key(Action::MoveUp), Action::MoveUp.description()),
+        format!("  {:<24} {}", key(Action::MoveDown), Action::MoveDown.description()),
+        format!("  {:<24} {}", key(Action::RowUp), Action::RowUp.description()),
+        format!("  {:<24} {}", key(Action::RowDown), Action::RowDown.description()),
+        format!("  {:<24} {}", key(Action::MoveLeft), Action::MoveLeft.description()),
+        format!("  {:<24} {}", key(Action::MoveRight), Action::MoveRight.description()),
+        "".to_string(),
+        "  Try it: move the cursor down to the line below and to the end of the word.".to_string(),
+        "  --> here".to_string(),
+        "".to_string(),
+        "== Editing ==".to_string(),
+        "".to_string(),
+        "  Typing inserts text at the cursor.".to_string(),
+        format!("  {:<24} {}", key(Action::DeleteBack), Action::DeleteBack.description()),
+        format!("  {:<24} {}", key(Action::Newline), Action::Newline.description()),
+        format!("  {:<24} {}", key(Action::Indent), Action::Indent.description()),
+        format!("  {:<24} {}", key(Action::Undo), Action::Undo.description()),
+        format!("  {:<24} {}", key(Action::Redo), Action::Redo.description()),
+        "".to_string(),
+        "  Try it: fix the typos in this line.".to_string(),
+        "  --> The quik brown foxx jumps over the lazy dog".to_string(),
+        "".to_string(),
+        "== Saving and quitting ==".to_string(),
+        "".to_string(),
+        format!("  {:<24} {}", key(Action::Save), Action::Save.description()),
+        format!("  {:<24} {}", key(Action::Quit), Action::Quit.description()),
+        "".to_string(),
+        "== Commands ==".to_string(),
+        "".to_string(),
+        format!("  Press {} and type a command name, then Enter. Esc cancels.", key(Action::Command)),
+        "".to_string(),
+    ];
+
+    for (name, description) in COMMANDS {
+        lines.push(format!("  {:<24} {}", name, description));
+    }
+
+    lines.push("".to_string());
+    lines.push("That's everything! Happy editing.".to_string());
+    lines
+}
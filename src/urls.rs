@@ -0,0 +1,58 @@
+use crate::shell;
+
+const SCHEMES: &[&str] = &["https://", "http://", "ftp://", "file://", "mailto:"];
+
+// The char columns of the URLs on a line, as (start, end). A URL runs to whitespace or a quote or bracket that
+// can't be part of one, and trailing punctuation is left out unless it closes a bracket the URL opened, like
+// the ) in https://en.wikipedia.org/wiki/Rust_(programming_language)
+pub fn find(line: &str) -> Vec<(usize, usize)> {
+    let chars = line.chars().collect::<Vec<char>>();
+    let mut urls = vec![];
+    let mut column = 0;
+
+    while column < chars.len() {
+        let starts_word = column == 0 || !chars[column - 1].is_alphanumeric();
+        let matches = |scheme: &&str| scheme.chars().enumerate().all(|(offset, char)| chars.get(column + offset) == Some(&char));
+        let Some(scheme) = SCHEMES.iter().find(|scheme| starts_word && matches(scheme)) else {
+            column += 1;
+            continue;
+        };
+
+        let mut end = column + scheme.chars().count();
+        while end < chars.len() && !chars[end].is_whitespace() && !"<>\"'`".contains(chars[end]) {
+            end += 1;
+        }
+        loop {
+            let opened = |open: char| chars[column..end].iter().filter(|char| **char == open).count();
+            match chars[end - 1] {
+                '.' | ',' | ';' | ':' | '!' | '?' => end -= 1,
+                ')' if opened('(') < opened(')') => end -= 1,
+                ']' if opened('[') < opened(']') => end -= 1,
+                _ => break,
+            }
+        }
+
+        if end > column + scheme.chars().count() {
+            urls.push((column, end));
+        }
+        column = end.max(column + 1);
+    }
+    urls
+}
+
+// The URL the cursor is on, if any
+pub fn at(line: &str, column: usize) -> Option<String> {
+    let (start, end) = find(line).into_iter().find(|(start, end)| (*start..*end).contains(&column))?;
+    Some(line.chars().skip(start).take(end - start).collect())
+}
+
+// Hands a URL to the desktop's opener without waiting for it
+pub fn open(url: &str) -> Result<(), String> {
+    let opener = if cfg!(target_os = "macos") { "open" } else { "xdg-open" };
+    if !shell::on_path(opener) {
+        return Err(format!("{} isn't installed", opener));
+    }
+
+    shell::run(&format!("{} {} >/dev/null 2>&1 &", opener, shell::quote(url)), "", None).map(|_| ())
+        .map_err(|err| format!("Unable to run {}: {}", opener, err))
+}
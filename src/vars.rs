@@ -0,0 +1,76 @@
+use std::collections::BTreeMap;
+
+use crate::config::Value;
+
+// Variables the editor itself reads, with the type each must have. Any other name is free for plugins and hooks
+// to keep their own state in, e.g. "lint.enabled"
+pub const BUILTIN: &[(&str, &str, &str)] = &[
+    ("filetype", "string", "What the buffer is treated as, e.g. rust or markdown"),
+    ("indent_width", "integer", "Spaces one level of indentation takes"),
+    ("indent_with_tabs", "boolean", "Indent with a tab rather than spaces"),
+    ("smart_indent", "boolean", "Enter after a line opening a block indents one level deeper"),
+    ("final_newline", "boolean", "Saving adds a line break at the end of the file when it has none"),
+    ("align_columns", "boolean", "Line up the fields of a CSV or TSV file on screen, without changing the file"),
+    ("rebase_keys", "boolean", "Letters change the command on the line in a rebase todo list rather than typing"),
+];
+
+// A buffer's own variables, which win over the config. Kept with the rest of the buffer, so each has its own
+#[derive(Clone, Debug, Default)]
+pub struct Vars {
+    values: BTreeMap<String, Value>,
+}
+
+impl Vars {
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.values.get(name)
+    }
+
+    pub fn set(&mut self, name: &str, value: Value) -> Result<(), String> {
+        check(name, &value)?;
+        self.values.insert(name.to_string(), value);
+        Ok(())
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<Value> {
+        self.values.remove(name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Value)> {
+        self.values.iter()
+    }
+}
+
+// Whether a value can be stored under a name: built-in variables only take their own type and sensible values
+pub fn check(name: &str, value: &Value) -> Result<(), String> {
+    if name.is_empty() || !name.chars().all(|char| char.is_ascii_alphanumeric() || matches!(char, '_' | '.' | '-')) {
+        return Err(format!("invalid variable name `{}`", name));
+    }
+
+    match (name, value) {
+        ("indent_width", Value::Integer(width)) if *width > 0 => Ok(()),
+        ("indent_with_tabs" | "smart_indent" | "final_newline" | "rebase_keys" | "align_columns", Value::Boolean(_)) | ("filetype", Value::String(_)) => Ok(()),
+        (_, Value::Array(_)) => Err(format!("`{}` can't be an array", name)),
+        _ => match BUILTIN.iter().find(|(builtin, _, _)| *builtin == name) {
+            Some((_, kind, _)) => Err(format!("`{}` must be a {}{}", name, if name == "indent_width" { "positive " } else { "" }, kind)),
+            None => Ok(()),
+        },
+    }
+}
+
+// A value typed at the command prompt or sent over the control socket: true, false and numbers are read as such,
+// anything else as a string
+pub fn parse(text: &str) -> Value {
+    match text {
+        "true" => Value::Boolean(true),
+        "false" => Value::Boolean(false),
+        _ => text.parse::<i64>().map_or(Value::String(text.to_string()), Value::Integer),
+    }
+}
+
+// Variables as environment variables for hooks, e.g. indent_width as TINYEDITOR_INDENT_WIDTH
+pub fn env(vars: &[(String, Value)]) -> Vec<(String, String)> {
+    vars.iter().map(|(name, value)| {
+        let name = name.chars().map(|char| if char.is_ascii_alphanumeric() { char.to_ascii_uppercase() } else { '_' }).collect::<String>();
+        (format!("TINYEDITOR_{}", name), value.to_string())
+    }).collect()
+}
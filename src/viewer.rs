@@ -0,0 +1,175 @@
+use std::{fs::File, io::Write, os::fd::AsRawFd, path::Path};
+
+use crate::{input::{Key, KeyDecoder}, terminal::{clear_screen, enable_raw_mode, get_screen_size, set_cursor_pos}};
+
+// A read-only mapping of a whole file. The kernel pages it in as it is touched, so opening is instant
+// whatever the size
+struct Mmap {
+    ptr: *mut libc::c_void,
+    len: usize,
+}
+
+impl Mmap {
+    fn open(file: &File) -> std::io::Result<Mmap> {
+        let len = file.metadata()?.len() as usize;
+        if len == 0 {
+            // mmap refuses empty mappings
+            return Ok(Mmap { ptr: std::ptr::null_mut(), len });
+        }
+
+        let ptr = unsafe { libc::mmap(std::ptr::null_mut(), len, libc::PROT_READ, libc::MAP_PRIVATE, file.as_raw_fd(), 0) };
+        if ptr == libc::MAP_FAILED {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        Ok(Mmap { ptr, len })
+    }
+
+    fn bytes(&self) -> &[u8] {
+        if self.len == 0 {
+            return &[];
+        }
+        unsafe { std::slice::from_raw_parts(self.ptr as *const u8, self.len) }
+    }
+}
+
+impl Drop for Mmap {
+    fn drop(&mut self) {
+        if self.len > 0 {
+            unsafe { libc::munmap(self.ptr, self.len) };
+        }
+    }
+}
+
+// Byte offsets of line starts, found only as far as the viewer has scrolled
+struct LineIndex {
+    starts: Vec<usize>,
+    complete: bool,
+}
+
+impl LineIndex {
+    // Makes sure the start of `line` is known, unless the file has fewer lines
+    fn index_to(&mut self, bytes: &[u8], line: usize) {
+        while !self.complete && self.starts.len() <= line {
+            let from = *self.starts.last().unwrap_or(&0);
+            match bytes[from..].iter().position(|byte| *byte == b'\n') {
+                Some(newline) => self.starts.push(from + newline + 1),
+                None => self.complete = true,
+            }
+        }
+    }
+
+    fn line<'a>(&mut self, bytes: &'a [u8], line: usize) -> Option<&'a [u8]> {
+        self.index_to(bytes, line + 1);
+        let start = *self.starts.get(line)?;
+        let end = self.starts.get(line + 1).map_or(bytes.len(), |next| next - 1);
+        Some(&bytes[start..end])
+    }
+}
+
+// How many bytes each row of the hex view shows
+const HEX_WIDTH: usize = 16;
+
+// e.g. "00000010  6c 6f 20 77 6f 72 6c 64  0a                       |lo world.|"
+fn hex_row(bytes: &[u8], offset: usize) -> String {
+    let row = &bytes[offset..(offset + HEX_WIDTH).min(bytes.len())];
+    let hex = (0..HEX_WIDTH).map(|column| {
+        let gap = if column == HEX_WIDTH / 2 { " " } else { "" };
+        row.get(column).map_or(format!("{}   ", gap), |byte| format!("{}{:02x} ", gap, byte))
+    }).collect::<String>();
+    let ascii = row.iter().map(|byte| if byte.is_ascii_graphic() || *byte == b' ' { *byte as char } else { '.' }).collect::<String>();
+    format!("{:08x}  {} |{}|", offset, hex, ascii)
+}
+
+fn draw(path: &Path, bytes: &[u8], index: &mut LineIndex, top: usize, hex: bool) -> std::io::Result<()> {
+    let (width, height) = get_screen_size().unwrap_or((1, 1));
+    let text_height = height.saturating_sub(2).max(1);
+
+    let mut out = std::io::stdout();
+    clear_screen(&mut out)?;
+
+    for row in 0..text_height {
+        let text = if hex {
+            let offset = (top + row) * HEX_WIDTH;
+            if offset >= bytes.len() {
+                break;
+            }
+            hex_row(bytes, offset)
+        } else {
+            let Some(line) = index.line(bytes, top + row) else { break };
+            String::from_utf8_lossy(line).to_string()
+        };
+        set_cursor_pos(&mut out, row, 0)?;
+        write!(out, "{}\x1b[K", text.chars().filter(|char| !char.is_control()).take(width).collect::<String>())?;
+    }
+
+    let offset = if hex { top * HEX_WIDTH } else { index.starts.get(top).copied().unwrap_or(bytes.len()) };
+    let percent = if bytes.is_empty() { 100 } else { offset.min(bytes.len()) * 100 / bytes.len() };
+    let total = match rows(bytes, index, hex) {
+        Some(rows) => rows.to_string(),
+        None => "?".to_string(),
+    };
+    let left = format!(" {} [{}]", path.display(), if hex { "hex" } else { "view" });
+    let right = format!("{}/{}, {}% ", top + 1, total, percent);
+    let padding = width.saturating_sub(left.chars().count() + right.chars().count());
+    set_cursor_pos(&mut out, height.saturating_sub(2), 0)?;
+    write!(out, "\x1b[7m{}\x1b[0m", format!("{}{}{}", left, " ".repeat(padding), right).chars().take(width).collect::<String>())?;
+
+    set_cursor_pos(&mut out, height.saturating_sub(1), 0)?;
+    write!(out, "{}\x1b[K", "Arrows scroll, Space/b page, g/G top/bottom, q quits".chars().take(width).collect::<String>())?;
+    out.flush()
+}
+
+// How many rows there are to scroll through, if known yet
+fn rows(bytes: &[u8], index: &LineIndex, hex: bool) -> Option<usize> {
+    if hex {
+        Some(bytes.len().div_ceil(HEX_WIDTH))
+    } else {
+        index.complete.then_some(index.starts.len())
+    }
+}
+
+// Pages through a file without loading it, for files too big to edit, as text or as a hex dump
+pub fn run(path: &Path, hex: bool) -> std::io::Result<()> {
+    let file = File::open(path)?;
+    let map = Mmap::open(&file)?;
+    let bytes = map.bytes();
+    let mut index = LineIndex { starts: vec![0], complete: false };
+    let keys = KeyDecoder::default();
+    let mut top = 0;
+
+    let _guard = enable_raw_mode();
+    loop {
+        let (_, height) = get_screen_size().unwrap_or((1, 1));
+        let page = height.saturating_sub(2).max(1);
+
+        // Never scroll past the last page
+        if !hex {
+            index.index_to(bytes, top + page);
+        }
+        if let Some(rows) = rows(bytes, &index, hex) {
+            top = top.min(rows.saturating_sub(page));
+        }
+        draw(path, bytes, &mut index, top, hex)?;
+
+        match keys.read_key() {
+            Key::Up(_) => top = top.saturating_sub(1),
+            Key::Down(_) | Key::Char('\r') => top += 1,
+            Key::Char(' ') | Key::PageDown(_) => top += page,
+            Key::Char('b') | Key::PageUp(_) => top = top.saturating_sub(page),
+            Key::Char('g') | Key::Home(_) => top = 0,
+            Key::Char('G') | Key::End(_) => {
+                if !hex {
+                    index.index_to(bytes, usize::MAX);
+                }
+                top = rows(bytes, &index, hex).unwrap_or(top);
+            }
+            Key::Char('q' | '\x11' | '\x03') => break,
+            _ => {}
+        }
+    }
+
+    let mut out = std::io::stdout();
+    clear_screen(&mut out)?;
+    out.flush()
+}
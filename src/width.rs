@@ -0,0 +1,170 @@
+use crate::graphemes;
+
+// How many terminal cells text takes. Wide East Asian chars and most emoji take two, combining marks none, tabs
+// run to the next tab stop, and everything else takes one. Columns elsewhere are char indexes, these turn them
+// into cells on screen and back. Tab stops are counted from the start of the line, see Stops
+
+// Ranges of chars terminals draw two cells wide, from Unicode's East Asian Width property
+const WIDE: &[(u32, u32)] = &[
+    (0x1100, 0x115F), // Hangul Jamo
+    (0x231A, 0x231B),
+    (0x2329, 0x232A),
+    (0x23E9, 0x23EC),
+    (0x23F0, 0x23F0),
+    (0x23F3, 0x23F3),
+    (0x25FD, 0x25FE),
+    (0x2614, 0x2615),
+    (0x2648, 0x2653),
+    (0x267F, 0x267F),
+    (0x2693, 0x2693),
+    (0x26A1, 0x26A1),
+    (0x26AA, 0x26AB),
+    (0x26BD, 0x26BE),
+    (0x26C4, 0x26C5),
+    (0x26CE, 0x26CE),
+    (0x26D4, 0x26D4),
+    (0x26EA, 0x26EA),
+    (0x26F2, 0x26F3),
+    (0x26F5, 0x26F5),
+    (0x26FA, 0x26FA),
+    (0x26FD, 0x26FD),
+    (0x2705, 0x2705),
+    (0x270A, 0x270B),
+    (0x2728, 0x2728),
+    (0x274C, 0x274C),
+    (0x274E, 0x274E),
+    (0x2753, 0x2755),
+    (0x2757, 0x2757),
+    (0x2795, 0x2797),
+    (0x27B0, 0x27B0),
+    (0x27BF, 0x27BF),
+    (0x2B1B, 0x2B1C),
+    (0x2B50, 0x2B50),
+    (0x2B55, 0x2B55),
+    (0x2E80, 0x303E), // CJK radicals, punctuation
+    (0x3041, 0x33FF), // Kana, CJK compatibility
+    (0x3400, 0x4DBF), // CJK extension A
+    (0x4E00, 0x9FFF), // CJK unified ideographs
+    (0xA000, 0xA4CF), // Yi
+    (0xA960, 0xA97F),
+    (0xAC00, 0xD7A3), // Hangul syllables
+    (0xF900, 0xFAFF), // CJK compatibility ideographs
+    (0xFE10, 0xFE19),
+    (0xFE30, 0xFE6F), // CJK compatibility forms, small forms
+    (0xFF00, 0xFF60), // Fullwidth forms
+    (0xFFE0, 0xFFE6),
+    (0x16FE0, 0x16FE4),
+    (0x17000, 0x18AFF), // Tangut
+    (0x1B000, 0x1B2FF), // Kana supplement
+    (0x1F004, 0x1F004),
+    (0x1F0CF, 0x1F0CF),
+    (0x1F18E, 0x1F18E),
+    (0x1F191, 0x1F19A),
+    (0x1F200, 0x1F251),
+    (0x1F300, 0x1F64F), // Emoji
+    (0x1F680, 0x1F6FF),
+    (0x1F7E0, 0x1F7EB),
+    (0x1F90C, 0x1F9FF),
+    (0x1FA70, 0x1FAFF),
+    (0x20000, 0x2FFFD), // CJK extensions B onwards
+    (0x30000, 0x3FFFD),
+];
+
+// Chars with no look of their own: the soft hyphen, zero-width spaces and joiners other than ZWJ, and marks and
+// controls that set the direction of text. Terminals disagree on whether they take a cell, so they are either left
+// out of what's drawn or shown as a placeholder a cell wide, see Stops
+pub fn is_invisible(char: char) -> bool {
+    matches!(char, '\u{00AD}' | '\u{180E}' | '\u{200B}' | '\u{200E}' | '\u{200F}' | '\u{202A}'..='\u{202E}' | '\u{2060}'..='\u{2064}' | '\u{2066}'..='\u{2069}' | '\u{FEFF}')
+}
+
+// Cells a char takes, not counting tabs. Chars that combine with the one before take none, see graphemes
+pub fn char_width(char: char) -> usize {
+    let code = char as u32;
+    if graphemes::is_extend(char) || char == '\u{200D}' || is_invisible(char) {
+        return 0;
+    }
+    if code < 0x1100 {
+        return 1;
+    }
+    let index = WIDE.partition_point(|(_, last)| *last < code);
+    if WIDE.get(index).is_some_and(|(first, _)| *first <= code) { 2 } else { 1 }
+}
+
+// Where tabs run to: every `tab` cells, and in column mode separators too run to the start of the next column,
+// see csv. Invisible chars take a cell when their placeholders are shown
+#[derive(Clone, Debug, Default)]
+pub struct Stops {
+    pub tab: usize,
+    pub columns: Option<(char, Vec<usize>)>, // The separator and the cell each column after the first starts at
+    pub invisibles: bool,
+}
+
+impl Stops {
+    pub fn tabs(tab: usize) -> Stops {
+        Stops { tab, columns: None, invisibles: false }
+    }
+}
+
+// Cells a char takes when it starts at `cell`
+pub fn width_at(char: char, cell: usize, stops: &Stops) -> usize {
+    let column = stops.columns.as_ref().filter(|(separator, _)| *separator == char).and_then(|(_, starts)| starts.iter().find(|start| **start > cell));
+    match (char, column) {
+        (_, Some(start)) => start - cell,
+        ('\t', None) => stops.tab.max(1) - cell % stops.tab.max(1),
+        (char, None) if stops.invisibles && is_invisible(char) => 1,
+        (char, None) => char_width(char),
+    }
+}
+
+// The cell the char at `column` starts at. Columns past the end of the line are a cell each
+pub fn cell_of(line: &str, column: usize, stops: &Stops) -> usize {
+    let mut cell = 0;
+    let mut count = 0;
+    for char in line.chars().take(column) {
+        cell += width_at(char, cell, stops);
+        count += 1;
+    }
+    cell + column - count
+}
+
+// The column of the char covering `cell`. Cells past the end of the line are a column each, so moving through
+// a short line keeps the place on the screen
+pub fn column_at(line: &str, cell: usize, stops: &Stops) -> usize {
+    let mut start = 0;
+    let mut count = 0;
+    for char in line.chars() {
+        let end = start + width_at(char, start, stops);
+        if cell < end {
+            return count;
+        }
+        start = end;
+        count += 1;
+    }
+    count + cell - start
+}
+
+// The first column shown when a line `width` cells wide scrolls sideways just far enough to show `column`
+pub fn scroll_start(line: &str, column: usize, width: usize, stops: &Stops) -> usize {
+    let cell = cell_of(line, column, stops);
+    if cell < width {
+        return 0;
+    }
+    let first = cell + 1 - width;
+    let skip = column_at(line, first, stops);
+    // A wide char cut in half by the left edge isn't shown
+    if cell_of(line, skip, stops) < first { skip + 1 } else { skip }
+}
+
+// The cells text outside the buffer takes, e.g. in the status bar, where a tab is just a char
+pub fn text_width(text: &str) -> usize {
+    text.chars().map(char_width).sum()
+}
+
+// As much of the start of the text as fits in `cells`
+pub fn truncate(text: &str, cells: usize) -> String {
+    let mut used = 0;
+    text.chars().take_while(|char| {
+        used += char_width(*char);
+        used <= cells
+    }).collect()
+}
@@ -0,0 +1,38 @@
+use crate::width::{self, Stops};
+
+// Where a line breaks onto screen rows when wrapped at `width` cells, as a char range per row. Rows break after
+// the last whitespace that fits, or mid-word when a word is wider than the pane. A full last row is followed by
+// an empty one, so the cursor at the end of the line has somewhere to go
+pub fn rows(line: &str, width: usize, stops: &Stops) -> Vec<(usize, usize)> {
+    let width = width.max(1);
+    let mut rows = vec![];
+    let mut start = (0, 0); // Column and cell the row starts at
+    let mut cell = 0;
+    let mut after_space = None; // Just past the last whitespace on the row, as a column and cell
+
+    for (column, char) in line.chars().enumerate() {
+        let char_width = width::width_at(char, cell, stops);
+        if cell + char_width - start.1 > width && column > start.0 {
+            let end = after_space.unwrap_or((column, cell));
+            rows.push((start.0, end.0));
+            start = end;
+            after_space = None;
+        }
+        cell += char_width;
+        if char.is_whitespace() {
+            after_space = Some((column + 1, cell));
+        }
+    }
+
+    let end = line.chars().count();
+    rows.push((start.0, end));
+    if cell - start.1 >= width {
+        rows.push((end, end));
+    }
+    rows
+}
+
+// Which of a line's rows a column is on. The column where one row ends is the start of the next
+pub fn row_of(rows: &[(usize, usize)], column: usize) -> usize {
+    rows.iter().position(|(_, end)| column < *end).unwrap_or(rows.len() - 1)
+}